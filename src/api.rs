@@ -0,0 +1,302 @@
+//! Abstraction over the Milvue API, implemented by [MilvueClient] and by [InMemoryMilvue] for tests.
+use dicom_object::{FileDicomObject, InMemDicomObject};
+use futures_util::future::BoxFuture;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{debug, info};
+
+use crate::{
+    check_study_uids, DicomSource, MilvueClient, MilvueError, MilvueParams, Progress,
+    ProgressReporter, StatusProgress, StatusResponse,
+};
+
+/// Status of a study whose results are available.
+const DONE: &str = "done";
+
+/// Operations on the studies of a Milvue environment.
+///
+/// Services depending on this trait rather than on [MilvueClient] can be tested with an [InMemoryMilvue], or run
+/// against another backend. The trait is object safe, so that it can be shared as an `Arc<dyn MilvueApi>`.
+pub trait MilvueApi: Send + Sync {
+    /// Submits the DICOM files of a study, see [MilvueClient::upload()].
+    fn submit<'a>(
+        &'a self,
+        sources: Vec<DicomSource>,
+        progress: &'a ProgressReporter,
+    ) -> BoxFuture<'a, Result<(), MilvueError>>;
+
+    /// Returns the processing status of a study, see [MilvueClient::get_study_status()].
+    fn status<'a>(
+        &'a self,
+        study_instance_uid: &'a str,
+    ) -> BoxFuture<'a, Result<StatusResponse, MilvueError>>;
+
+    /// Waits for a study to be done, see [MilvueClient::wait_for_done()].
+    fn wait<'a>(
+        &'a self,
+        study_instance_uid: &'a str,
+        progress: &'a ProgressReporter,
+    ) -> BoxFuture<'a, Result<(), MilvueError>>;
+
+    /// Fetches the results of a study for one configuration, None when the study has no output for it, see
+    /// [MilvueClient::get()].
+    fn fetch<'a>(
+        &'a self,
+        study_instance_uid: &'a str,
+        milvue_params: &'a MilvueParams,
+        progress: &'a ProgressReporter,
+    ) -> BoxFuture<'a, Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError>>;
+}
+
+impl MilvueApi for MilvueClient {
+    fn submit<'a>(
+        &'a self,
+        sources: Vec<DicomSource>,
+        progress: &'a ProgressReporter,
+    ) -> BoxFuture<'a, Result<(), MilvueError>> {
+        Box::pin(async move {
+            self.upload(sources, progress).await?;
+            Ok(())
+        })
+    }
+
+    fn status<'a>(
+        &'a self,
+        study_instance_uid: &'a str,
+    ) -> BoxFuture<'a, Result<StatusResponse, MilvueError>> {
+        Box::pin(async move {
+            Ok(self
+                .get_study_status(study_instance_uid)
+                .await?
+                .json()
+                .await?)
+        })
+    }
+
+    fn wait<'a>(
+        &'a self,
+        study_instance_uid: &'a str,
+        progress: &'a ProgressReporter,
+    ) -> BoxFuture<'a, Result<(), MilvueError>> {
+        Box::pin(self.wait_for_done(study_instance_uid, progress))
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        study_instance_uid: &'a str,
+        milvue_params: &'a MilvueParams,
+        progress: &'a ProgressReporter,
+    ) -> BoxFuture<'a, Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError>> {
+        Box::pin(self.get(study_instance_uid, milvue_params, progress))
+    }
+}
+
+/// An in-memory Milvue environment returning canned results, without any network access.
+///
+/// A study is known once it was submitted or given results. Known studies are "done" unless another status is set
+/// with [InMemoryMilvue::with_status()], unknown studies are answered with a 404 [MilvueError::StatusResponseError]
+/// like the real API.
+///
+/// ```
+/// # use milvue_rs::{InMemoryMilvue, MilvueApi, MilvueParams};
+/// # async fn example(results: Vec<dicom_object::DefaultDicomObject>) {
+/// let api = InMemoryMilvue::new().with_results("1.2.3", results);
+/// let fetched = api
+///     .fetch("1.2.3", &MilvueParams::default(), &Default::default())
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct InMemoryMilvue {
+    studies: Mutex<HashMap<String, FakeStudy>>,
+}
+
+#[derive(Debug, Default)]
+struct FakeStudy {
+    status: Option<String>,
+    submitted: Vec<FileDicomObject<InMemDicomObject>>,
+    results: Option<Vec<FileDicomObject<InMemDicomObject>>>,
+    results_by_params: HashMap<MilvueParams, Vec<FileDicomObject<InMemDicomObject>>>,
+}
+
+impl InMemoryMilvue {
+    /// Creates an environment without any study.
+    pub fn new() -> Self {
+        InMemoryMilvue::default()
+    }
+
+    /// Returns `results` for a study, whatever the configuration requested.
+    pub fn with_results(
+        self,
+        study_instance_uid: &str,
+        results: Vec<FileDicomObject<InMemDicomObject>>,
+    ) -> Self {
+        self.study(study_instance_uid, |study| study.results = Some(results));
+        self
+    }
+
+    /// Returns `results` for a study requested with `milvue_params`, takes precedence over
+    /// [InMemoryMilvue::with_results()].
+    pub fn with_results_for(
+        self,
+        study_instance_uid: &str,
+        milvue_params: &MilvueParams,
+        results: Vec<FileDicomObject<InMemDicomObject>>,
+    ) -> Self {
+        self.study(study_instance_uid, |study| {
+            study
+                .results_by_params
+                .insert(milvue_params.clone(), results);
+        });
+        self
+    }
+
+    /// Sets the status of a study, e.g. "running" or "error".
+    ///
+    /// [MilvueApi::wait()] returns [MilvueError::ProcessingTimeout] at once for a study that isn't "done".
+    pub fn with_status(self, study_instance_uid: &str, status: &str) -> Self {
+        self.set_status(study_instance_uid, status);
+        self
+    }
+
+    /// Changes the status of a study, e.g. to simulate the end of its processing.
+    pub fn set_status(&self, study_instance_uid: &str, status: &str) {
+        self.study(study_instance_uid, |study| {
+            study.status = Some(status.to_string())
+        });
+    }
+
+    /// Returns the StudyInstanceUIDs of the studies submitted so far.
+    pub fn submitted_studies(&self) -> Vec<String> {
+        let studies = self.studies.lock().expect("studies lock poisoned");
+        studies
+            .iter()
+            .filter(|(_, study)| !study.submitted.is_empty())
+            .map(|(study_instance_uid, _)| study_instance_uid.clone())
+            .collect()
+    }
+
+    /// Returns the DICOM files submitted for a study, None if it was never submitted.
+    pub fn submitted(
+        &self,
+        study_instance_uid: &str,
+    ) -> Option<Vec<FileDicomObject<InMemDicomObject>>> {
+        let studies = self.studies.lock().expect("studies lock poisoned");
+        studies
+            .get(study_instance_uid)
+            .filter(|study| !study.submitted.is_empty())
+            .map(|study| study.submitted.clone())
+    }
+
+    /// Creates the study if needed and updates it.
+    fn study<T>(&self, study_instance_uid: &str, update: impl FnOnce(&mut FakeStudy) -> T) -> T {
+        let mut studies = self.studies.lock().expect("studies lock poisoned");
+        update(studies.entry(study_instance_uid.to_string()).or_default())
+    }
+
+    /// Reads a known study.
+    fn known_study<T>(
+        &self,
+        study_instance_uid: &str,
+        read: impl FnOnce(&FakeStudy) -> T,
+    ) -> Result<T, MilvueError> {
+        let studies = self.studies.lock().expect("studies lock poisoned");
+        match studies.get(study_instance_uid) {
+            Some(study) => Ok(read(study)),
+            None => {
+                debug!("Study {} unknown to the in-memory API", study_instance_uid);
+                Err(not_found())
+            }
+        }
+    }
+}
+
+impl MilvueApi for InMemoryMilvue {
+    fn submit<'a>(
+        &'a self,
+        sources: Vec<DicomSource>,
+        _progress: &'a ProgressReporter,
+    ) -> BoxFuture<'a, Result<(), MilvueError>> {
+        Box::pin(async move {
+            let mut objects = Vec::with_capacity(sources.len());
+            for source in sources {
+                objects.push(source.into_object().await?);
+            }
+            let study_instance_uid = check_study_uids(&objects)?;
+            info!(
+                "Study {} submitted with {} files to the in-memory API",
+                study_instance_uid,
+                objects.len()
+            );
+            self.study(&study_instance_uid, |study| study.submitted.extend(objects));
+            Ok(())
+        })
+    }
+
+    fn status<'a>(
+        &'a self,
+        study_instance_uid: &'a str,
+    ) -> BoxFuture<'a, Result<StatusResponse, MilvueError>> {
+        Box::pin(async move {
+            let status = self.known_study(study_instance_uid, |study| {
+                study.status.clone().unwrap_or_else(|| DONE.to_string())
+            })?;
+            Ok(StatusResponse {
+                study_instance_uid: study_instance_uid.to_string(),
+                status,
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                message: String::new(),
+            })
+        })
+    }
+
+    fn wait<'a>(
+        &'a self,
+        study_instance_uid: &'a str,
+        progress: &'a ProgressReporter,
+    ) -> BoxFuture<'a, Result<(), MilvueError>> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let status = self.status(study_instance_uid).await?.status;
+            progress.report(Progress::Status(StatusProgress {
+                status: status.clone(),
+                polls: 1,
+                elapsed: start.elapsed(),
+            }));
+            match status.as_str() {
+                DONE => Ok(()),
+                _ => Err(MilvueError::ProcessingTimeout(Duration::ZERO)),
+            }
+        })
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        study_instance_uid: &'a str,
+        milvue_params: &'a MilvueParams,
+        _progress: &'a ProgressReporter,
+    ) -> BoxFuture<'a, Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError>> {
+        Box::pin(async move {
+            self.known_study(study_instance_uid, |study| {
+                study
+                    .results_by_params
+                    .get(milvue_params)
+                    .or(study.results.as_ref())
+                    .cloned()
+            })
+        })
+    }
+}
+
+/// Response of the Milvue API for an unknown study.
+fn not_found() -> MilvueError {
+    let response = http::Response::builder()
+        .status(http::StatusCode::NOT_FOUND)
+        .body(String::new())
+        .expect("static response is valid");
+    MilvueError::StatusResponseError(response.into())
+}
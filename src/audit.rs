@@ -0,0 +1,399 @@
+//! Append-only, hash-chained audit log of the transfers of studies to and from Milvue.
+//!
+//! Every upload and download made by a [crate::MilvueClient] given an [AuditLog] is recorded as one JSON line: time,
+//! operator, environment URL, StudyInstanceUID (optionally hashed), inference command, number of instances, bytes
+//! transferred and outcome. Each record holds the hash of the previous one, so that [verify()] detects any record
+//! modified, inserted or removed after it was written.
+//!
+//! Requires the `audit` feature.
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    env, fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, error, info};
+
+use crate::{structs::MilvueError, InferenceCommand};
+
+/// Previous hash of the first record of a log.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Represents the direction of a transfer.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    /// DICOM files sent to Milvue.
+    Upload,
+    /// Results received from Milvue.
+    Download,
+}
+
+/// Represents the outcome of a transfer.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+/// Represents a transfer recorded in an [AuditLog], one JSON line of the file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// Position of the record in the log, starting at 1.
+    pub sequence: u64,
+    /// Time of the end of the transfer, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Identity of the person or service operating the client.
+    pub operator: String,
+    /// URL of the environment.
+    pub url: String,
+    pub operation: AuditOperation,
+    /// StudyInstanceUID, or its SHA-256 if `study_instance_uid_hashed`, unknown if the files were only given as
+    /// readers.
+    #[serde(
+        rename = "StudyInstanceUID",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub study_instance_uid: Option<String>,
+    pub study_instance_uid_hashed: bool,
+    /// Inference command of the results, for downloads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inference_command: Option<InferenceCommand>,
+    /// Number of DICOM instances sent or received.
+    pub instances: usize,
+    /// Number of bytes sent or received.
+    pub bytes: u64,
+    pub outcome: AuditOutcome,
+    /// Code of the error of a failed transfer, see [MilvueError::code()], or `cancelled`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Hash of the previous record, [GENESIS_HASH] for the first one.
+    pub previous_hash: String,
+    /// SHA-256 of the record without this field, see [AuditRecord::compute_hash()].
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hash: String,
+}
+
+impl AuditRecord {
+    /// Computes the hash of the record: the hexadecimal SHA-256 of its JSON serialization without the `hash` field.
+    pub fn compute_hash(&self) -> Result<String, MilvueError> {
+        let unhashed = AuditRecord {
+            hash: String::new(),
+            ..self.clone()
+        };
+        Ok(hex_sha256(&serde_json::to_vec(&unhashed)?))
+    }
+}
+
+/// Represents the audit settings of a configuration profile:
+///
+/// ```toml
+/// [profiles.prod.audit]
+/// path = "/var/log/milvue_rs/audit.jsonl"
+/// operator = "pacs-gateway"
+/// hash_study_uids = true
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditSettings {
+    /// File of the log, created if it doesn't exist.
+    pub path: PathBuf,
+    /// Identity of the operator [default: the user running the process, see [default_operator()]].
+    pub operator: Option<String>,
+    /// Record the SHA-256 of the StudyInstanceUIDs instead of the UIDs.
+    #[serde(default)]
+    pub hash_study_uids: bool,
+}
+
+impl AuditSettings {
+    /// Opens the log with these settings, see [AuditLog::open()].
+    pub fn open(&self) -> Result<AuditLog, MilvueError> {
+        let operator = self.operator.clone().unwrap_or_else(default_operator);
+        Ok(AuditLog::open(&self.path, &operator)?.hash_study_uids(self.hash_study_uids))
+    }
+}
+
+/// Append-only, hash-chained audit log, shared by the clients recording their transfers in it.
+///
+/// A single process should write to a given file at a time, records appended concurrently by several processes would
+/// break the chain.
+pub struct AuditLog {
+    path: PathBuf,
+    operator: String,
+    hash_study_uids: bool,
+    state: Mutex<ChainState>,
+}
+
+struct ChainState {
+    file: File,
+    sequence: u64,
+    last_hash: String,
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditLog")
+            .field("path", &self.path)
+            .field("operator", &self.operator)
+            .field("hash_study_uids", &self.hash_study_uids)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AuditLog {
+    /// Opens the log at `path`, creating it if it doesn't exist, to append records made by `operator`.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the log, or an error if the file cannot be read or created, or
+    ///   [MilvueError::AuditChainBroken] if its last record is incomplete or was modified.
+    pub fn open(path: &Path, operator: &str) -> Result<Self, MilvueError> {
+        let (sequence, last_hash) = match fs::read_to_string(path) {
+            Ok(content) => match content
+                .lines()
+                .enumerate()
+                .filter(|(_, l)| !l.is_empty())
+                .last()
+            {
+                Some((index, line)) => {
+                    let record = parse_record(index + 1, line)?;
+                    (record.sequence, record.hash)
+                }
+                None => (0, GENESIS_HASH.to_string()),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                debug!("No audit log at {}", path.display());
+                (0, GENESIS_HASH.to_string())
+            }
+            Err(e) => return Err(MilvueError::Io(path.to_path_buf(), e)),
+        };
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| MilvueError::Io(parent.to_path_buf(), e))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| MilvueError::Io(path.to_path_buf(), e))?;
+        info!(
+            "Recording the transfers in the audit log {} after record {}",
+            path.display(),
+            sequence
+        );
+
+        Ok(AuditLog {
+            path: path.to_path_buf(),
+            operator: operator.to_string(),
+            hash_study_uids: false,
+            state: Mutex::new(ChainState {
+                file,
+                sequence,
+                last_hash,
+            }),
+        })
+    }
+
+    /// Records the SHA-256 of the StudyInstanceUIDs instead of the UIDs.
+    pub fn hash_study_uids(mut self, hash_study_uids: bool) -> Self {
+        self.hash_study_uids = hash_study_uids;
+        self
+    }
+
+    /// Path of the log.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends a record and flushes it to disk.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the record written, or an error if the file cannot be written.
+    pub(crate) fn append(&self, transfer: AuditedTransfer) -> Result<AuditRecord, MilvueError> {
+        let mut state = self.state.lock().expect("audit log lock poisoned");
+        let mut record = AuditRecord {
+            sequence: state.sequence + 1,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+            operator: self.operator.clone(),
+            url: transfer.url,
+            operation: transfer.operation,
+            study_instance_uid: match self.hash_study_uids {
+                true => transfer
+                    .study_instance_uid
+                    .map(|uid| hex_sha256(uid.as_bytes())),
+                false => transfer.study_instance_uid,
+            },
+            study_instance_uid_hashed: self.hash_study_uids,
+            inference_command: transfer.inference_command,
+            instances: transfer.instances,
+            bytes: transfer.bytes,
+            outcome: match transfer.error {
+                Some(_) => AuditOutcome::Failure,
+                None => AuditOutcome::Success,
+            },
+            error: transfer.error.map(str::to_string),
+            previous_hash: state.last_hash.clone(),
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash()?;
+
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        state
+            .file
+            .write_all(&line)
+            .and_then(|_| state.file.sync_data())
+            .map_err(|e| MilvueError::Io(self.path.clone(), e))?;
+        state.sequence = record.sequence;
+        state.last_hash = record.hash.clone();
+        Ok(record)
+    }
+}
+
+/// Represents a transfer being made, recorded in the log when finished, or as cancelled if dropped before.
+pub(crate) struct AuditedTransfer {
+    pub(crate) url: String,
+    pub(crate) operation: AuditOperation,
+    pub(crate) study_instance_uid: Option<String>,
+    pub(crate) inference_command: Option<InferenceCommand>,
+    pub(crate) instances: usize,
+    pub(crate) bytes: u64,
+    pub(crate) error: Option<&'static str>,
+}
+
+/// Records an [AuditedTransfer] in a log when dropped, as cancelled unless [AuditGuard::finish()] was called.
+pub(crate) struct AuditGuard<'a> {
+    log: &'a AuditLog,
+    transfer: Option<AuditedTransfer>,
+}
+
+impl<'a> AuditGuard<'a> {
+    pub(crate) fn new(log: &'a AuditLog, transfer: AuditedTransfer) -> Self {
+        AuditGuard {
+            log,
+            transfer: Some(transfer),
+        }
+    }
+
+    /// Sets the number of bytes transferred so far.
+    pub(crate) fn set_bytes(&mut self, bytes: u64) {
+        if let Some(transfer) = &mut self.transfer {
+            transfer.bytes = bytes;
+        }
+    }
+
+    /// Sets the number of instances transferred.
+    pub(crate) fn set_instances(&mut self, instances: usize) {
+        if let Some(transfer) = &mut self.transfer {
+            transfer.instances = instances;
+        }
+    }
+
+    /// Records the transfer with the outcome of `result`.
+    pub(crate) fn finish<T>(mut self, result: &Result<T, MilvueError>) {
+        if let Some(mut transfer) = self.transfer.take() {
+            transfer.error = result.as_ref().err().map(MilvueError::code);
+            self.record(transfer);
+        }
+    }
+
+    fn record(&self, transfer: AuditedTransfer) {
+        if let Err(e) = self.log.append(transfer) {
+            error!("Error while writing the audit log: {}", e);
+        }
+    }
+}
+
+impl Drop for AuditGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(mut transfer) = self.transfer.take() {
+            transfer.error = Some("cancelled");
+            self.record(transfer);
+        }
+    }
+}
+
+/// Represents a verified audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditSummary {
+    /// Number of records.
+    pub records: u64,
+    /// Hash of the last record, [GENESIS_HASH] if the log is empty. Keeping it elsewhere allows detecting the removal
+    /// of the last records, which the chain alone cannot reveal.
+    pub last_hash: String,
+}
+
+/// Verifies the hash chain of an audit log.
+///
+/// # Arguments
+///
+/// * `path` - The path of the log.
+///
+/// # Returns
+///
+/// * A Result wrapping the number of records and the last hash, or [MilvueError::AuditChainBroken] with the first
+///   line that doesn't verify, or an error if the file cannot be read.
+pub fn verify(path: &Path) -> Result<AuditSummary, MilvueError> {
+    let content = fs::read_to_string(path).map_err(|e| MilvueError::Io(path.to_path_buf(), e))?;
+    let mut summary = AuditSummary {
+        records: 0,
+        last_hash: GENESIS_HASH.to_string(),
+    };
+    for (index, line) in content.lines().enumerate() {
+        let line_number = index + 1;
+        if line.is_empty() {
+            continue;
+        }
+        let record = parse_record(line_number, line)?;
+        if record.sequence != summary.records + 1 {
+            return Err(MilvueError::AuditChainBroken(
+                line_number,
+                format!("sequence {} follows {}", record.sequence, summary.records),
+            ));
+        }
+        if record.previous_hash != summary.last_hash {
+            return Err(MilvueError::AuditChainBroken(
+                line_number,
+                "previous hash doesn't match the previous record".to_string(),
+            ));
+        }
+        summary.records = record.sequence;
+        summary.last_hash = record.hash;
+    }
+    Ok(summary)
+}
+
+/// Parses a record and checks its hash.
+fn parse_record(line_number: usize, line: &str) -> Result<AuditRecord, MilvueError> {
+    let record: AuditRecord = serde_json::from_str(line)
+        .map_err(|e| MilvueError::AuditChainBroken(line_number, e.to_string()))?;
+    if record.compute_hash()? != record.hash {
+        return Err(MilvueError::AuditChainBroken(
+            line_number,
+            "hash doesn't match the content of the record".to_string(),
+        ));
+    }
+    Ok(record)
+}
+
+/// Identity of the user running the process, from the `USER` or `USERNAME` environment variable.
+pub fn default_operator() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Computes the SHA-256 of `data`, as a lowercase hexadecimal string.
+fn hex_sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
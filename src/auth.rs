@@ -0,0 +1,312 @@
+//! Authentication of the requests sent to the Milvue API.
+//!
+//! An [Authenticator] provides the headers added to every request. The API key can be given directly
+//! ([StaticKey]), read from a file ([KeyFile]) or an environment variable ([EnvKey]) on every request so that it can
+//! be rotated without restarting, or replaced by an OAuth2 bearer token ([OAuth2ClientCredentials]).
+use futures_util::future::BoxFuture;
+use reqwest::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fmt,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{debug, error, info};
+
+use crate::structs::MilvueError;
+
+/// Name of the header carrying the API key in the Milvue API.
+pub const MILVUE_KEY_HEADER: &str = "x-goog-meta-owner";
+
+/// Tokens are refreshed this long before they expire.
+const REFRESH_MARGIN: Duration = Duration::from_secs(30);
+
+/// Provides the credentials of the requests sent to the Milvue API.
+pub trait Authenticator: Send + Sync {
+    /// Returns the headers authenticating a request.
+    fn headers(&self) -> BoxFuture<'_, Result<HeaderMap, MilvueError>>;
+
+    /// Discards cached credentials after the API rejected them, the next call to [Authenticator::headers()] fetches
+    /// new ones. Does nothing by default.
+    fn invalidate(&self) {}
+}
+
+/// Represents how a credential is sent to the API.
+///
+/// In TOML, `"milvue_key"`, `"bearer"` or `{ header = "x-api-key" }`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthScheme {
+    /// The credential is sent in the `x-goog-meta-owner` header, as expected by the Milvue API.
+    #[default]
+    MilvueKey,
+    /// The credential is sent as `Authorization: Bearer <credential>`.
+    Bearer,
+    /// The credential is sent as is in a custom header.
+    Header(String),
+}
+
+impl AuthScheme {
+    /// Builds the headers carrying `credential`, marked as sensitive so that they are never logged.
+    pub fn headers(&self, credential: &str) -> Result<HeaderMap, MilvueError> {
+        let (name, value) = match self {
+            AuthScheme::MilvueKey => (
+                HeaderName::from_static(MILVUE_KEY_HEADER),
+                HeaderValue::from_str(credential)?,
+            ),
+            AuthScheme::Bearer => (
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", credential))?,
+            ),
+            AuthScheme::Header(name) => (
+                HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| MilvueError::InvalidValue("header name", name.clone()))?,
+                HeaderValue::from_str(credential)?,
+            ),
+        };
+        let mut value = value;
+        value.set_sensitive(true);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(name, value);
+        Ok(headers)
+    }
+}
+
+/// An API key known in advance.
+#[derive(Clone)]
+pub struct StaticKey {
+    key: String,
+    scheme: AuthScheme,
+}
+
+impl StaticKey {
+    /// Creates an authenticator sending `key` in the Milvue header.
+    pub fn new(key: &str) -> Self {
+        StaticKey {
+            key: key.to_string(),
+            scheme: AuthScheme::default(),
+        }
+    }
+
+    /// Sends the key with another scheme.
+    pub fn with_scheme(mut self, scheme: AuthScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+}
+
+impl fmt::Debug for StaticKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StaticKey")
+            .field("key", &"<redacted>")
+            .field("scheme", &self.scheme)
+            .finish()
+    }
+}
+
+impl Authenticator for StaticKey {
+    fn headers(&self) -> BoxFuture<'_, Result<HeaderMap, MilvueError>> {
+        Box::pin(async move { self.scheme.headers(&self.key) })
+    }
+}
+
+/// An API key read from a file before every request, surrounding whitespace is ignored.
+///
+/// The file can be replaced at any time to rotate the key.
+#[derive(Debug, Clone)]
+pub struct KeyFile {
+    path: PathBuf,
+    scheme: AuthScheme,
+}
+
+impl KeyFile {
+    /// Creates an authenticator sending the content of `path` in the Milvue header.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        KeyFile {
+            path: path.into(),
+            scheme: AuthScheme::default(),
+        }
+    }
+
+    /// Sends the key with another scheme.
+    pub fn with_scheme(mut self, scheme: AuthScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+}
+
+impl Authenticator for KeyFile {
+    fn headers(&self) -> BoxFuture<'_, Result<HeaderMap, MilvueError>> {
+        Box::pin(async move {
+            let key = tokio::fs::read_to_string(&self.path)
+                .await
+                .map_err(|e| MilvueError::Io(self.path.clone(), e))?;
+            self.scheme.headers(key.trim())
+        })
+    }
+}
+
+/// An API key read from an environment variable before every request.
+#[derive(Debug, Clone)]
+pub struct EnvKey {
+    var: String,
+    scheme: AuthScheme,
+}
+
+impl EnvKey {
+    /// Creates an authenticator sending the value of the variable `var` in the Milvue header.
+    pub fn new(var: &str) -> Self {
+        EnvKey {
+            var: var.to_string(),
+            scheme: AuthScheme::default(),
+        }
+    }
+
+    /// Sends the key with another scheme.
+    pub fn with_scheme(mut self, scheme: AuthScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+}
+
+impl Authenticator for EnvKey {
+    fn headers(&self) -> BoxFuture<'_, Result<HeaderMap, MilvueError>> {
+        Box::pin(async move {
+            let key =
+                env::var(&self.var).map_err(|_| MilvueError::EnvVarNotFound(self.var.clone()))?;
+            self.scheme.headers(&key)
+        })
+    }
+}
+
+/// A bearer token obtained with the OAuth2 client credentials grant.
+///
+/// The token is cached and requested again shortly before it expires, or after the API rejected it.
+pub struct OAuth2ClientCredentials {
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+    scopes: Vec<String>,
+    http: Client,
+    token: Mutex<Option<CachedToken>>,
+    refresh: tokio::sync::Mutex<()>,
+}
+
+struct CachedToken {
+    access_token: String,
+    expires_at: Option<Instant>,
+}
+
+/// Successful response of the token endpoint.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+impl OAuth2ClientCredentials {
+    /// Creates an authenticator requesting tokens from `token_url`.
+    pub fn new(token_url: &str, client_id: &str, client_secret: &str) -> Self {
+        OAuth2ClientCredentials {
+            token_url: token_url.to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            scopes: Vec::new(),
+            http: Client::new(),
+            token: Mutex::new(None),
+            refresh: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Requests tokens for these scopes.
+    pub fn with_scopes(mut self, scopes: Vec<String>) -> Self {
+        self.scopes = scopes;
+        self
+    }
+
+    /// Requests tokens with this HTTP client, e.g. to use the same proxy as the API.
+    pub fn with_http_client(mut self, http: Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Returns the cached token if it is still valid.
+    fn cached_token(&self) -> Option<String> {
+        let token = self.token.lock().expect("token lock poisoned");
+        token
+            .as_ref()
+            .filter(|token| {
+                token.expires_at.map_or(true, |expires_at| {
+                    Instant::now() + REFRESH_MARGIN < expires_at
+                })
+            })
+            .map(|token| token.access_token.clone())
+    }
+
+    /// Returns a valid token, requesting a new one if needed.
+    async fn token(&self) -> Result<String, MilvueError> {
+        if let Some(token) = self.cached_token() {
+            return Ok(token);
+        }
+        // Only one task requests a new token, the others wait and reuse it
+        let _refresh = self.refresh.lock().await;
+        if let Some(token) = self.cached_token() {
+            return Ok(token);
+        }
+
+        info!("Requesting an OAuth2 token from {}", self.token_url);
+        let mut form = vec![
+            ("grant_type", "client_credentials".to_string()),
+            ("client_id", self.client_id.clone()),
+            ("client_secret", self.client_secret.clone()),
+        ];
+        if !self.scopes.is_empty() {
+            form.push(("scope", self.scopes.join(" ")));
+        }
+        let response = self.http.post(&self.token_url).form(&form).send().await?;
+        if !response.status().is_success() {
+            error!(
+                "OAuth2 token request failed with status code {}",
+                response.status()
+            );
+            return Err(MilvueError::StatusResponseError(response));
+        }
+        let token: TokenResponse = response.json().await?;
+        debug!("OAuth2 token valid for {:?} seconds", token.expires_in);
+
+        *self.token.lock().expect("token lock poisoned") = Some(CachedToken {
+            access_token: token.access_token.clone(),
+            // a lifetime beyond the range of Instant never expires
+            expires_at: token
+                .expires_in
+                .and_then(|expires_in| Instant::now().checked_add(Duration::from_secs(expires_in))),
+        });
+        Ok(token.access_token)
+    }
+}
+
+impl fmt::Debug for OAuth2ClientCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuth2ClientCredentials")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"<redacted>")
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+impl Authenticator for OAuth2ClientCredentials {
+    fn headers(&self) -> BoxFuture<'_, Result<HeaderMap, MilvueError>> {
+        Box::pin(async move { AuthScheme::Bearer.headers(&self.token().await?) })
+    }
+
+    fn invalidate(&self) {
+        self.token.lock().expect("token lock poisoned").take();
+    }
+}
@@ -0,0 +1,62 @@
+use std::path::{Path, PathBuf};
+
+use clap::Subcommand;
+use milvue_rs::{audit, MilvueError};
+use tracing::error;
+
+use crate::output::{Event, OutputMode, EXIT_AUDIT_BROKEN, EXIT_FATAL, EXIT_SUCCESS};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AuditCommand {
+    /// Verify the hash chain of an audit log
+    Verify {
+        /// Audit log file
+        path: PathBuf,
+    },
+}
+
+/// Runs an audit command, printing its result in the format of `--output`, and returns the exit code of the process.
+pub fn run(command: &AuditCommand, mode: OutputMode) -> i32 {
+    match command {
+        AuditCommand::Verify { path } => verify(path, mode),
+    }
+}
+
+fn verify(path: &Path, mode: OutputMode) -> i32 {
+    let (event, exit_code) = match audit::verify(path) {
+        Ok(summary) => (
+            Event::AuditVerified {
+                path: path.to_path_buf(),
+                records: summary.records,
+                last_hash: summary.last_hash,
+            },
+            EXIT_SUCCESS,
+        ),
+        Err(e) => {
+            error!("Error while verifying the audit log: {}", e);
+            let exit_code = match e {
+                MilvueError::AuditChainBroken(_, _) => EXIT_AUDIT_BROKEN,
+                _ => EXIT_FATAL,
+            };
+            (Event::error(None, None, &e), exit_code)
+        }
+    };
+
+    match (mode, &event) {
+        (OutputMode::Json, _) => println!(
+            "{}",
+            serde_json::to_string(&event).expect("events are serializable")
+        ),
+        (
+            OutputMode::Text,
+            Event::AuditVerified {
+                records, last_hash, ..
+            },
+        ) => println!(
+            "Audit log verified: {} records, last hash {}",
+            records, last_hash
+        ),
+        (OutputMode::Text, _) => {}
+    }
+    exit_code
+}
@@ -0,0 +1,62 @@
+use milvue_rs::{CheckStatus, HealthReport, MilvueClient, MilvueError};
+
+use crate::output::{Event, OutputMode, EXIT_SUCCESS, EXIT_UNHEALTHY};
+
+/// Runs the health check of every environment and returns the exit code of the process.
+///
+/// # Arguments
+///
+/// * `clients` - The client of each environment, or the name and the error of an environment without a client,
+///   reported as unhealthy.
+/// * `mode` - The format of the reports printed on stdout.
+pub async fn run(
+    clients: Vec<Result<MilvueClient, (String, MilvueError)>>,
+    mode: OutputMode,
+) -> i32 {
+    let mut exit_code = EXIT_SUCCESS;
+    for client in clients {
+        let report = match client {
+            Ok(client) => client.health_check().await,
+            Err((environment, e)) => HealthReport::from_error(&environment, &e),
+        };
+        let healthy = report.is_healthy();
+        if !healthy {
+            exit_code = EXIT_UNHEALTHY;
+        }
+
+        match mode {
+            OutputMode::Json => println!(
+                "{}",
+                serde_json::to_string(&Event::HealthChecked { healthy, report })
+                    .expect("events are serializable")
+            ),
+            OutputMode::Text => {
+                println!("Environment {}", report.url);
+                for check in &report.checks {
+                    let status = match check.status {
+                        CheckStatus::Ok => "[ok]  ",
+                        CheckStatus::Warning => "[warn]",
+                        CheckStatus::Failed => "[fail]",
+                        CheckStatus::Skipped => "[skip]",
+                    };
+                    let elapsed = check
+                        .elapsed
+                        .map(|elapsed| format!(" ({} ms)", elapsed.as_millis()))
+                        .unwrap_or_default();
+                    println!("  {} {}: {}{}", status, check.name, check.message, elapsed);
+                    if let Some(hint) = &check.hint {
+                        println!("         hint: {}", hint);
+                    }
+                }
+                match (healthy, report.latency) {
+                    (true, Some(latency)) => {
+                        println!("  Healthy, latency {} ms", latency.as_millis())
+                    }
+                    (true, None) => println!("  Healthy"),
+                    (false, _) => println!("  Unhealthy"),
+                }
+            }
+        }
+    }
+    exit_code
+}
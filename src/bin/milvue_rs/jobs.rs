@@ -0,0 +1,708 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use milvue_rs::{InferenceCommand, MilvueError};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::output::Phase;
+
+/// Name of the file of a job, in the directory of the job.
+const JOB_FILE_NAME: &str = "job.json";
+
+/// State of a job of the serve mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Uploading,
+    Processing,
+    Downloading,
+    Done,
+    /// Failed on every attempt, only processed again when retried explicitly.
+    DeadLetter,
+}
+
+/// Error of the last failed attempt of a job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobError {
+    pub phase: Phase,
+    /// Stable identifier of the error, see [MilvueError::code].
+    pub code: String,
+    pub message: String,
+}
+
+impl JobError {
+    pub fn new(phase: Phase, error: &MilvueError) -> Self {
+        JobError {
+            phase,
+            code: error.code().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// A study submitted to the serve mode, processed by one of the workers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub study_instance_uid: String,
+    pub inference_commands: Vec<InferenceCommand>,
+    pub state: JobState,
+    /// The highest priorities are processed first.
+    pub priority: i32,
+    /// Origin of the submission, the jobs of a same priority are shared fairly between the sources.
+    pub source: String,
+    /// Number of DICOM files of the study.
+    pub files: usize,
+    /// Creation and last update of the job, in seconds since the Unix epoch.
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// Number of failed attempts.
+    pub attempts: u32,
+    /// The job isn't processed again before this time, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_at: Option<u64>,
+    /// Whether the study was uploaded, it is only waited for and downloaded when the server restarts.
+    #[serde(default)]
+    pub uploaded: bool,
+    /// URL of the environment the study was uploaded to, which is polled and downloaded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JobError>,
+    /// File names of the results, in the results directory of the job.
+    #[serde(default)]
+    pub results: Vec<String>,
+    /// SOPInstanceUIDs and paths of the DICOM files of the study.
+    pub instances: Vec<(String, PathBuf)>,
+}
+
+impl Job {
+    pub fn new(
+        study_instance_uid: String,
+        instances: Vec<(String, PathBuf)>,
+        inference_commands: Vec<InferenceCommand>,
+        priority: i32,
+        source: String,
+    ) -> Self {
+        let now = now();
+        Job {
+            id: uuid::Uuid::new_v4().to_string(),
+            study_instance_uid,
+            inference_commands,
+            state: JobState::Queued,
+            priority,
+            source,
+            files: instances.len(),
+            created_at: now,
+            updated_at: now,
+            attempts: 0,
+            retry_at: None,
+            uploaded: false,
+            endpoint: None,
+            error: None,
+            results: Vec::new(),
+            instances,
+        }
+    }
+}
+
+/// Scheduling of the jobs.
+#[derive(Debug, Clone)]
+pub struct QueueSettings {
+    /// Number of attempts before a job is moved to the dead letters.
+    pub max_attempts: u32,
+    /// Delay before the second attempt of a job, multiplied by the number of failed attempts for the next ones.
+    pub retry_delay: Duration,
+    /// Waiting time raising the priority of a queued job by 1, so that the lowest priorities aren't starved.
+    pub aging: Option<Duration>,
+    /// Time the done and dead-lettered jobs are kept with their results after their last update, forever if unset.
+    pub retention: Option<Duration>,
+}
+
+/// Durable queue of the jobs of the serve mode.
+///
+/// Every job is saved in its own directory, and the jobs interrupted by a restart are queued again when the queue
+/// is opened. The queued job with the highest priority is processed first, the jobs of a same priority are taken
+/// in turn from each source, oldest first.
+pub struct Jobs {
+    directory: PathBuf,
+    settings: QueueSettings,
+    state: Mutex<QueueState>,
+    changed: Notify,
+}
+
+/// Rank of a queued job: priority raised by the aging, then least recently served source, then oldest job.
+type Rank = (i64, Reverse<u64>, Reverse<u64>);
+
+#[derive(Default)]
+struct QueueState {
+    jobs: HashMap<String, Job>,
+    /// Number of the last job taken from each source.
+    served: HashMap<String, u64>,
+    taken: u64,
+}
+
+impl Jobs {
+    /// Opens the queue saved in a directory, or creates it.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory of the jobs, one subdirectory per job.
+    /// * `settings` - The scheduling of the jobs.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the queue, or an error if the directory can't be read.
+    pub fn open(directory: &Path, settings: QueueSettings) -> Result<Jobs, MilvueError> {
+        fs::create_dir_all(directory).map_err(|e| MilvueError::Io(directory.to_path_buf(), e))?;
+        let mut state = QueueState::default();
+        let entries =
+            fs::read_dir(directory).map_err(|e| MilvueError::Io(directory.to_path_buf(), e))?;
+        for entry in entries.flatten() {
+            let path = entry.path().join(JOB_FILE_NAME);
+            let job = match fs::read_to_string(&path) {
+                Ok(content) => match serde_json::from_str::<Job>(&content) {
+                    Ok(job) => job,
+                    Err(e) => {
+                        warn!("Ignoring the invalid job {}: {}", path.display(), e);
+                        continue;
+                    }
+                },
+                Err(_) => continue,
+            };
+            state.jobs.insert(job.id.clone(), job);
+        }
+
+        let jobs = Jobs {
+            directory: directory.to_path_buf(),
+            settings,
+            state: Mutex::new(QueueState::default()),
+            changed: Notify::new(),
+        };
+        let mut resumed = 0;
+        for job in state.jobs.values_mut() {
+            if matches!(
+                job.state,
+                JobState::Uploading | JobState::Processing | JobState::Downloading
+            ) {
+                job.state = JobState::Queued;
+                jobs.save(job);
+                resumed += 1;
+            }
+        }
+        info!(
+            "{} jobs loaded from {}, {} interrupted jobs queued again",
+            state.jobs.len(),
+            directory.display(),
+            resumed
+        );
+        *jobs.state.lock().expect("queue lock poisoned") = state;
+        Ok(jobs)
+    }
+
+    /// Returns the directory of a job.
+    pub fn directory(&self, id: &str) -> PathBuf {
+        self.directory.join(id)
+    }
+
+    /// Records a job and queues it for the workers.
+    pub fn submit(&self, job: Job) {
+        let mut state = self.lock();
+        self.save(&job);
+        state.jobs.insert(job.id.clone(), job);
+        drop(state);
+        self.changed.notify_waiters();
+    }
+
+    /// Waits for the next job to process and marks it as started.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_priority` - Lowest priority of the jobs taken, the workers reserved for urgent studies don't take
+    ///   the routine ones.
+    pub async fn next(&self, min_priority: i32) -> Job {
+        loop {
+            // registered before looking at the queue, a job submitted in between wakes the worker
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let retry_at = match self.take(min_priority) {
+                Ok(job) => return job,
+                Err(retry_at) => retry_at,
+            };
+            match retry_at {
+                Some(retry_at) => {
+                    let delay = Duration::from_secs(retry_at.saturating_sub(now()).max(1));
+                    tokio::select! {
+                        _ = changed => {}
+                        _ = tokio::time::sleep(delay) => {}
+                    }
+                }
+                None => changed.await,
+            }
+        }
+    }
+
+    /// Takes the queued job to process first.
+    ///
+    /// # Returns
+    ///
+    /// * The job, or the time of the next retry of a queued job, if any.
+    fn take(&self, min_priority: i32) -> Result<Job, Option<u64>> {
+        let now = now();
+        let mut state = self.lock();
+        let mut next_retry: Option<u64> = None;
+        let mut best: Option<(Rank, String)> = None;
+        for job in state.jobs.values() {
+            if job.state != JobState::Queued || job.priority < min_priority {
+                continue;
+            }
+            if let Some(retry_at) = job.retry_at.filter(|retry_at| *retry_at > now) {
+                next_retry = Some(next_retry.map_or(retry_at, |next| next.min(retry_at)));
+                continue;
+            }
+            let aged = match &self.settings.aging {
+                Some(aging) if aging.as_secs() > 0 => {
+                    (now.saturating_sub(job.created_at) / aging.as_secs()) as i64
+                }
+                _ => 0,
+            };
+            let served = state.served.get(&job.source).copied().unwrap_or(0);
+            let key: Rank = (
+                job.priority as i64 + aged,
+                Reverse(served),
+                Reverse(job.created_at),
+            );
+            if best.as_ref().map_or(true, |(best, _)| key > *best) {
+                best = Some((key, job.id.clone()));
+            }
+        }
+
+        let id = match best {
+            Some((_, id)) => id,
+            None => return Err(next_retry),
+        };
+        state.taken += 1;
+        let taken = state.taken;
+        let job = state.jobs.get_mut(&id).expect("The job was just found");
+        job.state = match job.uploaded {
+            true => JobState::Processing,
+            false => JobState::Uploading,
+        };
+        job.retry_at = None;
+        job.updated_at = now;
+        let job = job.clone();
+        state.served.insert(job.source.clone(), taken);
+        self.save(&job);
+        Ok(job)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.lock().jobs.get(id).cloned()
+    }
+
+    /// Returns the jobs, in a state if given, oldest first.
+    pub fn list(&self, state: Option<JobState>) -> Vec<Job> {
+        let mut jobs: Vec<Job> = self
+            .lock()
+            .jobs
+            .values()
+            .filter(|job| state.map_or(true, |state| job.state == state))
+            .cloned()
+            .collect();
+        jobs.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+        jobs
+    }
+
+    /// Modifies a job, updates its modification time and saves it.
+    ///
+    /// # Returns
+    ///
+    /// * The modified job, None if it doesn't exist.
+    pub fn update(&self, id: &str, change: impl FnOnce(&mut Job)) -> Option<Job> {
+        let mut state = self.lock();
+        let job = state.jobs.get_mut(id)?;
+        change(job);
+        job.updated_at = now();
+        // saved under the lock, the files are written in the order of the changes
+        self.save(job);
+        Some(job.clone())
+    }
+
+    pub fn set_state(&self, id: &str, state: JobState) {
+        self.update(id, |job| job.state = state);
+    }
+
+    /// Records a failed attempt of a job, and queues it again after a delay or moves it to the dead letters.
+    ///
+    /// # Returns
+    ///
+    /// * True if the job was moved to the dead letters.
+    pub fn fail(&self, id: &str, error: JobError) -> bool {
+        let settings = &self.settings;
+        let job = self.update(id, |job| {
+            job.attempts += 1;
+            // a study uploaded before a failure while waiting for it or downloading it isn't uploaded again
+            if matches!(error.phase, Phase::Upload) {
+                job.uploaded = false;
+            }
+            job.error = Some(error);
+            if job.attempts >= settings.max_attempts {
+                job.state = JobState::DeadLetter;
+            } else {
+                job.state = JobState::Queued;
+                job.retry_at = Some(now() + settings.retry_delay.as_secs() * job.attempts as u64);
+            }
+        });
+        self.changed.notify_waiters();
+        job.is_some_and(|job| job.state == JobState::DeadLetter)
+    }
+
+    /// Queues a job again without counting an attempt, e.g. while the circuit breaker rejects the requests. A study
+    /// already uploaded is only waited for.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The job.
+    /// * `error` - The reason, kept in the job until it is processed again.
+    /// * `delay` - Time before the job can be taken again.
+    pub fn postpone(&self, id: &str, error: JobError, delay: Duration) {
+        self.update(id, |job| {
+            job.error = Some(error);
+            job.state = JobState::Queued;
+            job.retry_at = Some(now() + delay.as_secs().max(1));
+        });
+        self.changed.notify_waiters();
+    }
+
+    /// Queues again a job of the dead letters.
+    ///
+    /// # Returns
+    ///
+    /// * The job, or None if it doesn't exist or isn't in the dead letters.
+    pub fn retry(&self, id: &str) -> Option<Job> {
+        let mut retried = false;
+        let job = self.update(id, |job| {
+            if job.state == JobState::DeadLetter {
+                job.state = JobState::Queued;
+                job.attempts = 0;
+                job.error = None;
+                job.results.clear();
+                retried = true;
+            }
+        })?;
+        self.changed.notify_waiters();
+        retried.then_some(job)
+    }
+
+    /// Removes the done and dead-lettered jobs older than the retention, with their directory.
+    ///
+    /// # Returns
+    ///
+    /// * The number of removed jobs.
+    pub fn prune(&self) -> usize {
+        let Some(retention) = self.settings.retention else {
+            return 0;
+        };
+        let expired_before = now().saturating_sub(retention.as_secs());
+        let mut state = self.lock();
+        let expired: Vec<String> = state
+            .jobs
+            .values()
+            .filter(|job| matches!(job.state, JobState::Done | JobState::DeadLetter))
+            .filter(|job| job.updated_at <= expired_before)
+            .map(|job| job.id.clone())
+            .collect();
+        for id in &expired {
+            state.jobs.remove(id);
+        }
+        drop(state);
+
+        for id in &expired {
+            let directory = self.directory(id);
+            if let Err(e) = fs::remove_dir_all(&directory) {
+                warn!(
+                    "Error while removing the expired job {}: {}",
+                    directory.display(),
+                    e
+                );
+            }
+        }
+        if !expired.is_empty() {
+            info!("{} expired jobs removed", expired.len());
+        }
+        expired.len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().expect("queue lock poisoned")
+    }
+
+    /// Writes a job in its directory, replacing the previous version atomically, called with the lock held.
+    fn save(&self, job: &Job) {
+        let directory = self.directory(&job.id);
+        let path = directory.join(JOB_FILE_NAME);
+        let tmp_path = directory.join(format!("{}.tmp", JOB_FILE_NAME));
+        let saved = fs::create_dir_all(&directory)
+            .and_then(|_| {
+                let content = serde_json::to_vec_pretty(job).expect("jobs are serializable");
+                fs::write(&tmp_path, content)
+            })
+            .and_then(|_| fs::rename(&tmp_path, &path));
+        if let Err(e) = saved {
+            warn!("Error while saving the job {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Directory of the jobs of a test, removed at the end of the test.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "milvue_rs-jobs-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&path);
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn settings() -> QueueSettings {
+        QueueSettings {
+            max_attempts: 3,
+            retry_delay: Duration::from_secs(60),
+            aging: None,
+            retention: None,
+        }
+    }
+
+    /// Submits a job created `age` seconds ago.
+    fn submit(jobs: &Jobs, priority: i32, source: &str, age: u64) -> String {
+        let mut job = Job::new(
+            "1.2.3".to_string(),
+            vec![("1.2.3.1".to_string(), PathBuf::from("1.dcm"))],
+            vec![InferenceCommand::SmartUrgences],
+            priority,
+            source.to_string(),
+        );
+        job.created_at -= age;
+        let id = job.id.clone();
+        jobs.submit(job);
+        id
+    }
+
+    fn error(phase: Phase) -> JobError {
+        JobError::new(phase, &MilvueError::NoApiKey)
+    }
+
+    #[test]
+    fn the_highest_priority_is_taken_first() {
+        let directory = TempDir::new("priority");
+        let jobs = Jobs::open(&directory.0, settings()).unwrap();
+        let routine = submit(&jobs, 0, "a", 10);
+        let urgent = submit(&jobs, 5, "a", 0);
+
+        assert_eq!(jobs.take(i32::MIN).unwrap().id, urgent);
+        // the reserved workers leave the routine studies
+        assert_eq!(jobs.take(1).unwrap_err(), None);
+        let job = jobs.take(i32::MIN).unwrap();
+        assert_eq!(job.id, routine);
+        assert_eq!(job.state, JobState::Uploading);
+        assert_eq!(jobs.take(i32::MIN).unwrap_err(), None);
+    }
+
+    #[test]
+    fn sources_are_served_in_turn() {
+        let directory = TempDir::new("fairness");
+        let jobs = Jobs::open(&directory.0, settings()).unwrap();
+        let first = submit(&jobs, 0, "a", 30);
+        let second = submit(&jobs, 0, "a", 20);
+        let other = submit(&jobs, 0, "b", 10);
+
+        let taken: Vec<String> = (0..3).map(|_| jobs.take(i32::MIN).unwrap().id).collect();
+        assert_eq!(taken, [first, other, second]);
+    }
+
+    #[test]
+    fn waiting_jobs_age_into_higher_priorities() {
+        let directory = TempDir::new("aging");
+        let jobs = Jobs::open(
+            &directory.0,
+            QueueSettings {
+                aging: Some(Duration::from_secs(10)),
+                ..settings()
+            },
+        )
+        .unwrap();
+        // 100 seconds of waiting raise the priority by 10
+        let old = submit(&jobs, 0, "a", 100);
+        let urgent = submit(&jobs, 5, "b", 0);
+
+        assert_eq!(jobs.take(i32::MIN).unwrap().id, old);
+        assert_eq!(jobs.take(i32::MIN).unwrap().id, urgent);
+    }
+
+    #[test]
+    fn failed_jobs_are_retried_later_then_dead_lettered() {
+        let directory = TempDir::new("retry");
+        let jobs = Jobs::open(&directory.0, settings()).unwrap();
+        let id = submit(&jobs, 0, "a", 0);
+
+        for attempt in 1..3 {
+            jobs.take(i32::MIN).unwrap();
+            let before = now();
+            assert!(!jobs.fail(&id, error(Phase::Upload)));
+            let job = jobs.get(&id).unwrap();
+            assert_eq!(job.state, JobState::Queued);
+            assert_eq!(job.attempts, attempt);
+            let retry_at = job.retry_at.unwrap();
+            let delay = 60 * attempt as u64;
+            assert!((before + delay..=now() + delay).contains(&retry_at));
+            assert_eq!(jobs.take(i32::MIN).unwrap_err(), Some(retry_at));
+            // the delay is over
+            jobs.update(&id, |job| job.retry_at = Some(now()));
+        }
+
+        jobs.take(i32::MIN).unwrap();
+        assert!(jobs.fail(&id, error(Phase::Download)));
+        let job = jobs.get(&id).unwrap();
+        assert_eq!(job.state, JobState::DeadLetter);
+        assert_eq!(job.attempts, 3);
+        assert_eq!(jobs.take(i32::MIN).unwrap_err(), None);
+        assert_eq!(jobs.list(Some(JobState::DeadLetter)).len(), 1);
+    }
+
+    #[test]
+    fn uploaded_studies_are_only_uploaded_again_after_an_upload_failure() {
+        let directory = TempDir::new("uploaded");
+        let jobs = Jobs::open(
+            &directory.0,
+            QueueSettings {
+                retry_delay: Duration::ZERO,
+                ..settings()
+            },
+        )
+        .unwrap();
+        let id = submit(&jobs, 0, "a", 0);
+        jobs.take(i32::MIN).unwrap();
+        jobs.update(&id, |job| job.uploaded = true);
+
+        jobs.fail(&id, error(Phase::Processing));
+        assert!(jobs.get(&id).unwrap().uploaded);
+        let job = jobs.take(i32::MIN).unwrap();
+        assert_eq!(job.state, JobState::Processing);
+
+        jobs.fail(&id, error(Phase::Upload));
+        assert!(!jobs.get(&id).unwrap().uploaded);
+        assert_eq!(jobs.take(i32::MIN).unwrap().state, JobState::Uploading);
+    }
+
+    #[test]
+    fn only_dead_letters_are_retried() {
+        let directory = TempDir::new("dead-letter");
+        let jobs = Jobs::open(
+            &directory.0,
+            QueueSettings {
+                max_attempts: 1,
+                ..settings()
+            },
+        )
+        .unwrap();
+        let id = submit(&jobs, 0, "a", 0);
+        assert!(jobs.retry(&id).is_none());
+        assert!(jobs.retry("unknown").is_none());
+
+        jobs.take(i32::MIN).unwrap();
+        jobs.update(&id, |job| job.results = vec!["partial.dcm".to_string()]);
+        assert!(jobs.fail(&id, error(Phase::Download)));
+        let job = jobs.retry(&id).unwrap();
+        assert_eq!(job.state, JobState::Queued);
+        assert_eq!(job.attempts, 0);
+        assert!(job.error.is_none());
+        assert!(job.results.is_empty());
+        assert_eq!(jobs.take(i32::MIN).unwrap().id, id);
+    }
+
+    #[test]
+    fn interrupted_jobs_are_queued_again_when_reopened() {
+        let directory = TempDir::new("reopen");
+        let (taken, queued, done) = {
+            let jobs = Jobs::open(&directory.0, settings()).unwrap();
+            let taken = submit(&jobs, 5, "a", 0);
+            let queued = submit(&jobs, 0, "a", 0);
+            let done = submit(&jobs, 0, "b", 0);
+            assert_eq!(jobs.take(i32::MIN).unwrap().id, taken);
+            jobs.set_state(&done, JobState::Done);
+            (taken, queued, done)
+        };
+        // an unreadable job is ignored
+        fs::create_dir_all(directory.0.join("broken")).unwrap();
+        fs::write(directory.0.join("broken").join(JOB_FILE_NAME), "{").unwrap();
+
+        let jobs = Jobs::open(&directory.0, settings()).unwrap();
+        assert_eq!(jobs.list(None).len(), 3);
+        assert_eq!(jobs.get(&taken).unwrap().state, JobState::Queued);
+        assert_eq!(jobs.get(&queued).unwrap().state, JobState::Queued);
+        assert_eq!(jobs.get(&done).unwrap().state, JobState::Done);
+        assert_eq!(jobs.take(i32::MIN).unwrap().id, taken);
+    }
+
+    #[test]
+    fn finished_jobs_are_removed_after_the_retention() {
+        let directory = TempDir::new("retention");
+        let jobs = Jobs::open(
+            &directory.0,
+            QueueSettings {
+                retention: Some(Duration::from_secs(3600)),
+                ..settings()
+            },
+        )
+        .unwrap();
+        let expired = submit(&jobs, 0, "a", 0);
+        let recent = submit(&jobs, 0, "a", 0);
+        let queued = submit(&jobs, 0, "a", 0);
+        jobs.update(&expired, |job| job.state = JobState::DeadLetter);
+        jobs.update(&recent, |job| job.state = JobState::Done);
+        // updated two hours ago
+        for id in [&expired, &queued] {
+            jobs.lock().jobs.get_mut(id).unwrap().updated_at -= 7200;
+        }
+
+        assert_eq!(jobs.prune(), 1);
+        assert!(jobs.get(&expired).is_none());
+        assert!(!jobs.directory(&expired).exists());
+        assert!(jobs.get(&recent).is_some());
+        assert!(jobs.get(&queued).is_some());
+        assert!(jobs.directory(&recent).exists());
+    }
+}
@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{self, Tracer},
+    Resource,
+};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
+
+/// Spans exported to OpenTelemetry, whatever the level of the logs.
+const TRACES_DIRECTIVE: &str = "milvue_rs=info";
+
+#[derive(Copy, Clone, ValueEnum, Debug)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Quiet,
+}
+
+#[derive(Copy, Clone, ValueEnum, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// One JSON object per line, with the fields of the current spans
+    Json,
+}
+
+#[derive(Copy, Clone, ValueEnum, Debug)]
+pub enum LogRotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+/// Logging options of the command line.
+pub struct LogSettings<'a> {
+    /// Overrides RUST_LOG when set.
+    pub level: Option<LogLevel>,
+    pub timestamp: bool,
+    pub format: LogFormat,
+    pub file: Option<&'a Path>,
+    pub rotation: LogRotation,
+    pub max_files: Option<usize>,
+    /// OTLP/HTTP collector receiving the spans, e.g. `http://localhost:4318`.
+    pub otlp_endpoint: Option<&'a str>,
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Installs the global subscriber: logs are written to stderr, and to a rotating file if requested, and spans are
+/// exported to an OpenTelemetry collector if requested.
+///
+/// The level given on the command line wins over RUST_LOG, which wins over the default `info` level.
+pub fn init(settings: &LogSettings) -> Result<(), String> {
+    let filter = match settings.level {
+        Some(level) => EnvFilter::new(directive(level)),
+        None => EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new(directive(LogLevel::Info))),
+    };
+
+    let mut layers: Vec<BoxedLayer> = vec![stderr_layer(settings)];
+    if let Some(path) = settings.file {
+        layers.push(file_layer(path, settings)?);
+    }
+
+    let tracer = settings.otlp_endpoint.map(tracer).transpose()?;
+
+    tracing_subscriber::registry()
+        .with(layers.with_filter(filter))
+        .with(tracer.map(|tracer| {
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer)
+                .with_filter(EnvFilter::new(TRACES_DIRECTIVE))
+        }))
+        .try_init()
+        .map_err(|e| format!("Error while setting subscriber for tracing: {}", e))
+}
+
+/// Sends the spans to the OTLP/HTTP collector at `endpoint` in batches, and propagates their W3C trace context to
+/// the Milvue API.
+///
+/// The `OTEL_EXPORTER_OTLP_*` environment variables override the endpoint.
+fn tracer(endpoint: &str) -> Result<Tracer, String> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(trace::config().with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            env!("CARGO_PKG_NAME"),
+        )])))
+        .install_batch(runtime::Tokio)
+        .map_err(|e| format!("Error while setting up the OTLP exporter: {}", e))
+}
+
+/// Exports the spans not sent yet, before the process exits.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+fn directive(level: LogLevel) -> &'static str {
+    match level {
+        LogLevel::Debug => "milvue_rs=debug",
+        LogLevel::Info => "milvue_rs=info",
+        LogLevel::Warn => "milvue_rs=warn",
+        LogLevel::Error => "milvue_rs=error",
+        LogLevel::Quiet => "milvue_rs=off",
+    }
+}
+
+// The formatters don't yield the same type with or without time or JSON, which prevents using a single builder.
+fn stderr_layer(settings: &LogSettings) -> BoxedLayer {
+    let layer = fmt::layer().with_writer(std::io::stderr);
+    match (settings.format, settings.timestamp) {
+        (LogFormat::Text, true) => layer.boxed(),
+        (LogFormat::Text, false) => layer.without_time().boxed(),
+        (LogFormat::Json, true) => layer.json().boxed(),
+        (LogFormat::Json, false) => layer.json().without_time().boxed(),
+    }
+}
+
+/// Log files are always timestamped and never colored.
+fn file_layer(path: &Path, settings: &LogSettings) -> Result<BoxedLayer, String> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("Invalid log file: {}", path.display()))?;
+
+    let mut builder = RollingFileAppender::builder()
+        .rotation(match settings.rotation {
+            LogRotation::Never => Rotation::NEVER,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+        })
+        .filename_prefix(file_name);
+    if let Some(max_files) = settings.max_files {
+        builder = builder.max_log_files(max_files);
+    }
+    let appender = builder
+        .build(&directory)
+        .map_err(|e| format!("Error while opening the log file {}: {}", path.display(), e))?;
+
+    let layer = fmt::layer().with_writer(appender).with_ansi(false);
+    Ok(match settings.format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    })
+}
@@ -0,0 +1,1270 @@
+mod audit;
+mod doctor;
+mod jobs;
+mod logging;
+mod metrics;
+mod output;
+mod progress;
+mod serve;
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use dicom_object::{FileDicomObject, InMemDicomObject, OpenFileOptions};
+use indicatif::MultiProgress;
+use milvue_rs::{
+    audit::AuditSettings,
+    cache::{hash_file, submission_key, CacheEntry, SubmissionCache},
+    config::{ApiKeySource, Config, PriorityRule, Profile, ProfileParams, ProxyConfig},
+    metrics::{Metrics, StudyState},
+    transcode, CircuitBreakerSettings, DicomSource, FailoverClient, InferenceCommand, Language,
+    MilvueClient, MilvueClientBuilder, MilvueError, MilvueParams, MilvueUrl, OutputFormat,
+    OutputSelection, Rate, RateLimits, RecapTheme, StaticReportFormat, StatusProgress,
+    StructuredReportFormat, Timeouts, TlsSettings, TranscodePolicy,
+};
+use tokio::sync::{
+    mpsc::{self, UnboundedSender},
+    Barrier, Mutex, Semaphore,
+};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use walkdir::WalkDir;
+
+use crate::{
+    audit::AuditCommand,
+    logging::{LogFormat, LogLevel, LogRotation, LogSettings},
+    output::{fatal, Event, Outcome, OutputMode, Phase, Printer, EXIT_CODES_HELP, EXIT_SUCCESS},
+    progress::{multi_progress, StudyProgress},
+    serve::ServeArgs,
+};
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    author,
+    version,
+    about,
+    after_help = EXIT_CODES_HELP,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Input directory
+    #[clap(required = true)]
+    input_dir: Option<PathBuf>,
+    /// Output directory, may contain DICOM attribute names between braces (e.g. "results/{StudyInstanceUID}") [default: .]
+    #[clap(short = 'o', long)]
+    output_dir: Option<String>,
+    /// Additional output directory the results are copied to, same syntax as --output-dir (repeatable)
+    #[clap(long = "sink")]
+    sinks: Vec<String>,
+    /// Recursive search in the input directory
+    #[clap(short = 'r', long, default_value = "false")]
+    recursive: bool,
+    /// Configuration file, read after the system and user configuration files
+    #[clap(short = 'c', long, global = true)]
+    config: Option<PathBuf>,
+    /// Profile of the configuration to use
+    #[clap(short = 'p', long, global = true)]
+    profile: Option<String>,
+    /// API key for the Milvue API, visible in the process list: prefer --api-key-file, --api-key-env or the configuration
+    #[clap(short = 'k', long, group = "key")]
+    api_key: Option<String>,
+    /// File containing the API key, read again before every request
+    #[clap(long, group = "key")]
+    api_key_file: Option<PathBuf>,
+    /// Environment variable containing the API key
+    #[clap(long, group = "key")]
+    api_key_env: Option<String>,
+    /// API URL for the Milvue API
+    #[clap(short, long)]
+    api_url: Option<String>,
+    /// PEM file of additional root certificates, e.g. the CA of the hospital network
+    #[clap(long = "ca-cert")]
+    ca_certs: Vec<PathBuf>,
+    /// PEM file of the client certificate, for mutual TLS
+    #[clap(long)]
+    client_cert: Option<PathBuf>,
+    /// PEM file of the private key of the client certificate, if not in --client-cert
+    #[clap(long, requires = "client_cert")]
+    client_key: Option<PathBuf>,
+    /// URL of the HTTP proxy, credentials are better set in the configuration [default: HTTPS_PROXY]
+    #[clap(long)]
+    proxy: Option<String>,
+    /// Host reached without the proxy
+    #[clap(long, requires = "proxy")]
+    no_proxy: Vec<String>,
+    /// Seconds allowed to establish a connection [default: 30]
+    #[clap(long)]
+    connect_timeout: Option<u64>,
+    /// Seconds allowed for a single request [default: unlimited]
+    #[clap(long)]
+    request_timeout: Option<u64>,
+    /// Deadline in seconds of the upload of a study [default: unlimited]
+    #[clap(long)]
+    upload_timeout: Option<u64>,
+    /// Deadline in seconds of the processing of a study by Milvue [default: unlimited]
+    #[clap(long)]
+    processing_timeout: Option<u64>,
+    /// Deadline in seconds of the download of the results of a study [default: unlimited]
+    #[clap(long)]
+    download_timeout: Option<u64>,
+    /// Requests per minute allowed for an operation, upload, status or results, e.g. upload=30 [default: unlimited]
+    #[clap(long, value_name = "OPERATION=PER_MINUTE", value_parser = parse_rate_limit, global = true)]
+    rate_limit: Vec<RateLimits>,
+    /// Consecutive connection errors or 5xx responses opening the circuit breaker [default: disabled, 5 if enabled]
+    #[clap(long, global = true)]
+    circuit_failures: Option<u32>,
+    /// Seconds the circuit breaker stays open before probing the API [default: disabled, 30 if enabled]
+    #[clap(long, global = true)]
+    circuit_open_duration: Option<u64>,
+    /// Profile of an environment new studies are submitted to while the others fail, in order of preference
+    #[clap(long = "failover-profile", global = true)]
+    failover_profiles: Vec<String>,
+    /// Milvue environment, the API URL is read from the matching MILVUE_API_URL* environment variable
+    #[arg(value_enum)]
+    #[clap(short = 'e', long, conflicts_with = "api_url")]
+    environment: Option<MilvueUrl>,
+    /// Maximum number of studies uploaded at the same time [default: unlimited, 4 with serve]
+    #[clap(short = 'j', long, global = true, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    concurrency: Option<usize>,
+    /// Run SmartUrgences inference on the dataset
+    #[clap(short = 'u', long)]
+    smarturgences: bool,
+    /// Run SmartXpert inference on the dataset
+    #[clap(short = 'x', long)]
+    smartxpert: bool,
+    /// Set the language for the annotated images [default: en]
+    #[arg(value_enum)]
+    #[clap(short, long)]
+    language: Option<Language>,
+    /// Specify the output format for the annotated images [default: overlay]
+    #[arg(value_enum)]
+    #[clap(short, long)]
+    format: Option<OutputFormat>,
+    /// Choose the output selection [default: all]
+    #[arg(value_enum)]
+    #[clap(short = 'O', long)]
+    output_selection: Option<OutputSelection>,
+    /// Choose the theme for the recap [default: dark]
+    #[arg(value_enum)]
+    #[clap(short = 't', long)]
+    recap_theme: Option<RecapTheme>,
+    /// Select the format for the static report [default: rgb]
+    #[arg(value_enum)]
+    #[clap(short = 's', long)]
+    static_report: Option<StaticReportFormat>,
+    /// Select the format for the structured report [default: none]
+    #[arg(value_enum)]
+    #[clap(short = 'S', long)]
+    structured_report: Option<StructuredReportFormat>,
+    /// Set the log level, overrides RUST_LOG [default: info]
+    #[arg(value_enum)]
+    #[clap(short = 'L', long, global = true)]
+    log_level: Option<LogLevel>,
+    /// Display timestamps with log messages
+    #[clap(short = 'T', long, global = true)]
+    timestamp: bool,
+    /// Format of the log messages
+    #[arg(value_enum)]
+    #[clap(long, default_value = "text", global = true)]
+    log_format: LogFormat,
+    /// Also write the log messages to this file, always with timestamps
+    #[clap(long, global = true)]
+    log_file: Option<PathBuf>,
+    /// When to start a new log file, suffixed with the date
+    #[arg(value_enum)]
+    #[clap(long, default_value = "daily", requires = "log_file", global = true)]
+    log_rotation: LogRotation,
+    /// Number of log files kept, the oldest are deleted [default: unlimited]
+    #[clap(long, requires = "log_file", global = true)]
+    log_max_files: Option<usize>,
+    /// Export the spans of the studies to this OpenTelemetry collector (OTLP/HTTP), e.g. http://localhost:4318
+    #[clap(long, global = true)]
+    otlp_endpoint: Option<String>,
+    /// Hide the progress bars
+    #[clap(long)]
+    no_progress: bool,
+    /// Format of the events printed on stdout, logs are always written to stderr
+    #[arg(value_enum)]
+    #[clap(long, default_value = "text", global = true)]
+    output: OutputMode,
+    /// Serve Prometheus metrics on GET /metrics at this address during the run, e.g. 127.0.0.1:9090
+    #[clap(long, global = true)]
+    metrics_addr: Option<SocketAddr>,
+    /// Record the submissions in a local cache and don't submit again a study with the same files and parameters
+    #[clap(long)]
+    cache: bool,
+    /// Location of the submission cache [default: milvue_rs/submissions.json in the user cache directory]
+    #[clap(long)]
+    cache_file: Option<PathBuf>,
+    /// What to do with a study found in the submission cache
+    #[arg(value_enum)]
+    #[clap(long, default_value = "reuse")]
+    cache_mode: CacheMode,
+    /// Submit the studies even if they are found in the submission cache
+    #[clap(long)]
+    force: bool,
+    /// Convert the DICOM files to this transfer syntax UID before the upload, unless their transfer syntax is accepted
+    #[clap(long, global = true)]
+    transcode_to: Option<String>,
+    /// Transfer syntax UIDs uploaded as is with --transcode-to [default: Explicit and Implicit VR Little Endian]
+    #[clap(
+        long = "accept-transfer-syntax",
+        requires = "transcode_to",
+        global = true
+    )]
+    accepted_transfer_syntaxes: Vec<String>,
+    /// Convert the results to this transfer syntax UID before saving them
+    #[clap(long, global = true)]
+    transcode_results_to: Option<String>,
+    /// Record every upload and download in this hash-chained audit log
+    #[clap(long, global = true)]
+    audit_log: Option<PathBuf>,
+    /// Identity of the operator recorded in the audit log [default: $USER]
+    #[clap(long, requires = "audit_log", global = true)]
+    audit_operator: Option<String>,
+    /// Record the SHA-256 of the StudyInstanceUIDs in the audit log instead of the UIDs
+    #[clap(long, requires = "audit_log", global = true)]
+    audit_hash_uids: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Audit log of the transfers
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+    /// Serve a local REST API submitting the studies with the API key of the profile
+    Serve(ServeArgs),
+    /// Diagnose the connection to the environments of the profile, from the DNS resolution to the authentication
+    Doctor,
+}
+
+#[derive(Copy, Clone, ValueEnum, Debug, PartialEq, Eq)]
+enum CacheMode {
+    /// Don't submit the study again, reuse the previous results or download them again if they were deleted
+    Reuse,
+    /// Ask Milvue whether it still knows the study, submit it again if it doesn't
+    CheckRemote,
+}
+
+/// Settings of the run, resolved from the configuration files and the command line arguments.
+struct Settings {
+    client: FailoverClient,
+    params: Vec<MilvueParams>,
+    /// The output directory template followed by the sinks.
+    output_templates: Vec<String>,
+    concurrency: Option<usize>,
+    transcode_uploads: Option<TranscodePolicy>,
+    transcode_results: Option<TranscodePolicy>,
+    /// The submission cache, if enabled.
+    cache: Option<Mutex<SubmissionCache>>,
+    cache_mode: CacheMode,
+    force: bool,
+    /// The metrics of the run, if served.
+    metrics: Option<Arc<Metrics>>,
+    /// Rules giving a priority to the studies.
+    priorities: Vec<PriorityRule>,
+}
+
+impl Settings {
+    /// Counts a state of the study for an inference command, if the metrics are served.
+    fn count(&self, inference_command: &InferenceCommand, state: StudyState) {
+        if let Some(metrics) = &self.metrics {
+            metrics.study(inference_command, state);
+        }
+    }
+
+    /// Counts a state of the study for every requested inference command.
+    fn count_all(&self, state: StudyState) {
+        for param in &self.params {
+            self.count(&param.inference_command, state);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    if let Err(message) = logging::init(&LogSettings {
+        level: args.log_level,
+        timestamp: args.timestamp,
+        format: args.log_format,
+        file: args.log_file.as_deref(),
+        rotation: args.log_rotation,
+        max_files: args.log_max_files,
+        otlp_endpoint: args.otlp_endpoint.as_deref(),
+    }) {
+        eprintln!("{}", message);
+        fatal(args.output, Event::fatal("invalid_log_file", message));
+    }
+
+    if let Some(Command::Audit { command }) = &args.command {
+        process::exit(audit::run(command, args.output));
+    }
+
+    // the doctor reports the settings that prevent building a client instead of failing on them
+    if let Some(Command::Doctor) = &args.command {
+        let exit_code = doctor::run(doctor_clients(&args), args.output).await;
+        logging::shutdown();
+        process::exit(exit_code);
+    }
+
+    let settings = match settings_from_args(&args) {
+        Ok(settings) => Arc::new(settings),
+        Err(e) => {
+            error!("Error: {}", e);
+            fatal(args.output, Event::error(None, None, &e));
+        }
+    };
+
+    if let (Some(addr), Some(metrics)) = (args.metrics_addr, &settings.metrics) {
+        if let Err(message) = metrics::spawn(addr, metrics.clone()) {
+            error!("{}", message);
+            fatal(args.output, Event::fatal("invalid_metrics_addr", message));
+        }
+    }
+
+    if let Some(Command::Serve(serve_args)) = &args.command {
+        if let Err(message) = serve::run(serve_args, settings).await {
+            error!("{}", message);
+            logging::shutdown();
+            fatal(args.output, Event::fatal("serve_failed", message));
+        }
+        logging::shutdown();
+        process::exit(EXIT_SUCCESS);
+    }
+
+    let input_dir = args
+        .input_dir
+        .as_deref()
+        .expect("The input directory is required without a subcommand");
+    let dicom_list = input_dir_validator(input_dir, args.recursive, args.output);
+
+    // progress bars would be interleaved with the JSON events
+    let multi = multi_progress(args.no_progress || args.output == OutputMode::Json);
+    let mut printer = Printer::new(args.output, multi.clone());
+
+    let inventory = match inventory_from_pathbuf(dicom_list) {
+        Some(inventory) => inventory,
+        None => {
+            warn!("No DICOM file to process.");
+            logging::shutdown();
+            process::exit(printer.finish());
+        }
+    };
+    debug!("Inventory: {:?}", inventory);
+
+    // the studies with the highest priority are started first
+    let mut inventory: Vec<_> = inventory
+        .into_iter()
+        .map(|study| (study_priority(&study.1, &settings), study))
+        .collect();
+    inventory.sort_by(|(a, study_a), (b, study_b)| b.cmp(a).then(study_a.0.cmp(&study_b.0)));
+
+    let barrier = Arc::new(tokio::sync::Barrier::new(inventory.len()));
+    let semaphore = Arc::new(Semaphore::new(
+        settings.concurrency.unwrap_or(Semaphore::MAX_PERMITS),
+    ));
+
+    // creating a channel to communicate between the manager and the workers
+    // and a vector to store the tasks
+    let (tx, mut rx) = mpsc::unbounded_channel::<Event>();
+    let mut tasks = Vec::new();
+
+    // process every study in parallel (in worker threads)
+    inventory.into_iter().for_each(|(_, study)| {
+        let settings = settings.clone();
+        let tx = tx.clone();
+        let barrier = barrier.clone();
+        let semaphore = semaphore.clone();
+        let multi = multi.clone();
+        let span = info_span!("study", study_instance_uid = %study.0);
+        let study_instance_uid = study.0.clone();
+        tasks.push((
+            study_instance_uid,
+            tokio::spawn(
+                process_study(study, tx, settings, barrier, semaphore, multi).instrument(span),
+            ),
+        ))
+    });
+
+    // launching a manager thread that will receive the events from the workers
+    let manager = tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            printer.print(event);
+        }
+        printer
+    });
+
+    // the end, a worker which stopped unexpectedly fails its study
+    for (study_instance_uid, task) in tasks {
+        if let Err(e) = task.await {
+            error!("The worker of study {} stopped: {}", study_instance_uid, e);
+            let _ = tx.send(Event::Error {
+                study_instance_uid: Some(study_instance_uid.clone()),
+                phase: None,
+                code: "worker_failed",
+                http_status: None,
+                message: e.to_string(),
+            });
+            let _ = tx.send(Event::Finished {
+                study_instance_uid,
+                outcome: Outcome::Failed,
+            });
+        }
+    }
+    drop(tx);
+    let printer = match manager.await {
+        Ok(printer) => printer,
+        Err(e) => {
+            error!("The output of the run stopped: {}", e);
+            logging::shutdown();
+            fatal(args.output, Event::fatal("output_failed", e.to_string()));
+        }
+    };
+    logging::shutdown();
+    process::exit(printer.finish());
+}
+
+async fn process_study(
+    study: (String, Vec<(String, PathBuf)>),
+    tx: UnboundedSender<Event>,
+    settings: Arc<Settings>,
+    barrier: Arc<Barrier>,
+    semaphore: Arc<Semaphore>,
+    multi: MultiProgress,
+) {
+    // the manager only stops once every worker is done, the events are never lost
+    let emit = |event: Event| tx.send(event).expect("The manager outlives the workers");
+    let progress = StudyProgress::new(&multi, &study.0);
+    let reporter = progress.reporter(status_changes(&study.0, &tx));
+    let mut failed = false;
+    let received = Instant::now();
+    settings.count_all(StudyState::Received);
+
+    let cache_key = match &settings.cache {
+        Some(_) => cache_key(&study, &settings).await,
+        None => None,
+    };
+    let (upload_needed, download_needed) = match &cache_key {
+        Some(key) if !settings.force => check_cache(key, &study.0, &settings).await,
+        _ => (true, true),
+    };
+
+    if upload_needed {
+        let permit = semaphore
+            .acquire()
+            .await
+            .expect("The semaphore is never closed");
+        let sources = study_sources(&study, &settings)
+            .instrument(info_span!("inventory", files = study.1.len()))
+            .await;
+        let uploaded = match sources {
+            Ok(sources) => settings.client.upload(&study.0, sources, &reporter).await,
+            Err(e) => Err(e),
+        };
+        match uploaded {
+            Ok(_) => {
+                settings.count_all(StudyState::Uploaded);
+                emit(Event::Uploaded {
+                    study_instance_uid: study.0.clone(),
+                    files: study.1.len(),
+                })
+            }
+            Err(e) => {
+                warn!("Error while uploading the study: {}", e);
+                emit(Event::error(Some(&study.0), Some(Phase::Upload), &e));
+                settings.count_all(StudyState::Failed);
+                progress.finish("upload failed");
+                emit(Event::Finished {
+                    study_instance_uid: study.0.clone(),
+                    outcome: Outcome::Failed,
+                });
+                drop(permit);
+                // the other studies wait for this one before polling
+                barrier.wait().await;
+                return;
+            }
+        };
+
+        drop(permit);
+    }
+
+    // Wait for all studies to be uploaded
+    barrier.wait().await;
+
+    if !download_needed {
+        emit(Event::Skipped {
+            study_instance_uid: study.0.clone(),
+            reason: "results already downloaded",
+        });
+        settings.client.unpin(&study.0);
+        progress.finish("cached");
+        emit(Event::Finished {
+            study_instance_uid: study.0.clone(),
+            outcome: Outcome::Skipped,
+        });
+        return;
+    }
+
+    // Poll for results
+    emit(Event::Polling {
+        study_instance_uid: study.0.clone(),
+    });
+    match settings.client.wait_for_done(&study.0, &reporter).await {
+        Ok(_) => emit(Event::Predicted {
+            study_instance_uid: study.0.clone(),
+        }),
+        Err(e) => {
+            warn!("Error while polling for results: {}", e);
+            emit(Event::error(Some(&study.0), Some(Phase::Processing), &e));
+            failed = true;
+        }
+    };
+
+    // Download the results
+    for param in &settings.params {
+        info!(
+            "Downloading {} results for study {}",
+            param.inference_command, study.0
+        );
+    }
+
+    let response = match settings
+        .client
+        .get_many(&study.0, &settings.params, &reporter)
+        .await
+    {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Error while downloading the results: {}", e);
+            emit(Event::error(Some(&study.0), Some(Phase::Download), &e));
+            settings.client.unpin(&study.0);
+            settings.count_all(StudyState::Failed);
+            progress.finish("download failed");
+            emit(Event::Finished {
+                study_instance_uid: study.0.clone(),
+                outcome: Outcome::Failed,
+            });
+            return;
+        }
+    };
+
+    for (param, e) in &response.errors {
+        warn!(
+            "Error while downloading the {} results: {}",
+            param.inference_command, e
+        );
+        emit(Event::error(Some(&study.0), Some(Phase::Download), e));
+        settings.count(&param.inference_command, StudyState::Failed);
+    }
+
+    let mut complete = response.is_complete();
+    let mut outputs = Vec::new();
+    for (param, res) in response.results {
+        let mut saved = true;
+        emit(Event::Downloaded {
+            study_instance_uid: study.0.clone(),
+            inference_command: param.inference_command.clone(),
+            results: res.as_ref().map_or(0, Vec::len),
+        });
+        match res {
+            Some(dicoms) => {
+                let mut paths = Vec::new();
+                for dicom in dicoms {
+                    let dicom = match &settings.transcode_results {
+                        Some(policy) => match transcode(dicom.clone(), policy) {
+                            Ok(dicom) => dicom,
+                            Err(e) => {
+                                warn!("Error while transcoding a result, saved as is: {}", e);
+                                dicom
+                            }
+                        },
+                        None => dicom,
+                    };
+                    for template in &settings.output_templates {
+                        match save_result(&dicom, template) {
+                            Ok(path) => paths.push(path),
+                            Err(e) => {
+                                warn!("Error while saving a result: {}", e);
+                                emit(Event::error(Some(&study.0), Some(Phase::Download), &e));
+                                saved = false;
+                            }
+                        }
+                    }
+                }
+                emit(Event::Saved {
+                    study_instance_uid: study.0.clone(),
+                    inference_command: param.inference_command.clone(),
+                    paths: paths.clone(),
+                });
+                outputs.extend(paths);
+            }
+            None => {
+                warn!("No results for study {} for config {:#?}", study.0, param);
+            }
+        }
+        complete &= saved;
+        match failed || !saved {
+            true => settings.count(&param.inference_command, StudyState::Failed),
+            false => {
+                settings.count(&param.inference_command, StudyState::Completed);
+                if let Some(metrics) = &settings.metrics {
+                    metrics.observe_latency(&param.inference_command, received.elapsed());
+                }
+            }
+        }
+    }
+
+    if let (Some(cache), Some(key), true) = (&settings.cache, cache_key, complete) {
+        let mut cache = cache.lock().await;
+        cache.insert(
+            key,
+            CacheEntry::new(
+                &study.0,
+                settings.client.client_for(&study.0).url(),
+                outputs,
+            ),
+        );
+        if let Err(e) = cache.save() {
+            warn!("Error while saving the submission cache: {}", e);
+        }
+    }
+    // the environment of the study is no longer needed once its results are saved
+    settings.client.unpin(&study.0);
+
+    if complete {
+        progress.finish("done");
+    } else {
+        progress.finish("partially downloaded");
+    }
+    emit(Event::Finished {
+        study_instance_uid: study.0.clone(),
+        outcome: match failed || !complete {
+            true => Outcome::Failed,
+            false => Outcome::Succeeded,
+        },
+    });
+}
+
+/// Returns a callback emitting an event every time the processing status of a study changes.
+fn status_changes(
+    study_instance_uid: &str,
+    tx: &UnboundedSender<Event>,
+) -> impl Fn(&StatusProgress) + Send + Sync + 'static {
+    let study_instance_uid = study_instance_uid.to_string();
+    let tx = tx.clone();
+    let last_status = std::sync::Mutex::new(None);
+    move |status| {
+        let mut last_status = last_status.lock().expect("status lock poisoned");
+        if last_status.as_ref() != Some(&status.status) {
+            *last_status = Some(status.status.clone());
+            // the status may change after the manager stopped if a worker panicked
+            let _ = tx.send(Event::Status {
+                study_instance_uid: study_instance_uid.clone(),
+                status: status.status.clone(),
+            });
+        }
+    }
+}
+
+/// Builds the sources of the files of a study, converted to the accepted transfer syntaxes if requested.
+async fn study_sources(
+    study: &(String, Vec<(String, PathBuf)>),
+    settings: &Settings,
+) -> Result<Vec<DicomSource>, MilvueError> {
+    let mut sources = Vec::with_capacity(study.1.len());
+    for (sop_instance_uid, path) in &study.1 {
+        let source = DicomSource::File {
+            path: path.clone(),
+            sop_instance_uid: Some(sop_instance_uid.clone()),
+        };
+        sources.push(match &settings.transcode_uploads {
+            Some(policy) => source.transcode(policy).await?,
+            None => source,
+        });
+    }
+    Ok(sources)
+}
+
+/// Computes the priority of a study with the priority rules of the profile, from its first file.
+fn study_priority(files: &[(String, PathBuf)], settings: &Settings) -> i32 {
+    let path = match files.first() {
+        Some((_, path)) if !settings.priorities.is_empty() => path,
+        _ => return 0,
+    };
+    match OpenFileOptions::new()
+        .read_until(dicom_dictionary_std::tags::PIXEL_DATA)
+        .open_file(path)
+    {
+        Ok(object) => PriorityRule::highest(&settings.priorities, path, &object),
+        Err(e) => {
+            warn!(
+                "Error while reading {} for its priority: {}",
+                path.display(),
+                e
+            );
+            0
+        }
+    }
+}
+
+/// Computes the key of the study in the submission cache, hashing its files.
+async fn cache_key(
+    study: &(String, Vec<(String, PathBuf)>),
+    settings: &Settings,
+) -> Option<String> {
+    let files = study.1.clone();
+    let instances = tokio::task::spawn_blocking(move || {
+        files
+            .into_iter()
+            .map(|(sop_instance_uid, path)| Ok((sop_instance_uid, hash_file(&path)?)))
+            .collect::<Result<Vec<_>, MilvueError>>()
+    })
+    .await
+    .expect("Hashing the files doesn't panic");
+
+    match instances.and_then(|instances| submission_key(&study.0, &instances, &settings.params)) {
+        Ok(key) => Some(key),
+        Err(e) => {
+            warn!(
+                "Error while computing the cache key of study {}, the cache is ignored: {}",
+                study.0, e
+            );
+            None
+        }
+    }
+}
+
+/// Looks the study up in the submission cache.
+///
+/// Returns whether the study must be uploaded, and whether its results must be downloaded.
+async fn check_cache(key: &str, study_instance_uid: &str, settings: &Settings) -> (bool, bool) {
+    let cache = settings.cache.as_ref().expect("Only called with a cache");
+    let previous = match cache.lock().await.get(key) {
+        Some(previous) => previous.clone(),
+        None => return (true, true),
+    };
+    // the results of a study are only known to the environment it was submitted to
+    settings.client.pin(study_instance_uid, &previous.url);
+
+    match settings.cache_mode {
+        CacheMode::Reuse => {
+            info!(
+                "Study {} already submitted on {}",
+                study_instance_uid, previous.submitted_at
+            );
+            (false, !previous.outputs_exist())
+        }
+        CacheMode::CheckRemote => {
+            match settings.client.get_study_status(study_instance_uid).await {
+                Ok(_) => {
+                    info!("Study {} is known to Milvue", study_instance_uid);
+                    (false, true)
+                }
+                Err(e) => {
+                    info!(
+                        "Study {} is unknown to Milvue, submitting it again: {}",
+                        study_instance_uid, e
+                    );
+                    (true, true)
+                }
+            }
+        }
+    }
+}
+
+/// Resolves the settings of the run.
+///
+/// The selected profile of the configuration files is overridden by the command line arguments, and the parameters
+/// still unset fall back to the defaults of the command line.
+fn settings_from_args(args: &Args) -> Result<Settings, MilvueError> {
+    let metrics = args.metrics_addr.map(|_| Arc::new(Metrics::new()));
+    let (config, profile) = profile_with_args(args)?;
+
+    let mut params = profile.params.clone();
+    params.language.get_or_insert(Language::En);
+    params.output_format.get_or_insert(OutputFormat::Overlay);
+    params.output_selection.get_or_insert(OutputSelection::All);
+    params.recap_theme.get_or_insert(RecapTheme::Dark);
+    params
+        .static_report_format
+        .get_or_insert(StaticReportFormat::Rgb);
+    params
+        .structured_report_format
+        .get_or_insert(StructuredReportFormat::None);
+
+    let cache = match (args.cache, &args.cache_file) {
+        (false, _) => None,
+        (true, Some(path)) => Some(Mutex::new(SubmissionCache::open(path)?)),
+        (true, None) => match SubmissionCache::default_path() {
+            Some(path) => Some(Mutex::new(SubmissionCache::open(&path)?)),
+            None => {
+                warn!("No cache directory found, use --cache-file to enable the submission cache");
+                None
+            }
+        },
+    };
+
+    let mut output_templates = vec![profile
+        .output_template
+        .clone()
+        .unwrap_or_else(|| ".".to_string())];
+    output_templates.extend(profile.sinks.iter().cloned());
+
+    Ok(Settings {
+        client: failover_client(&config, &profile, metrics.as_ref())?,
+        params: params.to_params()?,
+        output_templates,
+        concurrency: profile.concurrency,
+        transcode_uploads: profile.transcode_uploads,
+        transcode_results: profile.transcode_results,
+        cache,
+        cache_mode: args.cache_mode,
+        force: args.force,
+        metrics,
+        priorities: profile.priorities.clone(),
+    })
+}
+
+/// Loads the configuration and the profile selected on the command line, with the values set on the command line on
+/// top of the profile.
+fn profile_with_args(args: &Args) -> Result<(Config, Profile), MilvueError> {
+    let config = Config::load(args.config.as_deref())?;
+    let mut profile = config.profile(args.profile.as_deref())?;
+
+    // An environment given on the command line must win over a URL set in the profile
+    if args.environment.is_some() {
+        profile.url = None;
+    }
+    profile.merge(profile_from_args(args));
+    Ok((config, profile))
+}
+
+/// Builds the clients diagnosed by the doctor: the one of the profile, followed by the ones of its `failover`
+/// profiles, without their audit log.
+///
+/// # Returns
+///
+/// * The client of each environment, or the name of its profile and the error preventing building it.
+fn doctor_clients(args: &Args) -> Vec<Result<MilvueClient, (String, MilvueError)>> {
+    let name = format!("profile {}", args.profile.as_deref().unwrap_or("default"));
+    let (config, mut profile) = match profile_with_args(args) {
+        Ok(profile) => profile,
+        Err(e) => return vec![Err((name, e))],
+    };
+    profile.audit = None;
+    let mut clients = vec![profile.client().map_err(|e| (name, e))];
+    for failover in &profile.failover {
+        let client = config.profile(Some(failover)).and_then(|mut profile| {
+            profile.audit = None;
+            profile.client()
+        });
+        clients.push(client.map_err(|e| (format!("profile {}", failover), e)));
+    }
+    clients
+}
+
+/// Builds the client of the environment of the profile, followed by the environments of its `failover` profiles.
+///
+/// With failover profiles, every environment gets a circuit breaker, with the default settings if its profile has
+/// none, to tell whether it is healthy. The failover environments use the settings of their own profile, except the
+/// audit log and the metrics of the main one.
+fn failover_client(
+    config: &Config,
+    profile: &Profile,
+    metrics: Option<&Arc<Metrics>>,
+) -> Result<FailoverClient, MilvueError> {
+    let failover = !profile.failover.is_empty();
+    let builder = |profile: &Profile| -> Result<MilvueClientBuilder, MilvueError> {
+        let mut builder = profile.client_builder()?;
+        if failover && profile.circuit_breaker.is_none() {
+            builder = builder.circuit_breaker(CircuitBreakerSettings::default());
+        }
+        if let Some(metrics) = metrics {
+            builder = builder.metrics(metrics.clone());
+        }
+        Ok(builder)
+    };
+    let primary = builder(profile)?.build()?;
+    let mut endpoints = vec![primary.clone()];
+    for name in &profile.failover {
+        let mut failover_profile = config.profile(Some(name))?;
+        failover_profile.audit = None;
+        let mut failover_builder = builder(&failover_profile)?;
+        if let Some(audit_log) = primary.audit_log() {
+            failover_builder = failover_builder.audit_log(audit_log.clone());
+        }
+        let client = failover_builder.build()?;
+        info!(
+            "Studies fail over to {} of profile {} while the previous environments fail",
+            client.url(),
+            name
+        );
+        endpoints.push(client);
+    }
+    FailoverClient::new(endpoints)
+}
+
+/// Parses a `--rate-limit` flag, `OPERATION=PER_MINUTE`.
+fn parse_rate_limit(value: &str) -> Result<RateLimits, String> {
+    let (operation, per_minute) = value
+        .split_once('=')
+        .ok_or_else(|| "expected OPERATION=PER_MINUTE, e.g. upload=30".to_string())?;
+    let per_minute = per_minute
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| format!("invalid rate {}: {}", per_minute, e))?;
+    if !(per_minute.is_finite() && per_minute > 0.0) {
+        return Err(format!("the rate must be positive, got {}", per_minute));
+    }
+    let mut rate_limits = RateLimits::default();
+    rate_limits
+        .set(operation.trim(), Rate::per_minute(per_minute))
+        .map_err(|e| e.to_string())?;
+    Ok(rate_limits)
+}
+
+/// Builds a profile holding the values explicitly set on the command line.
+fn profile_from_args(args: &Args) -> Profile {
+    let mut inference_commands = Vec::new();
+    if args.smarturgences {
+        inference_commands.push(InferenceCommand::SmartUrgences);
+    }
+    if args.smartxpert {
+        inference_commands.push(InferenceCommand::SmartXpert);
+    }
+
+    Profile {
+        environment: args.environment.clone(),
+        url: args.api_url.clone(),
+        api_key: match (&args.api_key, &args.api_key_file, &args.api_key_env) {
+            (Some(key), _, _) => Some(ApiKeySource::Value(key.clone())),
+            (_, Some(path), _) => Some(ApiKeySource::File(path.clone())),
+            (_, _, Some(var)) => Some(ApiKeySource::Env(var.clone())),
+            _ => None,
+        },
+        auth_scheme: None,
+        oauth2: None,
+        tls: (!args.ca_certs.is_empty() || args.client_cert.is_some()).then(|| TlsSettings {
+            root_certificates: args.ca_certs.clone(),
+            only_custom_roots: false,
+            client_certificate: args.client_cert.clone(),
+            client_key: args.client_key.clone(),
+        }),
+        proxy: args.proxy.as_ref().map(|url| ProxyConfig {
+            url: url.clone(),
+            username: None,
+            password: None,
+            no_proxy: args.no_proxy.clone(),
+        }),
+        timeouts: Timeouts {
+            connect: args.connect_timeout.map(Duration::from_secs),
+            request: args.request_timeout.map(Duration::from_secs),
+            upload: args.upload_timeout.map(Duration::from_secs),
+            processing: args.processing_timeout.map(Duration::from_secs),
+            download: args.download_timeout.map(Duration::from_secs),
+        },
+        rate_limits: args.rate_limit.iter().cloned().fold(
+            RateLimits::default(),
+            |mut rate_limits, rate_limit| {
+                rate_limits.merge(rate_limit);
+                rate_limits
+            },
+        ),
+        circuit_breaker: (args.circuit_failures.is_some() || args.circuit_open_duration.is_some())
+            .then(|| CircuitBreakerSettings {
+                failure_threshold: args.circuit_failures,
+                open_duration: args.circuit_open_duration.map(Duration::from_secs),
+            }),
+        params: ProfileParams {
+            inference_commands,
+            language: args.language.clone(),
+            output_format: args.format.clone(),
+            output_selection: args.output_selection.clone(),
+            recap_theme: args.recap_theme.clone(),
+            static_report_format: args.static_report.clone(),
+            structured_report_format: args.structured_report.clone(),
+            ..Default::default()
+        },
+        output_template: args.output_dir.clone(),
+        concurrency: args.concurrency,
+        sinks: args.sinks.clone(),
+        transcode_uploads: args.transcode_to.as_ref().map(|target| {
+            let mut policy = TranscodePolicy::new(target);
+            if args.accepted_transfer_syntaxes.is_empty() {
+                policy.accepted = TranscodePolicy::default().accepted;
+            } else {
+                policy.accepted = args.accepted_transfer_syntaxes.clone();
+            }
+            policy
+        }),
+        transcode_results: args
+            .transcode_results_to
+            .as_deref()
+            .map(TranscodePolicy::new),
+        audit: args.audit_log.as_ref().map(|path| AuditSettings {
+            path: path.clone(),
+            operator: args.audit_operator.clone(),
+            hash_study_uids: args.audit_hash_uids,
+        }),
+        priorities: Vec::new(),
+        failover: args.failover_profiles.clone(),
+    }
+}
+
+/// Writes a result in the directory built from the template, named after its SOPInstanceUID.
+///
+/// # Returns
+///
+/// * A Result wrapping the path of the result, [MilvueError::InvalidValue] if the template misses a `}` or the result
+///   has no valid SOPInstanceUID, or [MilvueError::Io] if the result cannot be written.
+fn save_result(
+    dicom: &FileDicomObject<InMemDicomObject>,
+    template: &str,
+) -> Result<PathBuf, MilvueError> {
+    let mut new_path = PathBuf::new();
+    for comp in PathBuf::from(template).components() {
+        if let Some(mut s) = comp.as_os_str().to_str() {
+            let mut new_component = String::new();
+            while let Some(start) = s.find('{') {
+                let end = s.find('}').ok_or_else(|| {
+                    MilvueError::InvalidValue("output template", template.to_string())
+                })?;
+                new_component.push_str(&s[..start]);
+                match dicom.element_by_name(&s[start + 1..end]) {
+                    Ok(element) => new_component
+                        .push_str(element.to_str()?.trim_end_matches([char::from(0), ' '])),
+                    Err(_) => {
+                        warn!("Could not find element {} in DICOM file, using default value \"ElementNameNotFound\"", &s[start + 1..end]);
+                        new_component.push_str("ElementNameNotFound");
+                    }
+                }
+                s = &s[end + 1..];
+                if s.find('{').is_none() {
+                    new_component.push_str(s);
+                }
+            }
+            if new_component.is_empty() {
+                new_path.push(s);
+            } else {
+                new_path.push(new_component);
+            }
+        }
+    }
+
+    if !new_path.exists() {
+        info!("Creating output directory: {}", new_path.display());
+        std::fs::create_dir_all(&new_path).map_err(|e| MilvueError::Io(new_path.clone(), e))?;
+    }
+
+    let sop_instance_uid = dicom
+        .element_by_name("SOPInstanceUID")?
+        .to_str()?
+        .trim_end_matches([char::from(0), ' '])
+        .to_string();
+    // the UID comes from the API, it must not escape the directory
+    if sop_instance_uid.is_empty()
+        || !sop_instance_uid
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.')
+    {
+        return Err(MilvueError::InvalidValue(
+            "SOPInstanceUID",
+            sop_instance_uid,
+        ));
+    }
+    new_path.push(format!("{}.dcm", sop_instance_uid));
+    dicom
+        .write_to_file(&new_path)
+        .map_err(|e| MilvueError::Io(new_path.clone(), std::io::Error::other(e)))?;
+    Ok(new_path)
+}
+
+/// Checks the input directory and lists its files, exits if it isn't a directory.
+fn input_dir_validator(input_dir: &Path, recursive: bool, mode: OutputMode) -> Vec<PathBuf> {
+    let message = if !input_dir.exists() {
+        format!("Input directory does not exist: {}", input_dir.display())
+    } else if !input_dir.is_dir() {
+        format!(
+            "Input directory is not a directory: {}",
+            input_dir.display()
+        )
+    } else {
+        String::new()
+    };
+    if !message.is_empty() {
+        error!("{}", message);
+        fatal(mode, Event::fatal("invalid_input", message));
+    }
+
+    list_files(input_dir, recursive)
+}
+
+/// Lists the files of a directory, or the path itself if it is a file.
+fn list_files(path: &Path, recursive: bool) -> Vec<PathBuf> {
+    let walker = match recursive {
+        true => WalkDir::new(path).into_iter(),
+        false => WalkDir::new(path).max_depth(1).into_iter(),
+    };
+
+    walker
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            if entry.file_type().is_file() {
+                Some(entry.into_path())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn inventory_from_pathbuf(dicoms: Vec<PathBuf>) -> Option<HashMap<String, Vec<(String, PathBuf)>>> {
+    let mut inventory = HashMap::new();
+
+    // loop over every path provided by the user
+    dicoms.iter().for_each(|dicom| {
+        // open the file until the PixelData tag
+        if let Some(object) = match OpenFileOptions::new()
+            .read_until(dicom_dictionary_std::tags::PIXEL_DATA)
+            .open_file(dicom)
+        {
+            Ok(object) => Some(object),
+            Err(e) => {
+                warn!("{:?} is not a valid dicom file: {}", dicom, e);
+                None
+            }
+        } {
+            // Actually build the inventory from the DICOM file
+            let uid = |name: &str| -> Option<String> {
+                Some(
+                    object
+                        .element_by_name(name)
+                        .ok()?
+                        .to_str()
+                        .ok()?
+                        .to_string(),
+                )
+            };
+            let (Some(study_instance_uid), Some(sop_instance_uid)) =
+                (uid("StudyInstanceUID"), uid("SOPInstanceUID"))
+            else {
+                warn!(
+                    "Skipping {}: no StudyInstanceUID or SOPInstanceUID",
+                    dicom.display()
+                );
+                return;
+            };
+
+            if sop_instance_uid.contains("1.2.826.0.1.3680043.10.457") {
+                warn!("Skipping {}: File is from Milvue", dicom.display());
+            }
+
+            inventory
+                .entry(study_instance_uid)
+                .or_insert_with(Vec::new)
+                .push((sop_instance_uid, dicom.to_owned()));
+        };
+    });
+
+    if inventory.is_empty() {
+        None
+    } else {
+        Some(inventory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::{DataElement, PrimitiveValue, VR};
+    use dicom_dictionary_std::tags;
+    use dicom_object::FileMetaTableBuilder;
+
+    /// SOP class of the test objects.
+    const DIGITAL_XRAY_IMAGE_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.1.1";
+
+    /// Builds a result of the study 1.2.3, with `sop_instance_uid` if any.
+    fn result(sop_instance_uid: Option<&str>) -> FileDicomObject<InMemDicomObject> {
+        let mut dicom = InMemDicomObject::from_element_iter([
+            DataElement::new(
+                tags::SOP_CLASS_UID,
+                VR::UI,
+                PrimitiveValue::from(DIGITAL_XRAY_IMAGE_STORAGE),
+            ),
+            DataElement::new(
+                tags::STUDY_INSTANCE_UID,
+                VR::UI,
+                PrimitiveValue::from("1.2.3"),
+            ),
+        ]);
+        if let Some(uid) = sop_instance_uid {
+            dicom.put(DataElement::new(
+                tags::SOP_INSTANCE_UID,
+                VR::UI,
+                PrimitiveValue::from(uid),
+            ));
+        }
+        dicom
+            .with_meta(
+                FileMetaTableBuilder::new()
+                    .transfer_syntax("1.2.840.10008.1.2.1")
+                    .media_storage_sop_class_uid(DIGITAL_XRAY_IMAGE_STORAGE)
+                    .media_storage_sop_instance_uid(sop_instance_uid.unwrap_or("1.2.3.9")),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn results_are_saved_in_the_directory_of_the_template() {
+        let directory = std::env::temp_dir().join(format!("milvue_rs-save-{}", std::process::id()));
+        let template = format!("{}/{{StudyInstanceUID}}", directory.display());
+
+        let path = save_result(&result(Some("1.2.3.4")), &template).unwrap();
+        assert_eq!(path, directory.join("1.2.3").join("1.2.3.4.dcm"));
+        assert!(path.exists());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn results_which_cannot_be_saved_are_errors() {
+        let directory =
+            std::env::temp_dir().join(format!("milvue_rs-unsaved-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let template = directory.display().to_string();
+
+        assert!(save_result(&result(None), &template).is_err());
+        assert!(matches!(
+            save_result(&result(Some("../1.2.3.4")), &template),
+            Err(MilvueError::InvalidValue("SOPInstanceUID", _))
+        ));
+        // the output directory is a file
+        let file = directory.join("file");
+        std::fs::write(&file, b"").unwrap();
+        assert!(matches!(
+            save_result(&result(Some("1.2.3.4")), &file.display().to_string()),
+            Err(MilvueError::Io(_, _))
+        ));
+        assert!(matches!(
+            save_result(
+                &result(Some("1.2.3.4")),
+                &format!("{}/{{Modality", template)
+            ),
+            Err(MilvueError::InvalidValue("output template", _))
+        ));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,35 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{http::header, response::IntoResponse, routing::get, Router, Server};
+use milvue_rs::metrics::Metrics;
+use tracing::{error, info};
+
+/// Content type of the Prometheus text format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Serves the metrics on `GET /metrics` at `addr`, in the background until the end of the process.
+pub fn spawn(addr: SocketAddr, metrics: Arc<Metrics>) -> Result<(), String> {
+    let app = Router::new().route(
+        "/metrics",
+        get(move || {
+            let metrics = metrics.clone();
+            async move { ([(header::CONTENT_TYPE, CONTENT_TYPE)], metrics.encode()).into_response() }
+        }),
+    );
+    let server = Server::try_bind(&addr)
+        .map_err(|e| {
+            format!(
+                "Error while binding the metrics endpoint to {}: {}",
+                addr, e
+            )
+        })?
+        .serve(app.into_make_service());
+
+    info!("Serving metrics on http://{}/metrics", server.local_addr());
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("Error while serving the metrics: {}", e);
+        }
+    });
+    Ok(())
+}
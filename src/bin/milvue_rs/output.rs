@@ -0,0 +1,265 @@
+use std::{path::PathBuf, process};
+
+use clap::ValueEnum;
+use indicatif::MultiProgress;
+use milvue_rs::{HealthReport, InferenceCommand, MilvueError};
+use serde::{Deserialize, Serialize};
+
+/// Every study was processed successfully, or skipped thanks to the submission cache.
+pub const EXIT_SUCCESS: i32 = 0;
+/// The run could not start, e.g. invalid configuration or input directory.
+pub const EXIT_FATAL: i32 = 1;
+/// At least one study failed, the others were processed.
+pub const EXIT_PARTIAL_FAILURE: i32 = 3;
+/// The audit log was modified, or its last record is incomplete.
+pub const EXIT_AUDIT_BROKEN: i32 = 4;
+/// At least one environment failed a step of the health check.
+pub const EXIT_UNHEALTHY: i32 = 5;
+
+/// Exit codes documented in the help of the binary.
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  every study was processed, or skipped thanks to the submission cache
+  1  fatal error, no study was processed (configuration, input directory)
+  2  invalid command line
+  3  at least one study failed
+  4  the audit log doesn't verify (audit verify)
+  5  an environment failed a step of the health check (doctor)";
+
+#[derive(Copy, Clone, ValueEnum, Debug, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Human readable lines
+    Text,
+    /// One JSON event per line, followed by a summary object
+    Json,
+}
+
+/// Phase of the processing of a study.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Upload,
+    Processing,
+    Download,
+}
+
+/// Final state of a study.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+/// Event of the run, printed on stdout.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Uploaded {
+        study_instance_uid: String,
+        files: usize,
+    },
+    Skipped {
+        study_instance_uid: String,
+        reason: &'static str,
+    },
+    Polling {
+        study_instance_uid: String,
+    },
+    Status {
+        study_instance_uid: String,
+        status: String,
+    },
+    Predicted {
+        study_instance_uid: String,
+    },
+    Downloaded {
+        study_instance_uid: String,
+        inference_command: InferenceCommand,
+        results: usize,
+    },
+    Saved {
+        study_instance_uid: String,
+        inference_command: InferenceCommand,
+        paths: Vec<PathBuf>,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        study_instance_uid: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        phase: Option<Phase>,
+        code: &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        http_status: Option<u16>,
+        message: String,
+    },
+    Finished {
+        study_instance_uid: String,
+        outcome: Outcome,
+    },
+    AuditVerified {
+        path: PathBuf,
+        records: u64,
+        last_hash: String,
+    },
+    HealthChecked {
+        healthy: bool,
+        #[serde(flatten)]
+        report: HealthReport,
+    },
+}
+
+impl Event {
+    /// Builds the error event of a library error.
+    pub fn error(
+        study_instance_uid: Option<&str>,
+        phase: Option<Phase>,
+        error: &MilvueError,
+    ) -> Self {
+        Event::Error {
+            study_instance_uid: study_instance_uid.map(str::to_string),
+            phase,
+            code: error.code(),
+            http_status: match error {
+                MilvueError::StatusResponseError(response) => Some(response.status().as_u16()),
+                _ => None,
+            },
+            message: error.to_string(),
+        }
+    }
+
+    /// Builds the event of an error preventing the run, outside of the library.
+    pub fn fatal(code: &'static str, message: String) -> Self {
+        Event::Error {
+            study_instance_uid: None,
+            phase: None,
+            code,
+            http_status: None,
+            message,
+        }
+    }
+}
+
+/// Counts of the run, printed last.
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub studies: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub results_saved: usize,
+    pub exit_code: i32,
+}
+
+/// Prints the events of the run in the selected mode and tallies the summary.
+pub struct Printer {
+    mode: OutputMode,
+    multi: MultiProgress,
+    summary: Summary,
+}
+
+impl Printer {
+    pub fn new(mode: OutputMode, multi: MultiProgress) -> Self {
+        Printer {
+            mode,
+            multi,
+            summary: Summary::default(),
+        }
+    }
+
+    pub fn print(&mut self, event: Event) {
+        match &event {
+            Event::Saved { paths, .. } => self.summary.results_saved += paths.len(),
+            Event::Finished { outcome, .. } => {
+                self.summary.studies += 1;
+                match outcome {
+                    Outcome::Succeeded => self.summary.succeeded += 1,
+                    Outcome::Failed => self.summary.failed += 1,
+                    Outcome::Skipped => self.summary.skipped += 1,
+                }
+            }
+            _ => {}
+        }
+
+        match self.mode {
+            OutputMode::Json => self.print_json(&event),
+            OutputMode::Text => {
+                let line = match &event {
+                    Event::Uploaded {
+                        study_instance_uid, ..
+                    } => format!("Uploaded: {:?}", study_instance_uid),
+                    Event::Skipped {
+                        study_instance_uid,
+                        reason,
+                    } => format!("Skipped: {:?} ({})", study_instance_uid, reason),
+                    Event::Polling { study_instance_uid } => {
+                        format!("Polling for results: {:?}", study_instance_uid)
+                    }
+                    Event::Predicted { study_instance_uid } => {
+                        format!("Predicted: {:?}", study_instance_uid)
+                    }
+                    Event::Downloaded {
+                        study_instance_uid, ..
+                    } => format!("Downloaded: {:?}", study_instance_uid),
+                    Event::Saved {
+                        study_instance_uid, ..
+                    } => format!("Saved: {:?}", study_instance_uid),
+                    // Shown by the progress bars and the logs
+                    Event::Status { .. }
+                    | Event::Error { .. }
+                    | Event::Finished { .. }
+                    | Event::AuditVerified { .. }
+                    | Event::HealthChecked { .. } => return,
+                };
+                self.multi.suspend(|| println!("{}", line));
+            }
+        }
+    }
+
+    /// Prints the summary and returns the exit code of the run.
+    pub fn finish(mut self) -> i32 {
+        self.summary.exit_code = match self.summary.failed {
+            0 => EXIT_SUCCESS,
+            _ => EXIT_PARTIAL_FAILURE,
+        };
+        match self.mode {
+            OutputMode::Json => {
+                #[derive(Serialize)]
+                struct Tagged<'a> {
+                    event: &'static str,
+                    #[serde(flatten)]
+                    summary: &'a Summary,
+                }
+                self.print_json(&Tagged {
+                    event: "summary",
+                    summary: &self.summary,
+                });
+            }
+            OutputMode::Text => println!(
+                "Done: {} studies, {} succeeded, {} failed, {} skipped",
+                self.summary.studies,
+                self.summary.succeeded,
+                self.summary.failed,
+                self.summary.skipped
+            ),
+        }
+        self.summary.exit_code
+    }
+
+    fn print_json(&self, value: &impl Serialize) {
+        let line = serde_json::to_string(value).expect("events are serializable");
+        self.multi.suspend(|| println!("{}", line));
+    }
+}
+
+/// Reports an error preventing the run and exits with [EXIT_FATAL].
+pub fn fatal(mode: OutputMode, event: Event) -> ! {
+    if mode == OutputMode::Json {
+        println!(
+            "{}",
+            serde_json::to_string(&event).expect("events are serializable")
+        );
+    }
+    process::exit(EXIT_FATAL)
+}
@@ -0,0 +1,85 @@
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use milvue_rs::{Progress, ProgressReporter, StatusProgress, TransferProgress};
+
+/// Creates the set of progress bars of the run, hidden if requested.
+pub fn multi_progress(hidden: bool) -> MultiProgress {
+    if hidden {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    }
+}
+
+/// Progress bar of a study, following its upload, processing and download.
+#[derive(Clone)]
+pub struct StudyProgress {
+    bar: ProgressBar,
+}
+
+impl StudyProgress {
+    pub fn new(multi: &MultiProgress, study_instance_uid: &str) -> Self {
+        let bar = multi.add(ProgressBar::new(0));
+        bar.set_prefix(study_instance_uid.to_string());
+        StudyProgress { bar }
+    }
+
+    /// Returns a reporter updating the bar with the events of the library, and passing every status to `on_status`.
+    pub fn reporter(
+        &self,
+        on_status: impl Fn(&StatusProgress) + Send + Sync + 'static,
+    ) -> ProgressReporter {
+        let bar = self.bar.clone();
+        ProgressReporter::new(move |progress| match progress {
+            Progress::Upload(transfer) => update_transfer(&bar, "uploading", &transfer),
+            Progress::Status(status) => {
+                on_status(&status);
+                if bar.length() != Some(0) {
+                    bar.set_style(spinner_style());
+                    bar.set_length(0);
+                }
+                bar.set_message(format!(
+                    "processing: {} (poll {}, {}s)",
+                    status.status,
+                    status.polls,
+                    status.elapsed.as_secs()
+                ));
+                bar.tick();
+            }
+            Progress::Download(transfer) => update_transfer(&bar, "downloading", &transfer),
+        })
+    }
+
+    pub fn finish(&self, message: &str) {
+        self.bar.set_style(spinner_style());
+        self.bar.finish_with_message(message.to_string());
+    }
+}
+
+fn update_transfer(bar: &ProgressBar, phase: &str, transfer: &TransferProgress) {
+    let total_bytes = transfer.total_bytes.unwrap_or(0);
+    if bar.length() != Some(total_bytes) {
+        bar.set_style(transfer_style());
+        bar.set_length(total_bytes);
+    }
+    bar.set_position(transfer.bytes);
+    let files = match transfer.total_files {
+        Some(total_files) => format!("{}/{}", transfer.files_completed, total_files),
+        None => transfer.files_completed.to_string(),
+    };
+    bar.set_message(format!(
+        "{} {} files, {}/s",
+        phase,
+        files,
+        HumanBytes(transfer.throughput() as u64)
+    ));
+}
+
+fn transfer_style() -> ProgressStyle {
+    ProgressStyle::with_template("{prefix:.bold} [{bar:30.cyan/blue}] {bytes}/{total_bytes} {msg}")
+        .expect("valid progress template")
+        .progress_chars("=> ")
+}
+
+fn spinner_style() -> ProgressStyle {
+    ProgressStyle::with_template("{prefix:.bold} {spinner} {msg}").expect("valid progress template")
+}
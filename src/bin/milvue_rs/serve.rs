@@ -0,0 +1,949 @@
+use std::{
+    io::{Cursor, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::Body,
+    extract::{
+        rejection::QueryRejection, DefaultBodyLimit, FromRequest, Multipart, Path as UrlPath,
+        Query, State,
+    },
+    http::{header, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router, Server,
+};
+use clap::Args;
+use dicom_object::{FileDicomObject, InMemDicomObject};
+use milvue_rs::{
+    metrics::StudyState, transcode, InferenceCommand, MilvueError, MilvueParams, ProgressReporter,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::{
+    inventory_from_pathbuf,
+    jobs::{Job, JobError, JobState, Jobs, QueueSettings},
+    list_files,
+    output::Phase,
+    study_priority, study_sources, Settings,
+};
+
+/// Number of studies processed at the same time when the concurrency isn't set.
+const DEFAULT_WORKERS: usize = 4;
+
+/// Interval between the removals of the expired jobs.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Args, Debug, Clone)]
+pub struct ServeArgs {
+    /// Address of the REST API, only reachable from this machine by default, other addresses require --token-file
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    pub listen: SocketAddr,
+    /// File of the token the clients must send in an `Authorization: Bearer` header
+    #[clap(long)]
+    pub token_file: Option<PathBuf>,
+    /// Directory of the uploaded files and of the results of the jobs
+    #[clap(long, default_value = "milvue_rs-jobs")]
+    pub work_dir: PathBuf,
+    /// Directory whose files may be submitted by path (repeatable), submissions by path are refused without it
+    #[clap(long = "allow-path")]
+    pub allowed_paths: Vec<PathBuf>,
+    /// Number of studies processed at the same time [default: --concurrency, or 4]
+    #[clap(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub workers: Option<usize>,
+    /// Number of the workers only processing the studies with a priority above 0
+    #[clap(long, default_value = "0")]
+    pub reserved_workers: usize,
+    /// Number of attempts of a study before it is moved to the dead letters
+    #[clap(long, default_value = "3", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_attempts: u32,
+    /// Delay in seconds before a failed study is processed again, multiplied by the number of failed attempts
+    #[clap(long, default_value = "60")]
+    pub retry_delay: u64,
+    /// Waiting time in seconds raising the priority of a queued study by 1 [default: no aging]
+    #[clap(long)]
+    pub aging: Option<u64>,
+    /// Time in seconds the done and dead-lettered studies are kept with their results, 0 to keep them forever
+    #[clap(long, default_value = "604800")]
+    pub retention: u64,
+}
+
+/// State shared by the handlers and the workers.
+struct ApiState {
+    settings: Arc<Settings>,
+    jobs: Jobs,
+    work_dir: PathBuf,
+    /// Canonical paths of the directories allowed in the submissions by path.
+    allowed_paths: Vec<PathBuf>,
+}
+
+/// Submission of the DICOM files found at a path of the server.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PathSubmission {
+    path: PathBuf,
+    #[serde(default)]
+    recursive: bool,
+    /// Inference commands of the jobs [default: the ones of the profile]
+    #[serde(default)]
+    inference_commands: Vec<InferenceCommand>,
+    /// Priority of the jobs [default: the one of the priority rules]
+    priority: Option<i32>,
+    /// Origin of the submission [default: the path]
+    source: Option<String>,
+}
+
+/// Options of a submission, given in the JSON body or in the fields of the multipart form.
+#[derive(Default)]
+struct SubmissionOptions {
+    inference_commands: Vec<InferenceCommand>,
+    priority: Option<i32>,
+    source: Option<String>,
+}
+
+/// Filter of `GET /jobs`.
+#[derive(Deserialize)]
+struct JobFilter {
+    state: Option<JobState>,
+}
+
+#[derive(Serialize)]
+struct JobList {
+    jobs: Vec<Job>,
+}
+
+/// Error returned by the REST API, with a stable code.
+struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: &'static str, message: impl ToString) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    fn job_not_found(id: &str) -> Self {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "job_not_found",
+            format!("No job {}", id),
+        )
+    }
+}
+
+impl From<MilvueError> for ApiError {
+    fn from(e: MilvueError) -> Self {
+        let status = match e {
+            MilvueError::InvalidValue(_, _) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::new(status, e.code(), e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(json!({ "code": self.code, "message": self.message }));
+        (self.status, body).into_response()
+    }
+}
+
+/// Serves the REST API until the process is interrupted, with the API key of the profile kept on the server.
+///
+/// # Arguments
+///
+/// * `args` - The arguments of the serve command.
+/// * `settings` - The settings resolved from the configuration and the global arguments.
+///
+/// # Returns
+///
+/// * An error message if the work directory, the token file or the address can't be used.
+pub async fn run(args: &ServeArgs, settings: Arc<Settings>) -> Result<(), String> {
+    std::fs::create_dir_all(&args.work_dir).map_err(|e| {
+        format!(
+            "Error while creating the work directory {}: {}",
+            args.work_dir.display(),
+            e
+        )
+    })?;
+    let token = match &args.token_file {
+        Some(path) => Some(read_token(path)?),
+        None if args.listen.ip().is_loopback() => None,
+        None => {
+            return Err(format!(
+                "Refusing to serve the API on {} without authentication, set --token-file or listen on a \
+                 loopback address",
+                args.listen
+            ))
+        }
+    };
+    let allowed_paths = args
+        .allowed_paths
+        .iter()
+        .map(|path| {
+            path.canonicalize()
+                .map_err(|e| format!("Invalid allowed path {}: {}", path.display(), e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let workers = args
+        .workers
+        .or(settings.concurrency)
+        .unwrap_or(DEFAULT_WORKERS);
+    if args.reserved_workers >= workers {
+        return Err(format!(
+            "--reserved-workers ({}) must be lower than the number of workers ({})",
+            args.reserved_workers, workers
+        ));
+    }
+    let jobs = Jobs::open(
+        &args.work_dir.join("jobs"),
+        QueueSettings {
+            max_attempts: args.max_attempts,
+            retry_delay: Duration::from_secs(args.retry_delay),
+            aging: args.aging.map(Duration::from_secs),
+            retention: match args.retention {
+                0 => None,
+                retention => Some(Duration::from_secs(retention)),
+            },
+        },
+    )
+    .map_err(|e| format!("Error while opening the job queue: {}", e))?;
+    let server = Arc::new(ApiState {
+        settings,
+        jobs,
+        work_dir: args.work_dir.clone(),
+        allowed_paths,
+    });
+
+    let app = Router::new()
+        .route("/studies", post(submit))
+        .route("/jobs", get(list_jobs))
+        .route("/jobs/:id", get(get_job))
+        .route("/jobs/:id/retry", post(retry_job))
+        .route("/jobs/:id/results.zip", get(get_results_zip))
+        .route("/jobs/:id/results/*name", get(get_result))
+        // the studies are streamed to the disk, whatever their size
+        .layer(DefaultBodyLimit::disable())
+        .layer(middleware::from_fn_with_state(token, authenticate))
+        .with_state(server.clone());
+    let http = Server::try_bind(&args.listen)
+        .map_err(|e| format!("Error while binding the API to {}: {}", args.listen, e))?
+        .serve(app.into_make_service());
+
+    {
+        let server = server.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                server.jobs.prune();
+            }
+        });
+    }
+
+    for worker in 0..workers {
+        let server = server.clone();
+        let min_priority = match worker < args.reserved_workers {
+            true => 1,
+            false => i32::MIN,
+        };
+        tokio::spawn(async move {
+            loop {
+                let job = server.jobs.next(min_priority).await;
+                let span = info_span!("study", study_instance_uid = %job.study_instance_uid, job = %job.id);
+                process_job(&server, job).instrument(span).await;
+            }
+        });
+    }
+
+    info!(
+        "Serving the API on http://{} with {} workers, {} reserved to the urgent studies",
+        http.local_addr(),
+        workers,
+        args.reserved_workers
+    );
+    http.with_graceful_shutdown(async {
+        let _ = tokio::signal::ctrl_c().await;
+        info!("Interrupted, stopping the API");
+    })
+    .await
+    .map_err(|e| format!("Error while serving the API: {}", e))
+}
+
+/// Reads the token of the API, surrounding whitespace removed.
+fn read_token(path: &Path) -> Result<Arc<str>, String> {
+    let token = std::fs::read_to_string(path).map_err(|e| {
+        format!(
+            "Error while reading the token file {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+    match token.trim() {
+        "" => Err(format!("The token file {} is empty", path.display())),
+        token => Ok(Arc::from(token)),
+    }
+}
+
+/// Rejects the requests without the bearer token of the API, if there is one.
+async fn authenticate(
+    State(token): State<Option<Arc<str>>>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if let Some(token) = token {
+        let given = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !constant_time_eq(given.trim().as_bytes(), token.as_bytes()) {
+            let error = ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "Missing or invalid bearer token",
+            );
+            return ([(header::WWW_AUTHENTICATE, "Bearer")], error).into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// Compares two byte strings in a time that doesn't depend on where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// `POST /studies`: creates a job for every study of the files uploaded in a multipart form, or found at the path
+/// of a JSON body.
+async fn submit(
+    State(server): State<Arc<ApiState>>,
+    request: Request<Body>,
+) -> Result<(StatusCode, Json<JobList>), ApiError> {
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let mut upload_dir = None;
+    let (files, options) = if content_type.starts_with("multipart/form-data") {
+        let multipart = Multipart::from_request(request, &()).await.map_err(|e| {
+            ApiError::new(StatusCode::BAD_REQUEST, "invalid_multipart", e.body_text())
+        })?;
+        let (directory, files, mut options) = server.receive_files(multipart).await?;
+        upload_dir = Some(directory);
+        options.source.get_or_insert_with(|| "upload".to_string());
+        (files, options)
+    } else if content_type.starts_with("application/json") {
+        let Json(submission) = Json::<PathSubmission>::from_request(request, &())
+            .await
+            .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, "invalid_json", e.body_text()))?;
+        let files = server.allowed_files(&submission).await?;
+        let source = submission
+            .source
+            .unwrap_or_else(|| submission.path.display().to_string());
+        let options = SubmissionOptions {
+            inference_commands: submission.inference_commands,
+            priority: submission.priority,
+            source: Some(source),
+        };
+        (files, options)
+    } else {
+        return Err(ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+            "Expected a multipart/form-data or application/json body",
+        ));
+    };
+
+    let settings = server.settings.clone();
+    let studies = tokio::task::spawn_blocking(move || {
+        let inventory = inventory_from_pathbuf(files)?;
+        Some(
+            inventory
+                .into_iter()
+                .map(|study| (study_priority(&study.1, &settings), study))
+                .collect::<Vec<_>>(),
+        )
+    });
+    let studies = match studies
+        .await
+        .expect("Reading the DICOM headers doesn't panic")
+    {
+        Some(studies) => studies,
+        None => {
+            remove_upload_dir(upload_dir.as_deref()).await;
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "no_dicom",
+                "No DICOM file in the submission",
+            ));
+        }
+    };
+
+    let inference_commands = match options.inference_commands.is_empty() {
+        true => server
+            .settings
+            .params
+            .iter()
+            .map(|param| param.inference_command.clone())
+            .collect(),
+        false => options.inference_commands,
+    };
+    let source = options.source.unwrap_or_default();
+    let mut jobs: Vec<Job> = studies
+        .into_iter()
+        .map(|(priority, (study_instance_uid, instances))| {
+            Job::new(
+                study_instance_uid,
+                instances,
+                inference_commands.clone(),
+                options.priority.unwrap_or(priority),
+                source.clone(),
+            )
+        })
+        .collect();
+
+    // the uploaded files of each study move to the upload directory of its job, removed when the job is finished
+    if let Some(directory) = &upload_dir {
+        let mut moved = Ok(());
+        for job in &mut jobs {
+            moved = server.move_uploaded_files(job).await;
+            if moved.is_err() {
+                break;
+            }
+        }
+        if let Err(e) = moved {
+            for job in &jobs {
+                server.remove_uploaded_files(&job.id);
+            }
+            remove_upload_dir(Some(directory)).await;
+            return Err(e.into());
+        }
+        // only the files that aren't DICOM are left
+        remove_upload_dir(Some(directory)).await;
+    }
+
+    for job in &jobs {
+        info!(
+            "Job {} submitted for study {} with {} files, priority {}",
+            job.id, job.study_instance_uid, job.files, job.priority
+        );
+        for inference_command in &job.inference_commands {
+            server
+                .settings
+                .count(inference_command, StudyState::Received);
+        }
+        server.jobs.submit(job.clone());
+    }
+    Ok((StatusCode::ACCEPTED, Json(JobList { jobs })))
+}
+
+/// `GET /jobs`: lists the jobs, oldest first, only the ones in a state with `?state=`.
+async fn list_jobs(
+    State(server): State<Arc<ApiState>>,
+    filter: Result<Query<JobFilter>, QueryRejection>,
+) -> Result<Json<JobList>, ApiError> {
+    let Query(filter) = filter
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, "invalid_query", e.body_text()))?;
+    Ok(Json(JobList {
+        jobs: server.jobs.list(filter.state),
+    }))
+}
+
+/// `GET /jobs/{id}`: returns a job.
+async fn get_job(
+    State(server): State<Arc<ApiState>>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Json<Job>, ApiError> {
+    server
+        .jobs
+        .get(&id)
+        .map(Json)
+        .ok_or_else(|| ApiError::job_not_found(&id))
+}
+
+/// `POST /jobs/{id}/retry`: queues again a job of the dead letters.
+async fn retry_job(
+    State(server): State<Arc<ApiState>>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Json<Job>, ApiError> {
+    let job = server
+        .jobs
+        .get(&id)
+        .ok_or_else(|| ApiError::job_not_found(&id))?;
+    // the uploaded files of a job are removed once it is in the dead letters
+    if !job.uploaded && job.instances.iter().any(|(_, path)| !path.exists()) {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "job_files_removed",
+            format!(
+                "The files of job {} were removed, submit the study again",
+                job.id
+            ),
+        ));
+    }
+    match server.jobs.retry(&id) {
+        Some(job) => {
+            info!("Job {} queued again", id);
+            Ok(Json(job))
+        }
+        None => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "job_not_dead_letter",
+            format!("Job {} is not in the dead letters", job.id),
+        )),
+    }
+}
+
+/// `GET /jobs/{id}/results/{name}`: returns a result of a job, named as in its `results`.
+async fn get_result(
+    State(server): State<Arc<ApiState>>,
+    UrlPath((id, name)): UrlPath<(String, String)>,
+) -> Result<Response, ApiError> {
+    let job = server
+        .jobs
+        .get(&id)
+        .ok_or_else(|| ApiError::job_not_found(&id))?;
+    // only the names listed in the job are served, never an arbitrary path
+    if !job.results.contains(&name) {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "result_not_found",
+            format!("No result {} for job {}", name, id),
+        ));
+    }
+    let path = server.results_dir(&id).join(&name);
+    let content = tokio::fs::read(&path)
+        .await
+        .map_err(|e| ApiError::from(MilvueError::Io(path, e)))?;
+    Ok(([(header::CONTENT_TYPE, "application/dicom")], content).into_response())
+}
+
+/// `GET /jobs/{id}/results.zip`: returns the results of a job in a zip archive.
+async fn get_results_zip(
+    State(server): State<Arc<ApiState>>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Response, ApiError> {
+    let job = server
+        .jobs
+        .get(&id)
+        .ok_or_else(|| ApiError::job_not_found(&id))?;
+    if !matches!(job.state, JobState::Done | JobState::DeadLetter) {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "job_not_finished",
+            format!("Job {} is not finished", id),
+        ));
+    }
+
+    let results_dir = server.results_dir(&id);
+    let names = job.results.clone();
+    let archive = tokio::task::spawn_blocking(move || zip_results(&results_dir, &names))
+        .await
+        .expect("Zipping the results doesn't panic")?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.zip\"", job.study_instance_uid),
+            ),
+        ],
+        archive,
+    )
+        .into_response())
+}
+
+impl ApiState {
+    /// Returns the directory of the results of a job.
+    fn results_dir(&self, id: &str) -> PathBuf {
+        self.jobs.directory(id).join("results")
+    }
+
+    /// Returns the directory of the uploaded files of a job.
+    fn upload_dir(&self, id: &str) -> PathBuf {
+        self.work_dir.join("uploads").join(id)
+    }
+
+    /// Writes the files of a multipart form in a new upload directory, removed if the form can't be read.
+    ///
+    /// # Returns
+    ///
+    /// * The upload directory, the paths of the files, and the options of the `inference_command`, `priority` and
+    ///   `source` fields.
+    async fn receive_files(
+        &self,
+        multipart: Multipart,
+    ) -> Result<(PathBuf, Vec<PathBuf>, SubmissionOptions), ApiError> {
+        let upload_dir = self.upload_dir(&uuid::Uuid::new_v4().to_string());
+        tokio::fs::create_dir_all(&upload_dir)
+            .await
+            .map_err(|e| MilvueError::Io(upload_dir.clone(), e))?;
+        match write_files(&upload_dir, multipart).await {
+            Ok((files, options)) => Ok((upload_dir, files, options)),
+            Err(e) => {
+                remove_upload_dir(Some(&upload_dir)).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Moves the uploaded files of a job to its own upload directory.
+    async fn move_uploaded_files(&self, job: &mut Job) -> Result<(), MilvueError> {
+        let directory = self.upload_dir(&job.id);
+        tokio::fs::create_dir_all(&directory)
+            .await
+            .map_err(|e| MilvueError::Io(directory.clone(), e))?;
+        for (_, path) in &mut job.instances {
+            let moved = directory.join(path.file_name().expect("uploaded files have a name"));
+            tokio::fs::rename(&path, &moved)
+                .await
+                .map_err(|e| MilvueError::Io(path.clone(), e))?;
+            *path = moved;
+        }
+        Ok(())
+    }
+
+    /// Removes the uploaded files of a job, if any, once it is done or in the dead letters.
+    fn remove_uploaded_files(&self, id: &str) {
+        let directory = self.upload_dir(id);
+        match std::fs::remove_dir_all(&directory) {
+            Ok(()) => info!("Uploaded files of job {} removed", id),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!(
+                "Error while removing the uploaded files {}: {}",
+                directory.display(),
+                e
+            ),
+        }
+    }
+
+    /// Lists the files at the path of a submission, if it is in an allowed directory.
+    async fn allowed_files(&self, submission: &PathSubmission) -> Result<Vec<PathBuf>, ApiError> {
+        let path = submission.path.canonicalize().map_err(|e| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                "path_not_found",
+                format!("{}: {}", submission.path.display(), e),
+            )
+        })?;
+        if !self.allowed_paths.iter().any(|root| path.starts_with(root)) {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "path_not_allowed",
+                format!(
+                    "{} is not in a directory allowed with --allow-path",
+                    submission.path.display()
+                ),
+            ));
+        }
+        let recursive = submission.recursive;
+        Ok(
+            tokio::task::spawn_blocking(move || list_files(&path, recursive))
+                .await
+                .expect("Listing the files doesn't panic"),
+        )
+    }
+
+    /// Returns the parameters of the inference commands of a job, based on the parameters of the profile.
+    fn params(&self, inference_commands: &[InferenceCommand]) -> Vec<MilvueParams> {
+        let params = &self.settings.params;
+        inference_commands
+            .iter()
+            .filter_map(|inference_command| {
+                let template = params
+                    .iter()
+                    .find(|param| &param.inference_command == inference_command)
+                    .or_else(|| params.first())?;
+                Some(MilvueParams {
+                    inference_command: inference_command.clone(),
+                    ..template.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// Counts a state of a job for each of its inference commands.
+    fn count(&self, job: &Job, state: StudyState) {
+        for inference_command in &job.inference_commands {
+            self.settings.count(inference_command, state);
+        }
+    }
+
+    /// Records a failed attempt of a job, which is retried later or moved to the dead letters. A job rejected by the
+    /// circuit breaker is held until the circuit is due for a probe, without counting an attempt.
+    fn fail(&self, job: &Job, phase: Phase, e: &MilvueError) {
+        if let MilvueError::CircuitOpen(_, retry_in) = e {
+            warn!("Job {} held until the circuit closes: {}", job.id, e);
+            return self
+                .jobs
+                .postpone(&job.id, JobError::new(phase, e), *retry_in);
+        }
+        if self.jobs.fail(&job.id, JobError::new(phase, e)) {
+            error!(
+                "Job {} moved to the dead letters after {} attempts: {}",
+                job.id,
+                job.attempts + 1,
+                e
+            );
+            self.count(job, StudyState::Failed);
+            self.remove_uploaded_files(&job.id);
+        } else {
+            warn!("Job {} failed, it will be retried: {}", job.id, e);
+        }
+    }
+}
+
+/// Writes the files of a multipart form in a directory.
+///
+/// # Returns
+///
+/// * The paths of the files, and the options of the `inference_command`, `priority` and `source` fields.
+async fn write_files(
+    upload_dir: &Path,
+    mut multipart: Multipart,
+) -> Result<(Vec<PathBuf>, SubmissionOptions), ApiError> {
+    let invalid = |e: axum::extract::multipart::MultipartError| {
+        ApiError::new(StatusCode::BAD_REQUEST, "invalid_multipart", e.body_text())
+    };
+    let mut files = Vec::new();
+    let mut options = SubmissionOptions::default();
+    while let Some(mut field) = multipart.next_field().await.map_err(invalid)? {
+        match field.name() {
+            Some("inference_command") => {
+                let value = field.text().await.map_err(invalid)?;
+                options.inference_commands.push(value.parse()?);
+                continue;
+            }
+            Some("priority") => {
+                let value = field.text().await.map_err(invalid)?;
+                let priority = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| MilvueError::InvalidValue("priority", value))?;
+                options.priority = Some(priority);
+                continue;
+            }
+            Some("source") => {
+                options.source = Some(field.text().await.map_err(invalid)?);
+                continue;
+            }
+            _ => {}
+        }
+        let path = upload_dir.join(format!("{}.dcm", files.len()));
+        let mut file = tokio::fs::File::create(&path)
+            .await
+            .map_err(|e| MilvueError::Io(path.clone(), e))?;
+        while let Some(chunk) = field.chunk().await.map_err(invalid)? {
+            file.write_all(&chunk)
+                .await
+                .map_err(|e| MilvueError::Io(path.clone(), e))?;
+        }
+        files.push(path);
+    }
+    Ok((files, options))
+}
+
+/// Removes an upload directory with the files left in it.
+async fn remove_upload_dir(upload_dir: Option<&Path>) {
+    if let Some(upload_dir) = upload_dir {
+        if let Err(e) = tokio::fs::remove_dir_all(upload_dir).await {
+            warn!(
+                "Error while removing the upload directory {}: {}",
+                upload_dir.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Uploads the study of a job, waits for its processing and saves its results.
+async fn process_job(server: &ApiState, job: Job) {
+    let settings = &server.settings;
+    let reporter = ProgressReporter::default();
+    let study = (job.study_instance_uid.clone(), job.instances.clone());
+
+    // a study uploaded before a restart is only waited for
+    if !job.uploaded {
+        let sources = study_sources(&study, settings)
+            .instrument(info_span!("inventory", files = study.1.len()))
+            .await;
+        let uploaded = match sources {
+            Ok(sources) => settings.client.upload(&study.0, sources, &reporter).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = uploaded {
+            return server.fail(&job, Phase::Upload, &e);
+        }
+        server.count(&job, StudyState::Uploaded);
+        let endpoint = settings.client.client_for(&study.0).url().to_string();
+        server.jobs.update(&job.id, |job| {
+            job.uploaded = true;
+            job.endpoint = Some(endpoint);
+            job.state = JobState::Processing;
+        });
+    } else if let Some(endpoint) = &job.endpoint {
+        settings.client.pin(&study.0, endpoint);
+    }
+
+    let result = wait_and_save(server, &job, &study.0, &reporter).await;
+    settings.client.unpin(&study.0);
+    if let Err((phase, e)) = result {
+        server.fail(&job, phase, &e);
+    }
+}
+
+/// Waits for the processing of the study of a job and saves its results.
+async fn wait_and_save(
+    server: &ApiState,
+    job: &Job,
+    study_instance_uid: &str,
+    reporter: &ProgressReporter,
+) -> Result<(), (Phase, MilvueError)> {
+    let settings = &server.settings;
+    if let Err(e) = settings
+        .client
+        .wait_for_done(study_instance_uid, reporter)
+        .await
+    {
+        return Err((Phase::Processing, e));
+    }
+
+    server.jobs.set_state(&job.id, JobState::Downloading);
+    let params = server.params(&job.inference_commands);
+    let response = match settings
+        .client
+        .get_many(study_instance_uid, &params, reporter)
+        .await
+    {
+        Ok(response) => response,
+        Err(e) => return Err((Phase::Download, e)),
+    };
+
+    let results_dir = server.results_dir(&job.id);
+    let mut results = Vec::new();
+    for (param, dicoms) in response.results {
+        let directory = param.inference_command.to_string();
+        for dicom in dicoms.unwrap_or_default() {
+            match save(dicom, settings, &results_dir.join(&directory)) {
+                Ok(name) => results.push(format!("{}/{}", directory, name)),
+                Err(e) => return Err((Phase::Download, e)),
+            }
+        }
+        settings.count(&param.inference_command, StudyState::Completed);
+        if let Some(metrics) = &settings.metrics {
+            metrics.observe_latency(&param.inference_command, elapsed_since(job.created_at));
+        }
+    }
+    results.sort();
+    results.dedup();
+    info!("Job {} done with {} results", job.id, results.len());
+    server.jobs.update(&job.id, |job| job.results = results);
+
+    match response.errors.into_iter().next() {
+        Some((param, e)) => {
+            warn!(
+                "Error while downloading the {} results: {}",
+                param.inference_command, e
+            );
+            Err((Phase::Download, e))
+        }
+        None => {
+            server.jobs.set_state(&job.id, JobState::Done);
+            server.remove_uploaded_files(&job.id);
+            Ok(())
+        }
+    }
+}
+
+/// Writes a result in a directory, named after its SOPInstanceUID, converted to the transfer syntax of the results
+/// if requested.
+///
+/// # Returns
+///
+/// * The file name of the result.
+fn save(
+    dicom: FileDicomObject<InMemDicomObject>,
+    settings: &Settings,
+    directory: &Path,
+) -> Result<String, MilvueError> {
+    let dicom = match &settings.transcode_results {
+        Some(policy) => match transcode(dicom.clone(), policy) {
+            Ok(dicom) => dicom,
+            Err(e) => {
+                warn!("Error while transcoding a result, saved as is: {}", e);
+                dicom
+            }
+        },
+        None => dicom,
+    };
+    let sop_instance_uid = dicom
+        .element_by_name("SOPInstanceUID")?
+        .to_str()?
+        .trim_end_matches([char::from(0), ' '])
+        .to_string();
+    // the UID comes from the API, it must not escape the directory
+    if sop_instance_uid.is_empty()
+        || !sop_instance_uid
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.')
+    {
+        return Err(MilvueError::InvalidValue(
+            "SOPInstanceUID",
+            sop_instance_uid,
+        ));
+    }
+
+    std::fs::create_dir_all(directory).map_err(|e| MilvueError::Io(directory.to_path_buf(), e))?;
+    let name = format!("{}.dcm", sop_instance_uid);
+    let path = directory.join(&name);
+    dicom
+        .write_to_file(&path)
+        .map_err(|e| MilvueError::Io(path, std::io::Error::other(e)))?;
+    Ok(name)
+}
+
+/// Builds a zip archive of the results of a job.
+fn zip_results(results_dir: &Path, names: &[String]) -> Result<Vec<u8>, ApiError> {
+    let failed =
+        |e: zip::result::ZipError| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "zip", e);
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for name in names {
+        let path = results_dir.join(name);
+        let content = std::fs::read(&path).map_err(|e| MilvueError::Io(path, e))?;
+        archive.start_file(name.as_str(), options).map_err(failed)?;
+        archive
+            .write_all(&content)
+            .map_err(|e| failed(zip::result::ZipError::Io(e)))?;
+    }
+    Ok(archive.finish().map_err(failed)?.into_inner())
+}
+
+/// Returns the time elapsed since a Unix timestamp in seconds.
+fn elapsed_since(timestamp: u64) -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.saturating_sub(Duration::from_secs(timestamp)))
+        .unwrap_or_default()
+}
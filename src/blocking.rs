@@ -0,0 +1,372 @@
+//! A blocking API, for programs that don't run a tokio runtime.
+//!
+//! Mirrors the asynchronous API: [MilvueClient] wraps a [crate::MilvueClient], and the free functions build a client
+//! on every call. They all share a single threaded runtime, built on first use. The functions must not be called from
+//! an asynchronous context, where they panic like `reqwest::blocking`.
+//!
+//! ```ignore rust no_run
+//! use milvue_rs::{blocking, DicomSource, MilvueParams};
+//!
+//! let client = blocking::MilvueClient::with_api_key("https://api.milvue.com", &key)?;
+//! client.upload(vec![DicomSource::from(path)], &Default::default())?;
+//! client.wait_for_done(study_instance_uid, &Default::default())?;
+//! let results = client.get(study_instance_uid, &MilvueParams::default(), &Default::default())?;
+//! ```
+//!
+//! Requires the `blocking` feature.
+use dicom_object::{FileDicomObject, InMemDicomObject};
+use std::{
+    future::Future,
+    sync::{Arc, OnceLock},
+};
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    auth::Authenticator, DicomSource, MilvueError, MilvueParams, MilvueUrl, MultiGetResponse,
+    ProgressReporter, StatusResponse,
+};
+
+/// A blocking client for a Milvue environment.
+///
+/// Cloning the client is cheap, the clones share their connections, and every client shares the same runtime.
+#[derive(Clone)]
+pub struct MilvueClient {
+    inner: crate::MilvueClient,
+    runtime: Arc<Runtime>,
+}
+
+impl std::fmt::Debug for MilvueClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MilvueClient")
+            .field("url", &self.inner.url())
+            .finish_non_exhaustive()
+    }
+}
+
+impl MilvueClient {
+    /// Creates a client for the environment at `url`.
+    pub fn new(
+        url: &str,
+        authenticator: impl Authenticator + 'static,
+    ) -> Result<MilvueClient, MilvueError> {
+        MilvueClient::from_async(crate::MilvueClient::new(url, authenticator)?)
+    }
+
+    /// Creates a client for the environment at `url` with a static API key.
+    pub fn with_api_key(url: &str, key: &str) -> Result<MilvueClient, MilvueError> {
+        MilvueClient::from_async(crate::MilvueClient::with_api_key(url, key)?)
+    }
+
+    /// Wraps an asynchronous client, e.g. built with [crate::MilvueClient::builder()].
+    pub fn from_async(inner: crate::MilvueClient) -> Result<MilvueClient, MilvueError> {
+        Ok(MilvueClient {
+            inner,
+            runtime: runtime()?,
+        })
+    }
+
+    /// Returns the wrapped asynchronous client.
+    pub fn as_async(&self) -> &crate::MilvueClient {
+        &self.inner
+    }
+
+    /// URL of the environment.
+    pub fn url(&self) -> &str {
+        self.inner.url()
+    }
+
+    /// Uploads DICOM files, see [crate::MilvueClient::upload()].
+    ///
+    /// # Arguments
+    ///
+    /// * `sources` - The DICOM files to be uploaded, see [DicomSource].
+    /// * `progress` - The [ProgressReporter] receiving the progress events, `&Default::default()` to ignore them.
+    ///
+    /// # Returns
+    ///
+    /// * A Result indicating success (empty Ok value) or an error.
+    pub fn upload(
+        &self,
+        sources: Vec<DicomSource>,
+        progress: &ProgressReporter,
+    ) -> Result<(), MilvueError> {
+        self.block_on(self.inner.upload(sources, progress))?;
+        Ok(())
+    }
+
+    /// Fetches the DICOM files of a study, see [crate::MilvueClient::get()].
+    ///
+    /// # Arguments
+    ///
+    /// * `study_instance_uid` - A string slice that holds the ID of the study
+    /// * `milvue_params` - A reference to MilvueParams containing parameters for the request
+    /// * `progress` - The [ProgressReporter] receiving the progress events, `&Default::default()` to ignore them
+    ///
+    /// # Returns
+    ///
+    /// * An Option containing a vector of DICOM files or None when there is no output for the given configuration.
+    pub fn get(
+        &self,
+        study_instance_uid: &str,
+        milvue_params: &MilvueParams,
+        progress: &ProgressReporter,
+    ) -> Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError> {
+        self.block_on(self.inner.get(study_instance_uid, milvue_params, progress))
+    }
+
+    /// Fetches the DICOM files of a study for several configurations, see [crate::MilvueClient::get_many()].
+    ///
+    /// # Arguments
+    ///
+    /// * `study_instance_uid` - A string slice that holds the ID of the study
+    /// * `milvue_params` - A list of MilvueParams, one request is made per configuration
+    /// * `progress` - The [ProgressReporter] receiving the progress events, `&Default::default()` to ignore them
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping a [MultiGetResponse] holding the results and errors paired with their configuration.
+    pub fn get_many(
+        &self,
+        study_instance_uid: &str,
+        milvue_params: &[MilvueParams],
+        progress: &ProgressReporter,
+    ) -> Result<MultiGetResponse, MilvueError> {
+        self.block_on(
+            self.inner
+                .get_many(study_instance_uid, milvue_params, progress),
+        )
+    }
+
+    /// Fetches the status of a study.
+    ///
+    /// # Arguments
+    ///
+    /// * `study_instance_uid` - A string slice that holds the ID of the study
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the parsed [StatusResponse] or an error
+    pub fn get_study_status(
+        &self,
+        study_instance_uid: &str,
+    ) -> Result<StatusResponse, MilvueError> {
+        self.block_on(async {
+            Ok(self
+                .inner
+                .get_study_status(study_instance_uid)
+                .await?
+                .json()
+                .await?)
+        })
+    }
+
+    /// Waits for a study to be done, see [crate::MilvueClient::wait_for_done()].
+    ///
+    /// # Arguments
+    ///
+    /// * `study_instance_uid` - A string slice that holds the ID of the study
+    /// * `progress` - The [ProgressReporter] receiving the progress events, `&Default::default()` to ignore them
+    ///
+    /// # Returns
+    ///
+    /// * A Result indicating success (empty Ok value) or an error
+    pub fn wait_for_done(
+        &self,
+        study_instance_uid: &str,
+        progress: &ProgressReporter,
+    ) -> Result<(), MilvueError> {
+        self.block_on(self.inner.wait_for_done(study_instance_uid, progress))
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+}
+
+/// Uploads DICOM objects in the default environment, see [crate::post()].
+pub fn post(
+    key: &str,
+    dicom_list: &mut [FileDicomObject<InMemDicomObject>],
+) -> Result<(), MilvueError> {
+    post_with_url(&MilvueUrl::default().get_url_from_envar()?, key, dicom_list)
+}
+
+/// Uploads DICOM objects to a specific URL, see [crate::post_with_url()].
+pub fn post_with_url(
+    url: &str,
+    key: &str,
+    dicom_list: &mut [FileDicomObject<InMemDicomObject>],
+) -> Result<(), MilvueError> {
+    runtime()?.block_on(crate::post_with_url(url, key, dicom_list))?;
+    Ok(())
+}
+
+/// Uploads DICOM files from any mix of sources in the default environment, see [crate::upload()].
+pub fn upload(key: &str, sources: Vec<DicomSource>) -> Result<(), MilvueError> {
+    upload_with_url(&MilvueUrl::default().get_url_from_envar()?, key, sources)
+}
+
+/// Uploads DICOM files from any mix of sources to a specific URL, see [crate::upload_with_url()].
+pub fn upload_with_url(url: &str, key: &str, sources: Vec<DicomSource>) -> Result<(), MilvueError> {
+    upload_with_progress(url, key, sources, &ProgressReporter::default())
+}
+
+/// Uploads DICOM files to a specific URL, reporting the progress of the upload, see [crate::upload_with_progress()].
+pub fn upload_with_progress(
+    url: &str,
+    key: &str,
+    sources: Vec<DicomSource>,
+    progress: &ProgressReporter,
+) -> Result<(), MilvueError> {
+    MilvueClient::with_api_key(url, key)?.upload(sources, progress)
+}
+
+/// Fetches DICOM files from a study in the default environment, see [crate::get()].
+pub fn get(
+    key: &str,
+    study_instance_uid: &str,
+    milvue_params: &MilvueParams,
+) -> Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError> {
+    get_with_url(
+        &MilvueUrl::default().get_url_from_envar()?,
+        key,
+        study_instance_uid,
+        milvue_params,
+    )
+}
+
+/// Fetches DICOM files from a study in the specified environment, see [crate::get_with_url()].
+pub fn get_with_url(
+    url: &str,
+    key: &str,
+    study_instance_uid: &str,
+    milvue_params: &MilvueParams,
+) -> Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError> {
+    get_with_progress(
+        url,
+        key,
+        study_instance_uid,
+        milvue_params,
+        &ProgressReporter::default(),
+    )
+}
+
+/// Fetches DICOM files from a study, reporting the progress of the download, see [crate::get_with_progress()].
+pub fn get_with_progress(
+    url: &str,
+    key: &str,
+    study_instance_uid: &str,
+    milvue_params: &MilvueParams,
+    progress: &ProgressReporter,
+) -> Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError> {
+    MilvueClient::with_api_key(url, key)?.get(study_instance_uid, milvue_params, progress)
+}
+
+/// Fetches the DICOM files of a study for several configurations in the default environment, see
+/// [crate::get_many()].
+pub fn get_many(
+    key: &str,
+    study_instance_uid: &str,
+    milvue_params: &[MilvueParams],
+) -> Result<MultiGetResponse, MilvueError> {
+    get_many_with_url(
+        &MilvueUrl::default().get_url_from_envar()?,
+        key,
+        study_instance_uid,
+        milvue_params,
+    )
+}
+
+/// Fetches the DICOM files of a study for several configurations in the specified environment, see
+/// [crate::get_many_with_url()].
+pub fn get_many_with_url(
+    url: &str,
+    key: &str,
+    study_instance_uid: &str,
+    milvue_params: &[MilvueParams],
+) -> Result<MultiGetResponse, MilvueError> {
+    get_many_with_progress(
+        url,
+        key,
+        study_instance_uid,
+        milvue_params,
+        &ProgressReporter::default(),
+    )
+}
+
+/// Fetches the DICOM files of a study for several configurations, reporting the progress of the downloads, see
+/// [crate::get_many_with_progress()].
+pub fn get_many_with_progress(
+    url: &str,
+    key: &str,
+    study_instance_uid: &str,
+    milvue_params: &[MilvueParams],
+    progress: &ProgressReporter,
+) -> Result<MultiGetResponse, MilvueError> {
+    MilvueClient::with_api_key(url, key)?.get_many(study_instance_uid, milvue_params, progress)
+}
+
+/// Fetches the status of a study in the default environment.
+pub fn get_study_status(
+    key: &str,
+    study_instance_uid: &str,
+) -> Result<StatusResponse, MilvueError> {
+    get_study_status_with_url(
+        &MilvueUrl::default().get_url_from_envar()?,
+        key,
+        study_instance_uid,
+    )
+}
+
+/// Fetches the status of a study in the specified environment.
+pub fn get_study_status_with_url(
+    url: &str,
+    key: &str,
+    study_instance_uid: &str,
+) -> Result<StatusResponse, MilvueError> {
+    MilvueClient::with_api_key(url, key)?.get_study_status(study_instance_uid)
+}
+
+/// Waits for a study to be done in the default environment, see [crate::wait_for_done()].
+pub fn wait_for_done(key: &str, study_instance_uid: &str) -> Result<(), MilvueError> {
+    wait_for_done_with_url(
+        &MilvueUrl::default().get_url_from_envar()?,
+        key,
+        study_instance_uid,
+    )
+}
+
+/// Waits for a study to be done in the specified environment, see [crate::wait_for_done_with_url()].
+pub fn wait_for_done_with_url(
+    url: &str,
+    key: &str,
+    study_instance_uid: &str,
+) -> Result<(), MilvueError> {
+    wait_for_done_with_progress(url, key, study_instance_uid, &ProgressReporter::default())
+}
+
+/// Waits for a study to be done, reporting every status received, see [crate::wait_for_done_with_progress()].
+pub fn wait_for_done_with_progress(
+    url: &str,
+    key: &str,
+    study_instance_uid: &str,
+    progress: &ProgressReporter,
+) -> Result<(), MilvueError> {
+    MilvueClient::with_api_key(url, key)?.wait_for_done(study_instance_uid, progress)
+}
+
+/// Runtime shared by the blocking clients and functions.
+static RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// Returns the runtime driving the requests of the blocking calls, built on the first call.
+fn runtime() -> Result<Arc<Runtime>, MilvueError> {
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime.clone());
+    }
+    let runtime = Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(MilvueError::RuntimeError)?;
+    // a runtime built concurrently by another thread wins, this one is dropped
+    Ok(RUNTIME.get_or_init(|| Arc::new(runtime)).clone())
+}
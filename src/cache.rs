@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, info};
+
+use crate::{structs::MilvueError, MilvueParams};
+
+/// Name of the cache file in the user cache directory.
+pub const CACHE_FILE_NAME: &str = "submissions.json";
+
+/// Local record of the studies already submitted, used to avoid uploading (and billing) the same study twice.
+///
+/// Submissions are identified by a key computed by [submission_key()] from the StudyInstanceUID, the content of its
+/// SOP instances and the [MilvueParams] requested, so a study is submitted again if any of them changes.
+///
+/// The cache is a JSON file, read by [SubmissionCache::open()] and written back by [SubmissionCache::save()].
+#[derive(Debug, Default)]
+pub struct SubmissionCache {
+    path: PathBuf,
+    entries: HashMap<String, CacheEntry>,
+}
+
+/// Represents a previous submission recorded in a [SubmissionCache].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    #[serde(rename = "StudyInstanceUID")]
+    pub study_instance_uid: String,
+    /// URL of the environment the study was submitted to.
+    pub url: String,
+    /// Time of the submission, in seconds since the Unix epoch.
+    pub submitted_at: u64,
+    /// Paths of the results downloaded for the submission.
+    #[serde(default)]
+    pub outputs: Vec<PathBuf>,
+}
+
+impl CacheEntry {
+    /// Creates an entry for a submission made now.
+    pub fn new(study_instance_uid: &str, url: &str, outputs: Vec<PathBuf>) -> Self {
+        CacheEntry {
+            study_instance_uid: study_instance_uid.to_string(),
+            url: url.to_string(),
+            submitted_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+            outputs,
+        }
+    }
+
+    /// Returns true if every result recorded for the submission still exists on disk.
+    pub fn outputs_exist(&self) -> bool {
+        !self.outputs.is_empty() && self.outputs.iter().all(|output| output.is_file())
+    }
+}
+
+impl SubmissionCache {
+    /// Opens the cache stored at `path`, an empty cache is returned if the file doesn't exist yet.
+    pub fn open(path: &Path) -> Result<Self, MilvueError> {
+        let entries = match fs::read(path) {
+            Ok(content) => serde_json::from_slice(&content)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                debug!("No submission cache at {}", path.display());
+                HashMap::new()
+            }
+            Err(e) => return Err(MilvueError::Io(path.to_path_buf(), e)),
+        };
+        Ok(SubmissionCache {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// Default location of the cache.
+    ///
+    /// `$XDG_CACHE_HOME/milvue_rs/submissions.json`, falling back to `$HOME/.cache/milvue_rs/submissions.json` on
+    /// Unix, and `%LOCALAPPDATA%\milvue_rs\submissions.json` on Windows.
+    pub fn default_path() -> Option<PathBuf> {
+        let cache_dir = if cfg!(windows) {
+            env::var_os("LOCALAPPDATA").map(PathBuf::from)
+        } else {
+            env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        };
+        cache_dir.map(|dir| dir.join("milvue_rs").join(CACHE_FILE_NAME))
+    }
+
+    /// Path of the file backing the cache.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the previous submission with this key, if any.
+    pub fn get(&self, key: &str) -> Option<&CacheEntry> {
+        self.entries.get(key)
+    }
+
+    /// Records a submission, replacing any previous one with the same key.
+    pub fn insert(&mut self, key: String, entry: CacheEntry) {
+        self.entries.insert(key, entry);
+    }
+
+    /// Forgets a submission.
+    pub fn remove(&mut self, key: &str) -> Option<CacheEntry> {
+        self.entries.remove(key)
+    }
+
+    /// Writes the cache back to its file, creating its directory if needed.
+    ///
+    /// The file is replaced atomically so that a crash never leaves a truncated cache.
+    pub fn save(&self) -> Result<(), MilvueError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| MilvueError::Io(dir.to_path_buf(), e))?;
+        }
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(&self.entries)?)
+            .map_err(|e| MilvueError::Io(tmp_path.clone(), e))?;
+        fs::rename(&tmp_path, &self.path).map_err(|e| MilvueError::Io(self.path.clone(), e))?;
+        info!("Submission cache saved to {}", self.path.display());
+        Ok(())
+    }
+}
+
+/// Computes the SHA-256 of a file, as a lowercase hexadecimal string.
+pub fn hash_file(path: &Path) -> Result<String, MilvueError> {
+    let mut file = fs::File::open(path).map_err(|e| MilvueError::Io(path.to_path_buf(), e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .map_err(|e| MilvueError::Io(path.to_path_buf(), e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Computes the key identifying a submission in a [SubmissionCache].
+///
+/// # Arguments
+///
+/// * `study_instance_uid` - The StudyInstanceUID of the study.
+/// * `instances` - The (SOPInstanceUID, content hash) of every file of the study, in any order. See [hash_file()].
+/// * `milvue_params` - The configurations requested for the study, in any order.
+///
+/// # Returns
+///
+/// * A Result wrapping the key, a SHA-256 as a lowercase hexadecimal string.
+pub fn submission_key(
+    study_instance_uid: &str,
+    instances: &[(String, String)],
+    milvue_params: &[MilvueParams],
+) -> Result<String, MilvueError> {
+    let mut instances = instances.to_vec();
+    instances.sort();
+    let mut params = milvue_params
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<Result<Vec<_>, _>>()?;
+    params.sort();
+
+    let mut hasher = Sha256::new();
+    hasher.update(study_instance_uid.as_bytes());
+    for (sop_instance_uid, content_hash) in instances {
+        hasher.update(b"\n");
+        hasher.update(sop_instance_uid.as_bytes());
+        hasher.update(b":");
+        hasher.update(content_hash.as_bytes());
+    }
+    for params in params {
+        hasher.update(b"\n");
+        hasher.update(params.as_bytes());
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
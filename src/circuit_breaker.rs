@@ -0,0 +1,170 @@
+use serde::Deserialize;
+use std::{sync::Mutex, time::Duration};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::client::deserialize_seconds;
+
+/// Default number of consecutive failures opening the circuit.
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// Default time the circuit stays open before a probe.
+pub const DEFAULT_OPEN_DURATION: Duration = Duration::from_secs(30);
+
+/// Time the other requests are rejected for while a probe is in flight.
+const PROBE_WAIT: Duration = Duration::from_secs(1);
+
+/// Represents the settings of a [CircuitBreaker]. Unset values use [DEFAULT_FAILURE_THRESHOLD] and
+/// [DEFAULT_OPEN_DURATION]; in a configuration profile, durations are in seconds:
+///
+/// ```toml
+/// [profiles.prod.circuit_breaker]
+/// failure_threshold = 5
+/// open_duration = 30
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CircuitBreakerSettings {
+    /// Consecutive failures, connection errors or 5xx responses, opening the circuit.
+    pub failure_threshold: Option<u32>,
+    /// Time the circuit stays open before a probe is sent.
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub open_duration: Option<Duration>,
+}
+
+/// State of a [CircuitBreaker].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are sent.
+    Closed,
+    /// Requests are rejected with [crate::MilvueError::CircuitOpen].
+    Open,
+    /// A probe is in flight, the other requests are rejected.
+    HalfOpen,
+}
+
+/// Decision of a [CircuitBreaker] about a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Permit {
+    /// The request can be sent.
+    Allowed,
+    /// The circuit is due for a probe, which the caller must send before its request.
+    Probe,
+    /// The request is rejected, the circuit is open for the given time at least.
+    Rejected(Duration),
+}
+
+/// Stops the requests to an environment failing repeatedly, shared by the clones of a [crate::MilvueClient].
+///
+/// After `failure_threshold` consecutive connection errors or 5xx responses, the circuit opens: requests fail at once
+/// with [crate::MilvueError::CircuitOpen] for `open_duration`. The next request then sends a probe, a status request
+/// of an unknown study; the circuit closes if the API answers, and opens again otherwise.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: CircuitState,
+    failures: u32,
+    /// End of the open period, or start of the probe when half-open.
+    since: Instant,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed circuit breaker.
+    pub fn new(settings: &CircuitBreakerSettings) -> CircuitBreaker {
+        CircuitBreaker {
+            failure_threshold: settings
+                .failure_threshold
+                .unwrap_or(DEFAULT_FAILURE_THRESHOLD)
+                .max(1),
+            open_duration: settings.open_duration.unwrap_or(DEFAULT_OPEN_DURATION),
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                failures: 0,
+                since: Instant::now(),
+            }),
+        }
+    }
+
+    /// Current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        self.lock().state
+    }
+
+    /// Returns false while the circuit is open and not yet due for a probe, or while a probe is in flight.
+    pub fn allows_requests(&self) -> bool {
+        let inner = self.lock();
+        let now = Instant::now();
+        match inner.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => now >= inner.since,
+            CircuitState::HalfOpen => now >= inner.since + self.open_duration,
+        }
+    }
+
+    /// Decides whether a request can be sent.
+    pub(crate) fn check(&self) -> Permit {
+        let mut inner = self.lock();
+        let now = Instant::now();
+        match inner.state {
+            CircuitState::Closed => Permit::Allowed,
+            CircuitState::Open if now < inner.since => Permit::Rejected(inner.since - now),
+            // a probe cancelled before its response is replaced after the open duration
+            CircuitState::HalfOpen if now < inner.since + self.open_duration => {
+                Permit::Rejected(PROBE_WAIT)
+            }
+            CircuitState::Open | CircuitState::HalfOpen => {
+                inner.state = CircuitState::HalfOpen;
+                inner.since = now;
+                Permit::Probe
+            }
+        }
+    }
+
+    /// Records a request answered by the API, closing the circuit if it was half-open. A response received while
+    /// the circuit is open comes from a request sent before it opened, and is ignored.
+    pub fn record_success(&self) {
+        let mut inner = self.lock();
+        match inner.state {
+            CircuitState::Open => return,
+            CircuitState::HalfOpen => info!("Circuit closed, the API answers again"),
+            CircuitState::Closed => {}
+        }
+        inner.state = CircuitState::Closed;
+        inner.failures = 0;
+    }
+
+    /// Records a connection error or a 5xx response, opening the circuit after `failure_threshold` consecutive ones
+    /// or after a failed probe.
+    pub fn record_failure(&self) {
+        let mut inner = self.lock();
+        inner.failures = inner.failures.saturating_add(1);
+        let open = match inner.state {
+            CircuitState::Closed => inner.failures >= self.failure_threshold,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if open {
+            warn!(
+                "Circuit opened after {} consecutive failures, requests are rejected for {:?}",
+                inner.failures, self.open_duration
+            );
+            inner.state = CircuitState::Open;
+            inner.since = Instant::now() + self.open_duration;
+        }
+    }
+
+    /// Time the circuit stays open before a probe.
+    pub fn open_duration(&self) -> Duration {
+        self.open_duration
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().expect("circuit breaker lock poisoned")
+    }
+}
@@ -0,0 +1,731 @@
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use reqwest::{Certificate, Identity};
+use reqwest::{Client, NoProxy, Proxy, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Deserializer};
+use std::{fmt, future::Future, path::PathBuf, sync::Arc, time::Duration};
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use std::{fs, path::Path};
+use tracing::{debug, info, warn};
+
+#[cfg(feature = "audit")]
+use crate::audit::{AuditGuard, AuditLog, AuditOperation, AuditedTransfer};
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+#[cfg(feature = "audit")]
+use crate::InferenceCommand;
+use crate::{
+    auth::{Authenticator, StaticKey},
+    circuit_breaker::{CircuitBreaker, CircuitBreakerSettings, Permit},
+    rate_limit::{retry_after, RateLimiter, RateLimits},
+    structs::MilvueError,
+    MilvueUrl,
+};
+
+/// Number of times a request rejected with 429 Too Many Requests is sent again.
+pub(crate) const MAX_RATE_LIMITED_RETRIES: u32 = 3;
+
+/// StudyInstanceUID of the status request probing an environment, unknown to the API.
+pub(crate) const PROBE_STUDY_INSTANCE_UID: &str = "2.25.0";
+
+/// Client of the Milvue API, holding the URL of an environment, its credentials and a pool of HTTP connections.
+///
+/// The free functions such as [crate::upload_with_url()] or [crate::get_with_url()] build a client with a
+/// [StaticKey] on every call; a client is cheap to clone and should be reused to share connections. Example:
+///
+/// ```ignore rust no_run
+/// use milvue_rs::{auth::KeyFile, MilvueClient};
+///
+/// let client = MilvueClient::builder()
+///     .url("https://api.milvue.com")
+///     .authenticator(KeyFile::new("/run/secrets/milvue_api_key"))
+///     .build()?;
+/// client.wait_for_done(study_instance_uid, &Default::default()).await?;
+/// ```
+#[derive(Clone)]
+pub struct MilvueClient {
+    url: String,
+    authenticator: Arc<dyn Authenticator>,
+    http: Client,
+    timeouts: Timeouts,
+    rate_limiter: Arc<RateLimiter>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<Metrics>>,
+    #[cfg(feature = "audit")]
+    audit_log: Option<Arc<AuditLog>>,
+}
+
+impl fmt::Debug for MilvueClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MilvueClient")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MilvueClient {
+    /// Creates a client for the environment at `url`.
+    pub fn new(
+        url: &str,
+        authenticator: impl Authenticator + 'static,
+    ) -> Result<MilvueClient, MilvueError> {
+        MilvueClient::builder()
+            .url(url)
+            .authenticator(authenticator)
+            .build()
+    }
+
+    /// Creates a client for the environment at `url` with a static API key.
+    pub fn with_api_key(url: &str, key: &str) -> Result<MilvueClient, MilvueError> {
+        MilvueClient::new(url, StaticKey::new(key))
+    }
+
+    /// Starts building a client.
+    pub fn builder() -> MilvueClientBuilder {
+        MilvueClientBuilder::default()
+    }
+
+    /// URL of the environment.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Waits for the rate limit of `operation`, then adds the credentials to a request and sends it.
+    ///
+    /// With a circuit breaker, the request fails at once with [MilvueError::CircuitOpen] while the circuit is open,
+    /// or is preceded by a probe when the circuit is due for one.
+    ///
+    /// If the API answers 429 Too Many Requests, the operation is slowed down as described in [RateLimiter] and the
+    /// request is sent again, up to 3 times. If it answers 401 Unauthorized, the cached credentials are discarded and
+    /// the request is sent again once with fresh ones. Requests whose body is a stream cannot be replayed, their
+    /// response is returned as is. The responses are counted in the metrics of the client under `operation`. With the
+    /// `opentelemetry` feature, the request carries the W3C trace context of the current span.
+    pub(crate) async fn send(
+        &self,
+        operation: &'static str,
+        request: RequestBuilder,
+    ) -> Result<Response, MilvueError> {
+        self.rate_limiter.acquire(operation).await;
+        self.send_acquired(operation, request).await
+    }
+
+    /// Sends a request once the rate limit of `operation` allowed it, as described in [MilvueClient::send()].
+    pub(crate) async fn send_acquired(
+        &self,
+        operation: &'static str,
+        request: RequestBuilder,
+    ) -> Result<Response, MilvueError> {
+        #[cfg(feature = "opentelemetry")]
+        let request = request.headers(trace_context());
+        let mut request = request;
+        self.check_circuit().await?;
+        let mut rate_limited = 0;
+        loop {
+            let retry = request.try_clone();
+            let response = self.send_authenticated(operation, request).await?;
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                self.rate_limiter.recover(operation);
+                return Ok(response);
+            }
+            let pause = self
+                .rate_limiter
+                .throttle(operation, retry_after(&response));
+            match retry {
+                Some(retry) if rate_limited < MAX_RATE_LIMITED_RETRIES => {
+                    warn!(
+                        "Rate limited by the API, retrying the {} request in {:?}",
+                        operation, pause
+                    );
+                    #[cfg(feature = "metrics")]
+                    if let Some(metrics) = &self.metrics {
+                        metrics.retry(operation, "rate_limited");
+                    }
+                    rate_limited += 1;
+                    request = retry;
+                    self.rate_limiter.acquire(operation).await;
+                }
+                _ => {
+                    warn!(
+                        "Rate limited by the API, pausing the {} requests for {:?}",
+                        operation, pause
+                    );
+                    return Ok(response);
+                }
+            }
+        }
+    }
+
+    /// Adds the credentials to a request and sends it, once more with fresh credentials if the API answers 401
+    /// Unauthorized and the request can be replayed.
+    async fn send_authenticated(
+        &self,
+        operation: &'static str,
+        request: RequestBuilder,
+    ) -> Result<Response, MilvueError> {
+        let retry = request.try_clone();
+        let response = self
+            .record(
+                operation,
+                request.headers(self.authenticator.headers().await?).send(),
+            )
+            .await?;
+        match (response.status(), retry) {
+            (StatusCode::UNAUTHORIZED, Some(retry)) => {
+                warn!("Credentials rejected by the API, retrying with fresh ones");
+                self.authenticator.invalidate();
+                #[cfg(feature = "metrics")]
+                if let Some(metrics) = &self.metrics {
+                    metrics.retry(operation, "unauthorized");
+                }
+                Ok(self
+                    .record(
+                        operation,
+                        retry.headers(self.authenticator.headers().await?).send(),
+                    )
+                    .await?)
+            }
+            (StatusCode::UNAUTHORIZED, None) => {
+                self.authenticator.invalidate();
+                Ok(response)
+            }
+            _ => Ok(response),
+        }
+    }
+
+    /// Fails with [MilvueError::CircuitOpen] if the circuit breaker rejects the requests, after sending a probe if
+    /// the circuit is due for one.
+    async fn check_circuit(&self) -> Result<(), MilvueError> {
+        let Some(circuit_breaker) = &self.circuit_breaker else {
+            return Ok(());
+        };
+        match circuit_breaker.check() {
+            Permit::Allowed => Ok(()),
+            Permit::Rejected(retry_in) => Err(MilvueError::CircuitOpen(self.url.clone(), retry_in)),
+            Permit::Probe => {
+                info!("Probing {} before closing the circuit", self.url);
+                let probe = self.http.get(format!(
+                    "{}/v3/studies/{}/status",
+                    self.url, PROBE_STUDY_INSTANCE_UID
+                ));
+                match self.authenticator.headers().await {
+                    // the outcome of the probe is recorded in the circuit breaker, whatever the status
+                    Ok(headers) => {
+                        let _ = self.record("probe", probe.headers(headers).send()).await;
+                    }
+                    Err(e) => {
+                        warn!("Could not authenticate the probe of {}: {}", self.url, e);
+                        circuit_breaker.record_failure();
+                    }
+                }
+                match circuit_breaker.check() {
+                    Permit::Allowed => Ok(()),
+                    _ => Err(MilvueError::CircuitOpen(
+                        self.url.clone(),
+                        circuit_breaker.open_duration(),
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Counts the status code of a response, or `error` if no response was received, and records in the circuit
+    /// breaker whether the API answered.
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    async fn record(
+        &self,
+        operation: &'static str,
+        response: impl Future<Output = Result<Response, reqwest::Error>>,
+    ) -> Result<Response, reqwest::Error> {
+        let response = response.await;
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            match &response {
+                Ok(response) if !response.status().is_server_error() => {
+                    circuit_breaker.record_success()
+                }
+                _ => circuit_breaker.record_failure(),
+            }
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            match &response {
+                Ok(response) => metrics.response(operation, response.status().as_str()),
+                Err(_) => metrics.response(operation, "error"),
+            }
+        }
+        response
+    }
+
+    pub(crate) fn http(&self) -> &Client {
+        &self.http
+    }
+
+    /// Headers authenticating a request, without sending it.
+    pub(crate) async fn credentials(&self) -> Result<reqwest::header::HeaderMap, MilvueError> {
+        self.authenticator.headers().await
+    }
+
+    /// Metrics updated by the requests of the client, if set with [MilvueClientBuilder::metrics()].
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> Option<&Arc<Metrics>> {
+        self.metrics.as_ref()
+    }
+
+    /// Audit log recording the uploads and downloads of the client, if set with [MilvueClientBuilder::audit_log()].
+    #[cfg(feature = "audit")]
+    pub fn audit_log(&self) -> Option<&Arc<AuditLog>> {
+        self.audit_log.as_ref()
+    }
+
+    /// Starts recording a transfer in the audit log, if any.
+    #[cfg(feature = "audit")]
+    pub(crate) fn audit(
+        &self,
+        operation: AuditOperation,
+        study_instance_uid: Option<String>,
+        inference_command: Option<InferenceCommand>,
+        instances: usize,
+    ) -> Option<AuditGuard<'_>> {
+        self.audit_log.as_deref().map(|log| {
+            AuditGuard::new(
+                log,
+                AuditedTransfer {
+                    url: self.url.clone(),
+                    operation,
+                    study_instance_uid,
+                    inference_command,
+                    instances,
+                    bytes: 0,
+                    error: None,
+                },
+            )
+        })
+    }
+
+    /// Timeouts and deadlines of the client.
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    /// Rate limiter of the requests, shared by the clones of the client.
+    pub fn rate_limiter(&self) -> &Arc<RateLimiter> {
+        &self.rate_limiter
+    }
+
+    /// Circuit breaker of the requests, shared by the clones of the client, if set with
+    /// [MilvueClientBuilder::circuit_breaker()].
+    pub fn circuit_breaker(&self) -> Option<&Arc<CircuitBreaker>> {
+        self.circuit_breaker.as_ref()
+    }
+}
+
+/// Builds the `traceparent` and `tracestate` headers of the current span with the global propagator of
+/// OpenTelemetry, which the application sets to a `TraceContextPropagator`.
+#[cfg(feature = "opentelemetry")]
+fn trace_context() -> reqwest::header::HeaderMap {
+    use opentelemetry::propagation::Injector;
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    struct Headers(HeaderMap);
+
+    impl Injector for Headers {
+        fn set(&mut self, key: &str, value: String) {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(key.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                self.0.insert(name, value);
+            }
+        }
+    }
+
+    let context = tracing::Span::current().context();
+    let mut headers = Headers(HeaderMap::new());
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut headers)
+    });
+    headers.0
+}
+
+/// Default time allowed to establish a connection.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Represents the timeouts of the requests, and the deadlines of the phases of a study.
+///
+/// Unset values mean no limit, except `connect` which defaults to [DEFAULT_CONNECT_TIMEOUT]. In a configuration
+/// profile, durations are in seconds:
+///
+/// ```toml
+/// [profiles.prod.timeouts]
+/// connect = 10
+/// request = 300
+/// upload = 600
+/// processing = 900
+/// download = 300
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Timeouts {
+    /// Time allowed to establish a connection.
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub connect: Option<Duration>,
+    /// Time allowed for a single request, from the connection to the end of the response body.
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub request: Option<Duration>,
+    /// Deadline of the upload of a study, see [MilvueError::UploadTimeout]. It starts once the rate limit allows
+    /// the upload, and again for every upload sent after a 429 Too Many Requests.
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub upload: Option<Duration>,
+    /// Deadline of the processing of a study by Milvue, see [MilvueError::ProcessingTimeout], including the waits
+    /// for the rate limit of the status polls.
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub processing: Option<Duration>,
+    /// Deadline of the download of the results of a study, see [MilvueError::DownloadTimeout], including the waits
+    /// for the rate limit of the downloads.
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub download: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Merges other timeouts on top of these, values set in `other` win.
+    pub fn merge(&mut self, other: Timeouts) {
+        if other.connect.is_some() {
+            self.connect = other.connect;
+        }
+        if other.request.is_some() {
+            self.request = other.request;
+        }
+        if other.upload.is_some() {
+            self.upload = other.upload;
+        }
+        if other.processing.is_some() {
+            self.processing = other.processing;
+        }
+        if other.download.is_some() {
+            self.download = other.download;
+        }
+    }
+}
+
+/// Reads a duration given in seconds, possibly fractional.
+pub(crate) fn deserialize_seconds<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<f64>::deserialize(deserializer)?
+        .map(|seconds| Duration::try_from_secs_f64(seconds).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Runs a phase of a study, failing with `error` if it lasts longer than `deadline`.
+pub(crate) async fn with_deadline<T>(
+    deadline: Option<Duration>,
+    error: fn(Duration) -> MilvueError,
+    phase: impl Future<Output = Result<T, MilvueError>>,
+) -> Result<T, MilvueError> {
+    match deadline {
+        Some(deadline) => match tokio::time::timeout(deadline, phase).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("Deadline of {:?} exceeded", deadline);
+                Err(error(deadline))
+            }
+        },
+        None => phase.await,
+    }
+}
+
+/// Represents the TLS settings of a [MilvueClient], e.g. for a hospital network with a private CA. Example in a
+/// configuration profile:
+///
+/// ```toml
+/// [profiles.prod.tls]
+/// root_certificates = ["/etc/pki/hospital-ca.pem"]
+/// client_certificate = "/etc/pki/gateway.pem"
+/// client_key = "/etc/pki/gateway.key"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    /// PEM files of additional root certificates, each file may hold several certificates.
+    #[serde(default)]
+    pub root_certificates: Vec<PathBuf>,
+    /// Trust only `root_certificates`, not the system or built-in ones.
+    #[serde(default)]
+    pub only_custom_roots: bool,
+    /// PEM file of the client certificate chain, for mutual TLS.
+    pub client_certificate: Option<PathBuf>,
+    /// PEM file of the PKCS#8 private key of the client certificate, which may also be in `client_certificate`.
+    pub client_key: Option<PathBuf>,
+}
+
+/// Represents the HTTP proxy of a [MilvueClient].
+///
+/// Without explicit settings, the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables are used.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct ProxySettings {
+    /// URL of the proxy, e.g. `http://proxy.hospital.local:3128`.
+    pub url: String,
+    /// User name, if the proxy requires basic authentication.
+    pub username: Option<String>,
+    /// Password of `username`, redacted from the debug output.
+    pub password: Option<String>,
+    /// Hosts reached without the proxy, e.g. `localhost`, `.hospital.local` or `10.0.0.0/8`.
+    pub no_proxy: Vec<String>,
+}
+
+impl fmt::Debug for ProxySettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxySettings")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("no_proxy", &self.no_proxy)
+            .finish()
+    }
+}
+
+/// Builds a [MilvueClient].
+#[derive(Default)]
+pub struct MilvueClientBuilder {
+    url: Option<String>,
+    environment: Option<MilvueUrl>,
+    authenticator: Option<Arc<dyn Authenticator>>,
+    tls: Option<TlsSettings>,
+    proxy: Option<ProxySettings>,
+    timeouts: Timeouts,
+    rate_limits: RateLimits,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    circuit_breaker: Option<CircuitBreakerSettings>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<Metrics>>,
+    #[cfg(feature = "audit")]
+    audit_log: Option<Arc<AuditLog>>,
+}
+
+impl MilvueClientBuilder {
+    /// Sets the URL of the environment.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_string());
+        self
+    }
+
+    /// Sets the environment, its URL is read from its environment variable when building, see [MilvueUrl].
+    pub fn environment(mut self, environment: MilvueUrl) -> Self {
+        self.environment = Some(environment);
+        self
+    }
+
+    /// Sets the credentials of the requests.
+    pub fn authenticator(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
+    /// Sets credentials shared with other clients.
+    pub fn shared_authenticator(mut self, authenticator: Arc<dyn Authenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// Uses a static API key, see [StaticKey].
+    pub fn api_key(self, key: &str) -> Self {
+        self.authenticator(StaticKey::new(key))
+    }
+
+    /// Sets the TLS settings.
+    pub fn tls(mut self, tls: TlsSettings) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Sends the requests through a proxy.
+    pub fn proxy(mut self, proxy: ProxySettings) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Sets the timeouts of the requests and the deadlines of the phases of a study.
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Limits the rate of the requests of each operation, unlimited by default.
+    pub fn rate_limits(mut self, rate_limits: RateLimits) -> Self {
+        self.rate_limits = rate_limits;
+        self
+    }
+
+    /// Sets a rate limiter shared with other clients, e.g. reaching the same environment, instead of the one built
+    /// from [MilvueClientBuilder::rate_limits()].
+    pub fn shared_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Stops the requests while the environment fails repeatedly, see [CircuitBreaker].
+    pub fn circuit_breaker(mut self, settings: CircuitBreakerSettings) -> Self {
+        self.circuit_breaker = Some(settings);
+        self
+    }
+
+    /// Records the responses, retries, transferred bytes and processing times of the requests in `metrics`, which
+    /// may be shared with other clients.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Records every upload and download in `audit_log`, which may be shared with other clients.
+    #[cfg(feature = "audit")]
+    pub fn audit_log(mut self, audit_log: Arc<AuditLog>) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    /// Builds an HTTP client with the TLS and proxy settings, e.g. for an
+    /// [crate::auth::OAuth2ClientCredentials] reaching its token endpoint through the same proxy.
+    ///
+    /// The TLS implementation is rustls if the `rustls` feature is enabled, native-tls otherwise.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the HTTP client, or an error if a certificate or key cannot be read or parsed, or if the
+    ///   proxy URL is invalid.
+    pub fn http_client(&self) -> Result<Client, MilvueError> {
+        let mut builder = Client::builder()
+            .connect_timeout(self.timeouts.connect.unwrap_or(DEFAULT_CONNECT_TIMEOUT));
+        if let Some(request) = self.timeouts.request {
+            builder = builder.timeout(request);
+        }
+        #[cfg(feature = "rustls")]
+        {
+            builder = builder.use_rustls_tls();
+        }
+
+        #[cfg(any(feature = "native-tls", feature = "rustls"))]
+        if let Some(tls) = &self.tls {
+            for path in &tls.root_certificates {
+                for certificate in read_pem_certificates(path)? {
+                    builder = builder.add_root_certificate(certificate);
+                }
+                info!("Trusting the root certificates of {}", path.display());
+            }
+            if tls.only_custom_roots {
+                builder = builder.tls_built_in_root_certs(false);
+            }
+            match (&tls.client_certificate, &tls.client_key) {
+                (Some(certificate), key) => {
+                    builder = builder.identity(read_identity(certificate, key.as_deref())?);
+                    info!("Using client certificate {}", certificate.display());
+                }
+                (None, Some(_)) => {
+                    return Err(MilvueError::InvalidValue(
+                        "TLS settings",
+                        "client_key without client_certificate".to_string(),
+                    ))
+                }
+                (None, None) => {}
+            }
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let mut reqwest_proxy = Proxy::all(&proxy.url)?;
+            if let Some(username) = &proxy.username {
+                reqwest_proxy =
+                    reqwest_proxy.basic_auth(username, proxy.password.as_deref().unwrap_or(""));
+            }
+            if !proxy.no_proxy.is_empty() {
+                reqwest_proxy =
+                    reqwest_proxy.no_proxy(NoProxy::from_string(&proxy.no_proxy.join(",")));
+            }
+            builder = builder.proxy(reqwest_proxy);
+            info!("Sending requests through the proxy {}", proxy.url);
+        }
+
+        Ok(builder.build()?)
+    }
+
+    /// Builds the client.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the client, or [MilvueError::NoApiUrl] if neither the URL nor the environment is set,
+    ///   [MilvueError::NoApiKey] if no authenticator is set, [MilvueError::InvalidValue] if a rate limit is invalid,
+    ///   or an error if the HTTP client cannot be created.
+    pub fn build(self) -> Result<MilvueClient, MilvueError> {
+        let http = self.http_client()?;
+        let url = match (self.url, self.environment) {
+            (Some(url), _) => url,
+            (None, Some(environment)) => environment.get_url_from_envar()?,
+            (None, None) => return Err(MilvueError::NoApiUrl),
+        };
+        let authenticator = self.authenticator.ok_or(MilvueError::NoApiKey)?;
+        let rate_limiter = match self.rate_limiter {
+            Some(rate_limiter) => rate_limiter,
+            None => Arc::new(RateLimiter::new(&self.rate_limits)?),
+        };
+        debug!("Building client for {}", url);
+
+        Ok(MilvueClient {
+            url: url.trim_end_matches('/').to_string(),
+            authenticator,
+            http,
+            timeouts: self.timeouts,
+            rate_limiter,
+            circuit_breaker: self
+                .circuit_breaker
+                .map(|settings| Arc::new(CircuitBreaker::new(&settings))),
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
+            #[cfg(feature = "audit")]
+            audit_log: self.audit_log,
+        })
+    }
+}
+
+/// Reads every certificate of a PEM file.
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+fn read_pem_certificates(path: &Path) -> Result<Vec<Certificate>, MilvueError> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let pem = fs::read_to_string(path).map_err(|e| MilvueError::Io(path.to_path_buf(), e))?;
+    let certificates = pem
+        .split_inclusive(END)
+        .filter(|block| block.contains(END))
+        .map(|block| Certificate::from_pem(block.as_bytes()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if certificates.is_empty() {
+        return Err(MilvueError::InvalidValue(
+            "PEM certificate file",
+            path.display().to_string(),
+        ));
+    }
+    Ok(certificates)
+}
+
+/// Reads a client certificate chain and its private key, which may be in the same file.
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+fn read_identity(certificate: &Path, key: Option<&Path>) -> Result<Identity, MilvueError> {
+    let certificate_pem =
+        fs::read(certificate).map_err(|e| MilvueError::Io(certificate.to_path_buf(), e))?;
+    let key_pem = match key {
+        Some(key) => fs::read(key).map_err(|e| MilvueError::Io(key.to_path_buf(), e))?,
+        None => certificate_pem.clone(),
+    };
+
+    #[cfg(feature = "rustls")]
+    let identity = {
+        let mut pem = certificate_pem;
+        if key.is_some() {
+            pem.push(b'\n');
+            pem.extend_from_slice(&key_pem);
+        }
+        Identity::from_pem(&pem)?
+    };
+    #[cfg(not(feature = "rustls"))]
+    let identity = Identity::from_pkcs8_pem(&certificate_pem, &key_pem)?;
+
+    Ok(identity)
+}
@@ -0,0 +1,672 @@
+#[cfg(feature = "cli")]
+use dicom_object::InMemDicomObject;
+use reqwest::Client;
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::{debug, info};
+
+#[cfg(feature = "audit")]
+use crate::audit::AuditSettings;
+#[cfg(feature = "transcode")]
+use crate::TranscodePolicy;
+use crate::{
+    auth::{AuthScheme, Authenticator, EnvKey, KeyFile, OAuth2ClientCredentials, StaticKey},
+    structs::MilvueError,
+    CircuitBreakerSettings, InferenceCommand, Language, MilvueClient, MilvueClientBuilder,
+    MilvueParams, MilvueUrl, OutputFormat, OutputSelection, ProxySettings, RateLimits, RecapTheme,
+    StaticReportFormat, StructuredReportFormat, Timeouts, TlsSettings,
+};
+
+/// Name of the configuration file looked up in the system and user configuration directories.
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Name of the profile used when none is requested and the configuration doesn't set `default_profile`.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Represents the content of one or more `milvue_rs` TOML configuration files.
+///
+/// A configuration is a set of named [Profile]s. Example:
+///
+/// ```toml
+/// default_profile = "prod"
+///
+/// [profiles.prod]
+/// environment = "prod"
+/// api_key = { env = "MILVUE_API_KEY_PROD" }
+/// output_template = "/data/results/{PatientID}/{StudyInstanceUID}"
+/// concurrency = 4
+/// sinks = ["/mnt/archive/{StudyInstanceUID}"]
+///
+/// [profiles.prod.params]
+/// inference_commands = ["smarturgences", "smartxpert"]
+/// language = "fr"
+/// output_format = "gsps"
+/// ```
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Profile used when none is explicitly requested.
+    pub default_profile: Option<String>,
+    /// Profiles indexed by name.
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+impl Config {
+    /// Loads and merges the system, user and explicit configuration files, in that order.
+    ///
+    /// Missing system and user files are ignored, a missing explicit file is an error. Profiles defined in several
+    /// files are merged field by field, the last file to set a field wins.
+    ///
+    /// # Arguments
+    ///
+    /// * `explicit` - An optional path to a configuration file, typically provided with `--config`.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the merged Config, or an error if a file cannot be read or parsed.
+    pub fn load(explicit: Option<&Path>) -> Result<Config, MilvueError> {
+        let mut config = Config::default();
+
+        for path in [system_config_path(), user_config_path()]
+            .into_iter()
+            .flatten()
+        {
+            if path.is_file() {
+                config.merge(Config::from_file(&path)?);
+            } else {
+                debug!("No configuration file at {}", path.display());
+            }
+        }
+
+        if let Some(path) = explicit {
+            config.merge(Config::from_file(path)?);
+        }
+
+        Ok(config)
+    }
+
+    /// Reads a single configuration file.
+    pub fn from_file(path: &Path) -> Result<Config, MilvueError> {
+        info!("Loading configuration file {}", path.display());
+        let content = fs::read_to_string(path)
+            .map_err(|e| MilvueError::ConfigReadError(path.to_path_buf(), e))?;
+        Config::from_toml(&content)
+    }
+
+    /// Parses a configuration from a TOML string.
+    pub fn from_toml(content: &str) -> Result<Config, MilvueError> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Merges another configuration on top of this one.
+    pub fn merge(&mut self, other: Config) {
+        if other.default_profile.is_some() {
+            self.default_profile = other.default_profile;
+        }
+        for (name, profile) in other.profiles {
+            match self.profiles.remove(&name) {
+                Some(mut existing) => {
+                    existing.merge(profile);
+                    self.profiles.insert(name, existing);
+                }
+                None => {
+                    self.profiles.insert(name, profile);
+                }
+            }
+        }
+    }
+
+    /// Selects a profile by name.
+    ///
+    /// When `name` is None, the `default_profile` is used, then the profile named [DEFAULT_PROFILE] if it exists,
+    /// and finally an empty profile.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping a copy of the profile, or an error if an explicitly requested profile doesn't exist.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile, MilvueError> {
+        match name.or(self.default_profile.as_deref()) {
+            Some(name) => self
+                .profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| MilvueError::ProfileNotFound(name.to_string())),
+            None => Ok(self
+                .profiles
+                .get(DEFAULT_PROFILE)
+                .cloned()
+                .unwrap_or_default()),
+        }
+    }
+}
+
+/// Represents a named set of settings for the `milvue_rs` binary.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Milvue environment, resolved from its environment variable as described in [MilvueUrl].
+    pub environment: Option<MilvueUrl>,
+    /// Explicit API URL, takes precedence over `environment`.
+    pub url: Option<String>,
+    /// Where to read the API key from, takes precedence over `oauth2`.
+    pub api_key: Option<ApiKeySource>,
+    /// How the API key is sent, in the Milvue header by default.
+    pub auth_scheme: Option<AuthScheme>,
+    /// OAuth2 client credentials used to obtain bearer tokens instead of an API key.
+    pub oauth2: Option<OAuth2Settings>,
+    /// Private root certificates and client certificate.
+    pub tls: Option<TlsSettings>,
+    /// HTTP proxy, the `HTTPS_PROXY` environment variable is used if unset.
+    pub proxy: Option<ProxyConfig>,
+    /// Timeouts of the requests and deadlines of the phases of a study.
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    /// Rates of the submissions, status polls and downloads, unlimited if unset.
+    #[serde(default)]
+    pub rate_limits: RateLimits,
+    /// Circuit breaker stopping the requests while the environment fails, none if unset.
+    pub circuit_breaker: Option<CircuitBreakerSettings>,
+    /// Profiles of the environments new studies are submitted to while this one fails, in order of preference.
+    #[serde(default)]
+    pub failover: Vec<String>,
+    /// Default request parameters.
+    #[serde(default)]
+    pub params: ProfileParams,
+    /// Output directory, may contain DICOM attribute names between braces, e.g. `results/{StudyInstanceUID}`.
+    pub output_template: Option<String>,
+    /// Maximum number of studies uploaded at the same time, at least 1.
+    #[serde(default, deserialize_with = "deserialize_concurrency")]
+    pub concurrency: Option<usize>,
+    /// Additional output directories the results are copied to, using the same syntax as `output_template`.
+    #[serde(default)]
+    pub sinks: Vec<String>,
+    /// Transfer syntaxes the DICOM files are converted to before the upload, untouched if unset.
+    #[cfg(feature = "transcode")]
+    pub transcode_uploads: Option<TranscodePolicy>,
+    /// Transfer syntaxes the results are converted to before being saved, untouched if unset.
+    #[cfg(feature = "transcode")]
+    pub transcode_results: Option<TranscodePolicy>,
+    /// Audit log recording the uploads and downloads, none if unset.
+    #[cfg(feature = "audit")]
+    pub audit: Option<AuditSettings>,
+    /// Rules giving a priority to the studies, the highest matching priority is used.
+    #[cfg(feature = "cli")]
+    #[serde(default)]
+    pub priorities: Vec<PriorityRule>,
+}
+
+impl Profile {
+    /// Merges another profile on top of this one, fields set in `other` win.
+    pub fn merge(&mut self, other: Profile) {
+        if other.environment.is_some() {
+            self.environment = other.environment;
+        }
+        if other.url.is_some() {
+            self.url = other.url;
+        }
+        if other.api_key.is_some() {
+            self.api_key = other.api_key;
+        }
+        if other.auth_scheme.is_some() {
+            self.auth_scheme = other.auth_scheme;
+        }
+        if other.oauth2.is_some() {
+            self.oauth2 = other.oauth2;
+        }
+        if other.tls.is_some() {
+            self.tls = other.tls;
+        }
+        if other.proxy.is_some() {
+            self.proxy = other.proxy;
+        }
+        self.timeouts.merge(other.timeouts);
+        self.rate_limits.merge(other.rate_limits);
+        if other.circuit_breaker.is_some() {
+            self.circuit_breaker = other.circuit_breaker;
+        }
+        if !other.failover.is_empty() {
+            self.failover = other.failover;
+        }
+        self.params.merge(other.params);
+        if other.output_template.is_some() {
+            self.output_template = other.output_template;
+        }
+        if other.concurrency.is_some() {
+            self.concurrency = other.concurrency;
+        }
+        if !other.sinks.is_empty() {
+            self.sinks = other.sinks;
+        }
+        #[cfg(feature = "transcode")]
+        if other.transcode_uploads.is_some() {
+            self.transcode_uploads = other.transcode_uploads;
+        }
+        #[cfg(feature = "transcode")]
+        if other.transcode_results.is_some() {
+            self.transcode_results = other.transcode_results;
+        }
+        #[cfg(feature = "audit")]
+        if other.audit.is_some() {
+            self.audit = other.audit;
+        }
+        #[cfg(feature = "cli")]
+        if !other.priorities.is_empty() {
+            self.priorities = other.priorities;
+        }
+    }
+
+    /// Resolves the API URL of the profile.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the URL, the explicit `url` if set, otherwise the one of `environment`, or
+    ///   [MilvueError::NoApiUrl] if neither is set.
+    pub fn resolve_url(&self) -> Result<String, MilvueError> {
+        match (&self.url, &self.environment) {
+            (Some(url), _) => Ok(url.clone()),
+            (None, Some(environment)) => environment.get_url_from_envar(),
+            (None, None) => Err(MilvueError::NoApiUrl),
+        }
+    }
+
+    /// Resolves the API key of the profile.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the key, or [MilvueError::NoApiKey] if no source is set.
+    pub fn resolve_api_key(&self) -> Result<String, MilvueError> {
+        match &self.api_key {
+            Some(source) => source.resolve(),
+            None => Err(MilvueError::NoApiKey),
+        }
+    }
+
+    /// Builds a [MilvueClient] with the URL, credentials, TLS, proxy, timeout, rate limit, circuit breaker and audit
+    /// settings of the profile.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the client, or an error if the URL or the credentials are missing, if the TLS, proxy or
+    ///   rate limit settings are invalid, or if the audit log cannot be opened.
+    pub fn client(&self) -> Result<MilvueClient, MilvueError> {
+        self.client_builder()?.build()
+    }
+
+    /// Prepares a [MilvueClient] with the settings of the profile, to be completed before building it, e.g. with
+    /// metrics.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the builder, or an error if the URL or the credentials are missing, if the TLS or proxy
+    ///   settings are invalid, or if the audit log cannot be opened.
+    pub fn client_builder(&self) -> Result<MilvueClientBuilder, MilvueError> {
+        let mut builder = MilvueClient::builder()
+            .url(&self.resolve_url()?)
+            .timeouts(self.timeouts.clone())
+            .rate_limits(self.rate_limits.clone());
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            builder = builder.circuit_breaker(circuit_breaker.clone());
+        }
+        if let Some(tls) = &self.tls {
+            builder = builder.tls(tls.clone());
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.resolve()?);
+        }
+        #[cfg(feature = "audit")]
+        if let Some(audit) = &self.audit {
+            builder = builder.audit_log(Arc::new(audit.open()?));
+        }
+        let authenticator = self.authenticator(&builder.http_client()?)?;
+        Ok(builder.shared_authenticator(authenticator))
+    }
+
+    /// Builds the [Authenticator] of the profile.
+    ///
+    /// Keys read from a file or an environment variable are read again before every request, so that they can be
+    /// rotated.
+    ///
+    /// # Arguments
+    ///
+    /// * `http` - The HTTP client requesting the OAuth2 tokens.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the authenticator, or [MilvueError::NoApiKey] if neither `api_key` nor `oauth2` is set.
+    pub fn authenticator(&self, http: &Client) -> Result<Arc<dyn Authenticator>, MilvueError> {
+        let scheme = self.auth_scheme.clone().unwrap_or_default();
+        match (&self.api_key, &self.oauth2) {
+            (Some(ApiKeySource::Value(key)), _) => {
+                Ok(Arc::new(StaticKey::new(key).with_scheme(scheme)))
+            }
+            (Some(ApiKeySource::File(path)), _) => {
+                Ok(Arc::new(KeyFile::new(path).with_scheme(scheme)))
+            }
+            (Some(ApiKeySource::Env(var)), _) => Ok(Arc::new(EnvKey::new(var).with_scheme(scheme))),
+            (None, Some(oauth2)) => Ok(Arc::new(
+                OAuth2ClientCredentials::new(
+                    &oauth2.token_url,
+                    &oauth2.client_id,
+                    &oauth2.client_secret.resolve()?,
+                )
+                .with_scopes(oauth2.scopes.clone())
+                .with_http_client(http.clone()),
+            )),
+            (None, None) => Err(MilvueError::NoApiKey),
+        }
+    }
+}
+
+/// Represents the HTTP proxy of a profile. Example:
+///
+/// ```toml
+/// [profiles.prod.proxy]
+/// url = "http://proxy.hospital.local:3128"
+/// username = "milvue"
+/// password = { env = "PROXY_PASSWORD" }
+/// no_proxy = ["localhost", ".hospital.local"]
+/// ```
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// URL of the proxy, e.g. `http://proxy.hospital.local:3128`.
+    pub url: String,
+    /// User name, if the proxy requires basic authentication.
+    pub username: Option<String>,
+    /// Where to read the password from.
+    pub password: Option<ApiKeySource>,
+    /// Hosts reached without the proxy.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("no_proxy", &self.no_proxy)
+            .finish()
+    }
+}
+
+impl ProxyConfig {
+    /// Reads the password and builds the [ProxySettings] of a client.
+    pub fn resolve(&self) -> Result<ProxySettings, MilvueError> {
+        Ok(ProxySettings {
+            url: self.url.clone(),
+            username: self.username.clone(),
+            password: self
+                .password
+                .as_ref()
+                .map(ApiKeySource::resolve)
+                .transpose()?,
+            no_proxy: self.no_proxy.clone(),
+        })
+    }
+}
+
+/// Represents the OAuth2 client credentials of a profile. Example:
+///
+/// ```toml
+/// [profiles.prod.oauth2]
+/// token_url = "https://auth.example.com/oauth2/token"
+/// client_id = "milvue-gateway"
+/// client_secret = { file = "/run/secrets/milvue_client_secret" }
+/// scopes = ["studies"]
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OAuth2Settings {
+    /// URL of the token endpoint.
+    pub token_url: String,
+    /// Client identifier registered with the token endpoint.
+    pub client_id: String,
+    /// Where to read the client secret from.
+    pub client_secret: ApiKeySource,
+    /// Scopes requested, none by default.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// Represents a rule giving a priority to the studies whose files match all its conditions, used by the command
+/// line tools. Example:
+///
+/// ```toml
+/// [[profiles.prod.priorities]]
+/// attribute = "RequestedProcedurePriority"
+/// values = ["STAT", "HIGH"]
+/// priority = 10
+///
+/// [[profiles.prod.priorities]]
+/// path = "/srv/exports/followups"
+/// priority = -5
+/// ```
+#[cfg(feature = "cli")]
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PriorityRule {
+    /// Priority of the matching studies, the highest are processed first.
+    pub priority: i32,
+    /// Name of a DICOM attribute, e.g. `RequestedProcedurePriority` or `StudyDescription`.
+    pub attribute: Option<String>,
+    /// Values of `attribute` matching the rule, compared without case, any value if empty.
+    #[serde(default)]
+    pub values: Vec<String>,
+    /// Directory containing the files of the matching studies.
+    pub path: Option<PathBuf>,
+}
+
+#[cfg(feature = "cli")]
+impl PriorityRule {
+    /// Computes the priority of a DICOM file with a list of rules.
+    ///
+    /// # Arguments
+    ///
+    /// * `rules` - The priority rules, e.g. the `priorities` of a profile.
+    /// * `path` - The path of the file.
+    /// * `object` - The DICOM object read from the file, at least its attributes before the pixel data.
+    ///
+    /// # Returns
+    ///
+    /// * The highest priority of the matching rules, 0 if none matches.
+    pub fn highest(rules: &[PriorityRule], path: &Path, object: &InMemDicomObject) -> i32 {
+        rules
+            .iter()
+            .filter(|rule| rule.matches(path, object))
+            .map(|rule| rule.priority)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns true if a DICOM file matches the conditions of the rule, a rule without condition matches every file.
+    pub fn matches(&self, path: &Path, object: &InMemDicomObject) -> bool {
+        if let Some(attribute) = &self.attribute {
+            let value = match object.element_by_name(attribute) {
+                Ok(element) => match element.to_str() {
+                    Ok(value) => value.trim_end_matches([char::from(0), ' ']).to_string(),
+                    Err(_) => return false,
+                },
+                Err(_) => return false,
+            };
+            if !self.values.is_empty()
+                && !self
+                    .values
+                    .iter()
+                    .any(|expected| expected.eq_ignore_ascii_case(&value))
+            {
+                return false;
+            }
+        }
+        if let Some(directory) = &self.path {
+            let canonical =
+                |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+            if !canonical(path).starts_with(canonical(directory)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Represents the source of the API key.
+///
+/// In TOML, exactly one of `api_key = { value = "..." }`, `api_key = { file = "..." }` or
+/// `api_key = { env = "..." }`.
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeySource {
+    /// The key itself. Prefer the other sources to avoid storing the key in clear text.
+    Value(String),
+    /// Path of a file containing the key, surrounding whitespace is ignored.
+    File(PathBuf),
+    /// Name of an environment variable containing the key.
+    Env(String),
+}
+
+impl fmt::Debug for ApiKeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiKeySource::Value(_) => f.debug_tuple("Value").field(&"<redacted>").finish(),
+            ApiKeySource::File(path) => f.debug_tuple("File").field(path).finish(),
+            ApiKeySource::Env(var) => f.debug_tuple("Env").field(var).finish(),
+        }
+    }
+}
+
+impl ApiKeySource {
+    /// Reads the key from its source.
+    pub fn resolve(&self) -> Result<String, MilvueError> {
+        match self {
+            ApiKeySource::Value(key) => Ok(key.clone()),
+            ApiKeySource::File(path) => fs::read_to_string(path)
+                .map(|key| key.trim().to_string())
+                .map_err(|e| MilvueError::ConfigReadError(path.clone(), e)),
+            ApiKeySource::Env(var) => {
+                env::var(var).map_err(|_| MilvueError::EnvVarNotFound(var.clone()))
+            }
+        }
+    }
+}
+
+/// Represents the default [MilvueParams] of a profile.
+///
+/// Every field is optional so that profiles can be merged and overridden by command line arguments.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileParams {
+    /// Inference commands to run, one request is made per command.
+    #[serde(default)]
+    pub inference_commands: Vec<InferenceCommand>,
+    pub signed_url: Option<bool>,
+    pub output_format: Option<OutputFormat>,
+    pub language: Option<Language>,
+    pub timezone: Option<String>,
+    pub output_selection: Option<OutputSelection>,
+    pub recap_theme: Option<RecapTheme>,
+    pub structured_report_format: Option<StructuredReportFormat>,
+    pub static_report_format: Option<StaticReportFormat>,
+}
+
+impl ProfileParams {
+    /// Merges other parameters on top of these, fields set in `other` win.
+    pub fn merge(&mut self, other: ProfileParams) {
+        if !other.inference_commands.is_empty() {
+            self.inference_commands = other.inference_commands;
+        }
+        if other.signed_url.is_some() {
+            self.signed_url = other.signed_url;
+        }
+        if other.output_format.is_some() {
+            self.output_format = other.output_format;
+        }
+        if other.language.is_some() {
+            self.language = other.language;
+        }
+        if other.timezone.is_some() {
+            self.timezone = other.timezone;
+        }
+        if other.output_selection.is_some() {
+            self.output_selection = other.output_selection;
+        }
+        if other.recap_theme.is_some() {
+            self.recap_theme = other.recap_theme;
+        }
+        if other.structured_report_format.is_some() {
+            self.structured_report_format = other.structured_report_format;
+        }
+        if other.static_report_format.is_some() {
+            self.static_report_format = other.static_report_format;
+        }
+    }
+
+    /// Builds one [MilvueParams] per inference command.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the list of parameters, or [MilvueError::NoInferenceCommand] if no inference command is set.
+    pub fn to_params(&self) -> Result<Vec<MilvueParams>, MilvueError> {
+        if self.inference_commands.is_empty() {
+            return Err(MilvueError::NoInferenceCommand);
+        }
+        Ok(self
+            .inference_commands
+            .iter()
+            .map(|inference_command| MilvueParams {
+                signed_url: self.signed_url,
+                output_format: self.output_format.clone(),
+                language: self.language.clone(),
+                inference_command: inference_command.clone(),
+                timezone: self.timezone.clone(),
+                output_selection: self.output_selection.clone(),
+                recap_theme: self.recap_theme.clone(),
+                structured_report_format: self.structured_report_format.clone(),
+                static_report_format: self.static_report_format.clone(),
+            })
+            .collect())
+    }
+}
+
+/// Deserializes a concurrency, rejecting 0 which would never let a study start.
+fn deserialize_concurrency<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<usize>, D::Error> {
+    match Option::<usize>::deserialize(deserializer)? {
+        Some(0) => Err(D::Error::custom("concurrency must be at least 1")),
+        concurrency => Ok(concurrency),
+    }
+}
+
+/// Path of the system wide configuration file.
+///
+/// `/etc/milvue_rs/config.toml` on Unix, `%PROGRAMDATA%\milvue_rs\config.toml` on Windows.
+pub fn system_config_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("PROGRAMDATA")
+            .map(|dir| PathBuf::from(dir).join("milvue_rs").join(CONFIG_FILE_NAME))
+    } else {
+        Some(PathBuf::from("/etc/milvue_rs").join(CONFIG_FILE_NAME))
+    }
+}
+
+/// Path of the user configuration file.
+///
+/// `$XDG_CONFIG_HOME/milvue_rs/config.toml`, falling back to `$HOME/.config/milvue_rs/config.toml` on Unix, and
+/// `%APPDATA%\milvue_rs\config.toml` on Windows.
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    config_dir.map(|dir| dir.join("milvue_rs").join(CONFIG_FILE_NAME))
+}
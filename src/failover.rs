@@ -0,0 +1,309 @@
+use dicom_object::{FileDicomObject, InMemDicomObject};
+use futures_util::future::BoxFuture;
+use reqwest::StatusCode;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tracing::{info, warn};
+
+use crate::{
+    DicomSource, MilvueApi, MilvueClient, MilvueError, MilvueParams, MultiGetResponse,
+    ProgressReporter, StatusResponse,
+};
+
+/// Client of several Milvue environments in order of preference, e.g. a primary region and a backup.
+///
+/// New studies are submitted to the first environment whose [crate::CircuitBreaker] allows requests, so each client
+/// should be built with [crate::MilvueClientBuilder::circuit_breaker()]; clients without one are always deemed
+/// healthy. A study is then pinned to the environment it was submitted to, which answers its status polls and
+/// downloads. Example:
+///
+/// ```ignore rust no_run
+/// use milvue_rs::{CircuitBreakerSettings, FailoverClient, MilvueClient};
+///
+/// let endpoint = |url: &str, key: &str| {
+///     MilvueClient::builder()
+///         .url(url)
+///         .api_key(key)
+///         .circuit_breaker(CircuitBreakerSettings::default())
+///         .build()
+/// };
+/// let client = FailoverClient::new(vec![
+///     endpoint("https://eu.api.milvue.com", primary_key)?,
+///     endpoint("https://us.api.milvue.com", backup_key)?,
+/// ])?;
+/// client.upload(study_instance_uid, sources, &Default::default()).await?;
+/// client.wait_for_done(study_instance_uid, &Default::default()).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct FailoverClient {
+    endpoints: Arc<Vec<MilvueClient>>,
+    /// Index of the environment each study was submitted to.
+    pins: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl FailoverClient {
+    /// Creates a client of environments given in order of preference.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the client, or [MilvueError::InvalidValue] if `endpoints` is empty.
+    pub fn new(endpoints: Vec<MilvueClient>) -> Result<FailoverClient, MilvueError> {
+        if endpoints.is_empty() {
+            return Err(MilvueError::InvalidValue(
+                "failover endpoints",
+                "none".to_string(),
+            ));
+        }
+        Ok(FailoverClient {
+            endpoints: Arc::new(endpoints),
+            pins: Default::default(),
+        })
+    }
+
+    /// Clients of the environments, in order of preference.
+    pub fn endpoints(&self) -> &[MilvueClient] {
+        &self.endpoints
+    }
+
+    /// Client of the environment new studies are submitted to: the first whose circuit breaker allows requests, or
+    /// the first one if none does, so that its [MilvueError::CircuitOpen] is reported.
+    pub fn submission_client(&self) -> &MilvueClient {
+        self.endpoints
+            .iter()
+            .find(|client| allows_requests(client))
+            .unwrap_or(&self.endpoints[0])
+    }
+
+    /// Client of the environment a study was submitted to, the first one if the study isn't pinned.
+    pub fn client_for(&self, study_instance_uid: &str) -> &MilvueClient {
+        let index = self
+            .pins
+            .lock()
+            .expect("pins lock poisoned")
+            .get(study_instance_uid)
+            .copied()
+            .unwrap_or(0);
+        &self.endpoints[index]
+    }
+
+    /// Pins a study to the environment at `url`, e.g. to resume a study submitted before a restart.
+    ///
+    /// # Returns
+    ///
+    /// * False if no environment has this URL, the study is then left unpinned.
+    pub fn pin(&self, study_instance_uid: &str, url: &str) -> bool {
+        let url = url.trim_end_matches('/');
+        match self.endpoints.iter().position(|client| client.url() == url) {
+            Some(index) => {
+                self.pins
+                    .lock()
+                    .expect("pins lock poisoned")
+                    .insert(study_instance_uid.to_string(), index);
+                true
+            }
+            None => {
+                warn!(
+                    "Study {} was submitted to {}, which isn't configured anymore",
+                    study_instance_uid, url
+                );
+                false
+            }
+        }
+    }
+
+    /// Unpins a study whose results were downloaded.
+    pub fn unpin(&self, study_instance_uid: &str) {
+        self.pins
+            .lock()
+            .expect("pins lock poisoned")
+            .remove(study_instance_uid);
+    }
+
+    /// Uploads the DICOM files of a study to the first healthy environment and pins the study to it, see
+    /// [MilvueClient::upload()].
+    ///
+    /// If the environment can't be reached or answers 502 Bad Gateway or 503 Service Unavailable, the files are
+    /// uploaded to the next environment whose circuit breaker allows requests. The files are then kept until the upload succeeds, in
+    /// memory for [DicomSource::Object]; a [DicomSource::Reader] can only be read once, so an upload with a reader
+    /// fails with the error of the first environment.
+    ///
+    /// # Arguments
+    ///
+    /// * `study_instance_uid` - The StudyInstanceUID of the files.
+    /// * `sources` - The DICOM files to be uploaded.
+    /// * `progress` - The [ProgressReporter] receiving the progress events.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the response of the environment, or the error of the last upload.
+    pub async fn upload(
+        &self,
+        study_instance_uid: &str,
+        sources: Vec<DicomSource>,
+        progress: &ProgressReporter,
+    ) -> Result<reqwest::Response, MilvueError> {
+        let mut candidates = self
+            .endpoints
+            .iter()
+            .filter(|client| allows_requests(client))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .peekable();
+        let mut client = candidates.next().unwrap_or(&self.endpoints[0]);
+        let mut sources = sources;
+        loop {
+            if client.url() != self.endpoints[0].url() {
+                info!(
+                    "Submitting study {} to the failover environment {}",
+                    study_instance_uid,
+                    client.url()
+                );
+            }
+            let replay = match candidates.peek() {
+                Some(_) => sources.iter().map(DicomSource::try_clone).collect(),
+                None => None,
+            };
+            match client.upload(sources, progress).await {
+                Ok(response) => {
+                    self.pin(study_instance_uid, client.url());
+                    return Ok(response);
+                }
+                Err(e) if is_unavailable(&e) => match (replay, candidates.next()) {
+                    (Some(replay), Some(next)) => {
+                        warn!(
+                            "Error while submitting study {} to {}, trying {}: {}",
+                            study_instance_uid,
+                            client.url(),
+                            next.url(),
+                            e
+                        );
+                        sources = replay;
+                        client = next;
+                    }
+                    _ => return Err(e),
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the status of a study from the environment it was submitted to, see
+    /// [MilvueClient::get_study_status()].
+    pub async fn get_study_status(
+        &self,
+        study_instance_uid: &str,
+    ) -> Result<reqwest::Response, MilvueError> {
+        self.client_for(study_instance_uid)
+            .get_study_status(study_instance_uid)
+            .await
+    }
+
+    /// Waits for a study to be done in the environment it was submitted to, see [MilvueClient::wait_for_done()].
+    pub async fn wait_for_done(
+        &self,
+        study_instance_uid: &str,
+        progress: &ProgressReporter,
+    ) -> Result<(), MilvueError> {
+        self.client_for(study_instance_uid)
+            .wait_for_done(study_instance_uid, progress)
+            .await
+    }
+
+    /// Fetches the results of a study from the environment it was submitted to, see [MilvueClient::get()].
+    pub async fn get(
+        &self,
+        study_instance_uid: &str,
+        milvue_params: &MilvueParams,
+        progress: &ProgressReporter,
+    ) -> Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError> {
+        self.client_for(study_instance_uid)
+            .get(study_instance_uid, milvue_params, progress)
+            .await
+    }
+
+    /// Fetches the results of a study for several configurations from the environment it was submitted to, see
+    /// [MilvueClient::get_many()].
+    pub async fn get_many(
+        &self,
+        study_instance_uid: &str,
+        milvue_params: &[MilvueParams],
+        progress: &ProgressReporter,
+    ) -> Result<MultiGetResponse, MilvueError> {
+        self.client_for(study_instance_uid)
+            .get_many(study_instance_uid, milvue_params, progress)
+            .await
+    }
+}
+
+/// Returns true if the circuit breaker of an environment, if any, allows requests.
+fn allows_requests(client: &MilvueClient) -> bool {
+    client
+        .circuit_breaker()
+        .map_or(true, |circuit_breaker| circuit_breaker.allows_requests())
+}
+
+/// Returns true if an error shows that an environment did not take the study, which another environment could
+/// accept: it could not be reached, its circuit is open, or it answered 502 Bad Gateway or 503 Service Unavailable.
+/// Other server errors may come after the study was received, submitting it again could process it twice.
+fn is_unavailable(e: &MilvueError) -> bool {
+    match e {
+        MilvueError::RequestError(e) => e.is_connect(),
+        MilvueError::StatusResponseError(response) => matches!(
+            response.status(),
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE
+        ),
+        MilvueError::CircuitOpen(_, _) => true,
+        _ => false,
+    }
+}
+
+impl MilvueApi for FailoverClient {
+    /// Submits the DICOM files of a study, whose StudyInstanceUID is read from the first source; fails with
+    /// [MilvueError::InvalidValue] if it cannot be read, as the study could not be routed afterwards.
+    fn submit<'a>(
+        &'a self,
+        sources: Vec<DicomSource>,
+        progress: &'a ProgressReporter,
+    ) -> BoxFuture<'a, Result<(), MilvueError>> {
+        Box::pin(async move {
+            let study_instance_uid = sources
+                .first()
+                .and_then(DicomSource::study_instance_uid)
+                .ok_or_else(|| {
+                    MilvueError::InvalidValue(
+                        "StudyInstanceUID of the submitted sources",
+                        "unreadable".to_string(),
+                    )
+                })?;
+            self.upload(&study_instance_uid, sources, progress).await?;
+            Ok(())
+        })
+    }
+
+    fn status<'a>(
+        &'a self,
+        study_instance_uid: &'a str,
+    ) -> BoxFuture<'a, Result<StatusResponse, MilvueError>> {
+        self.client_for(study_instance_uid)
+            .status(study_instance_uid)
+    }
+
+    fn wait<'a>(
+        &'a self,
+        study_instance_uid: &'a str,
+        progress: &'a ProgressReporter,
+    ) -> BoxFuture<'a, Result<(), MilvueError>> {
+        Box::pin(self.wait_for_done(study_instance_uid, progress))
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        study_instance_uid: &'a str,
+        milvue_params: &'a MilvueParams,
+        progress: &'a ProgressReporter,
+    ) -> BoxFuture<'a, Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError>> {
+        Box::pin(self.get(study_instance_uid, milvue_params, progress))
+    }
+}
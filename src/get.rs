@@ -1,10 +1,20 @@
+use bytes::{Bytes, BytesMut};
 use dicom_object::{file::ReadPreamble, FileDicomObject, InMemDicomObject, OpenFileOptions};
+use futures_util::future::join_all;
 use multer::Multipart;
-use reqwest::{header, Client};
-use std::io::Cursor;
-use tracing::{debug, error, info, warn};
+use reqwest::header;
+use std::{io::Cursor, sync::Arc, time::Instant};
+use tracing::{debug, error, info, instrument, warn};
 
-use crate::{structs::MilvueError, MilvueParams, MilvueUrl, StatusResponse};
+#[cfg(feature = "audit")]
+use crate::audit::AuditOperation;
+use crate::{
+    client::with_deadline,
+    progress::{StatusProgress, Transfer},
+    structs::MilvueError,
+    MilvueClient, MilvueParams, MilvueUrl, MultiGetResponse, Progress, ProgressReporter,
+    StatusResponse,
+};
 
 /// Fetches DICOM files from a study in the default environment.
 ///
@@ -17,8 +27,8 @@ use crate::{structs::MilvueError, MilvueParams, MilvueUrl, StatusResponse};
 /// # Returns
 ///
 /// * An option containing a vector of DICOM files or None, in the case of a None, this means that there is no output for the given
-/// configuration. For example, if you request a SmartXpert inference on a skull X-ray, there will be no output since SmartXpert
-/// doesn't support skull X-rays.
+///   configuration. For example, if you request a SmartXpert inference on a skull X-ray, there will be no output since SmartXpert
+///   doesn't support skull X-rays.
 pub async fn get(
     key: &str,
     study_instance_uid: &str,
@@ -45,63 +55,456 @@ pub async fn get(
 /// # Returns
 ///
 /// * An Option containing a vector of DICOM files or None, in the case of a None, this means that there is no output
-/// for the given configuration. For example, if you request a SmartXpert inference on a skull X-ray, there will be no
-/// output since SmartXpert doesn't support skull X-rays.
+///   for the given configuration. For example, if you request a SmartXpert inference on a skull X-ray, there will be no
+///   output since SmartXpert doesn't support skull X-rays.
 pub async fn get_with_url(
     url: &str,
     key: &str,
     study_instance_uid: &str,
     milvue_params: &MilvueParams,
 ) -> Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError> {
-    info!("Preparing GET request for study {}", study_instance_uid);
+    get_with_progress(
+        url,
+        key,
+        study_instance_uid,
+        milvue_params,
+        &ProgressReporter::default(),
+    )
+    .await
+}
+
+/// Fetches DICOM files from a study in the specified environment, reporting the progress of the download.
+///
+/// Behaves like [get_with_url()], and reports [Progress::Download] events to `progress` as the bytes are received.
+///
+/// # Arguments
+///
+/// * `url` - A reference to MilvueUrl that specifies the environment
+/// * `key` - A string slice that holds the API key
+/// * `study_instance_uid` - A string slice that holds the ID of the study
+/// * `milvue_params` - A reference to MilvueParams containing parameters for the request
+/// * `progress` - The [ProgressReporter] receiving the progress events
+///
+/// # Returns
+///
+/// * An Option containing a vector of DICOM files or None, see [get_with_url()].
+pub async fn get_with_progress(
+    url: &str,
+    key: &str,
+    study_instance_uid: &str,
+    milvue_params: &MilvueParams,
+    progress: &ProgressReporter,
+) -> Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError> {
+    MilvueClient::with_api_key(url, key)?
+        .get(study_instance_uid, milvue_params, progress)
+        .await
+}
+
+/// Fetches the DICOM files of a study for several configurations in the default environment.
+///
+/// # Arguments
+///
+/// * `key` - A string slice that holds the API key
+/// * `study_instance_uid` - A string slice that holds the ID of the study
+/// * `milvue_params` - A list of MilvueParams, one request is made per configuration
+///
+/// # Returns
+///
+/// * A Result wrapping a [MultiGetResponse] holding the results and errors paired with their configuration, or an error if the
+///   requests could not be prepared.
+pub async fn get_many(
+    key: &str,
+    study_instance_uid: &str,
+    milvue_params: &[MilvueParams],
+) -> Result<MultiGetResponse, MilvueError> {
+    get_many_with_url(
+        &MilvueUrl::default().get_url_from_envar()?,
+        key,
+        study_instance_uid,
+        milvue_params,
+    )
+    .await
+}
+
+/// Fetches the DICOM files of a study for several configurations in the specified environment.
+///
+/// The requests are sent concurrently with a single HTTP client. For example, SmartUrgences results in French and in
+/// English and SmartXpert results as GSPS can be fetched in one call.
+///
+/// # Arguments
+///
+/// * `url` - A reference to MilvueUrl that specifies the environment
+/// * `key` - A string slice that holds the API key
+/// * `study_instance_uid` - A string slice that holds the ID of the study
+/// * `milvue_params` - A list of MilvueParams, one request is made per configuration
+///
+/// # Returns
+///
+/// * A Result wrapping a [MultiGetResponse] holding the results and errors paired with their configuration, or an error if the
+///   requests could not be prepared.
+pub async fn get_many_with_url(
+    url: &str,
+    key: &str,
+    study_instance_uid: &str,
+    milvue_params: &[MilvueParams],
+) -> Result<MultiGetResponse, MilvueError> {
+    get_many_with_progress(
+        url,
+        key,
+        study_instance_uid,
+        milvue_params,
+        &ProgressReporter::default(),
+    )
+    .await
+}
+
+/// Fetches the DICOM files of a study for several configurations, reporting the progress of the downloads.
+///
+/// Behaves like [get_many_with_url()], and reports [Progress::Download] events to `progress`, cumulated over all
+/// configurations.
+///
+/// # Arguments
+///
+/// * `url` - A reference to MilvueUrl that specifies the environment
+/// * `key` - A string slice that holds the API key
+/// * `study_instance_uid` - A string slice that holds the ID of the study
+/// * `milvue_params` - A list of MilvueParams, one request is made per configuration
+/// * `progress` - The [ProgressReporter] receiving the progress events
+///
+/// # Returns
+///
+/// * A Result wrapping a [MultiGetResponse], see [get_many_with_url()].
+pub async fn get_many_with_progress(
+    url: &str,
+    key: &str,
+    study_instance_uid: &str,
+    milvue_params: &[MilvueParams],
+    progress: &ProgressReporter,
+) -> Result<MultiGetResponse, MilvueError> {
+    MilvueClient::with_api_key(url, key)?
+        .get_many(study_instance_uid, milvue_params, progress)
+        .await
+}
 
-    let milvue_api_url = format!("{}/v3/studies/{}", url, study_instance_uid);
+impl MilvueClient {
+    /// Fetches the DICOM files of a study, reporting the progress of the download.
+    ///
+    /// # Arguments
+    ///
+    /// * `study_instance_uid` - A string slice that holds the ID of the study
+    /// * `milvue_params` - A reference to MilvueParams containing parameters for the request
+    /// * `progress` - The [ProgressReporter] receiving the progress events, `&Default::default()` to ignore them
+    ///
+    /// # Returns
+    ///
+    /// * An Option containing a vector of DICOM files or None, see [get_with_url()], or
+    ///   [MilvueError::DownloadTimeout] if the download deadline of the client is exceeded.
+    pub async fn get(
+        &self,
+        study_instance_uid: &str,
+        milvue_params: &MilvueParams,
+        progress: &ProgressReporter,
+    ) -> Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError> {
+        info!("Preparing GET request for study {}", study_instance_uid);
 
-    let mut headers = header::HeaderMap::new();
+        let transfer = Transfer::new(progress.clone(), Progress::Download, None);
+        with_deadline(
+            self.timeouts().download,
+            MilvueError::DownloadTimeout,
+            self.fetch_results(study_instance_uid, milvue_params, &transfer),
+        )
+        .await
+    }
 
-    let mut api_key = header::HeaderValue::from_str(key)?;
-    api_key.set_sensitive(true);
-    headers.insert("x-goog-meta-owner", api_key);
+    /// Fetches the DICOM files of a study for several configurations concurrently, reporting the progress of the
+    /// downloads cumulated over all configurations.
+    ///
+    /// # Arguments
+    ///
+    /// * `study_instance_uid` - A string slice that holds the ID of the study
+    /// * `milvue_params` - A list of MilvueParams, one request is made per configuration
+    /// * `progress` - The [ProgressReporter] receiving the progress events, `&Default::default()` to ignore them
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping a [MultiGetResponse], see [get_many_with_url()], or [MilvueError::DownloadTimeout] if the
+    ///   download deadline of the client is exceeded, the results already received are then dropped.
+    #[instrument(name = "get_many", skip_all, fields(study_instance_uid = %study_instance_uid, configurations = milvue_params.len()))]
+    pub async fn get_many(
+        &self,
+        study_instance_uid: &str,
+        milvue_params: &[MilvueParams],
+        progress: &ProgressReporter,
+    ) -> Result<MultiGetResponse, MilvueError> {
+        with_deadline(
+            self.timeouts().download,
+            MilvueError::DownloadTimeout,
+            self.fetch_many(study_instance_uid, milvue_params, progress),
+        )
+        .await
+    }
 
-    headers.insert("Accept", header::HeaderValue::from_str("application/json")?);
-    debug!("Headers: {:?}", headers);
+    async fn fetch_many(
+        &self,
+        study_instance_uid: &str,
+        milvue_params: &[MilvueParams],
+        progress: &ProgressReporter,
+    ) -> Result<MultiGetResponse, MilvueError> {
+        info!(
+            "Preparing {} GET requests for study {}",
+            milvue_params.len(),
+            study_instance_uid
+        );
 
-    let client = Client::builder().default_headers(headers).build()?;
+        let transfer = Transfer::new(progress.clone(), Progress::Download, None);
 
-    info!("Sending GET request to {}", milvue_api_url);
-    let response = client
-        .get(milvue_api_url)
-        .query(milvue_params.to_query_param().as_slice())
-        .send()
-        .await?;
+        let responses = join_all(milvue_params.iter().map(|params| {
+            let transfer = &transfer;
+            async move {
+                (
+                    params.clone(),
+                    self.fetch_results(study_instance_uid, params, transfer)
+                        .await,
+                )
+            }
+        }))
+        .await;
 
-    match response.status() {
-        reqwest::StatusCode::OK => info!("GET request successfully sent."),
-        status => {
-            error!("GET request failed with status code {}", status);
-            return Err(MilvueError::StatusResponseError(response));
+        let mut multi_response = MultiGetResponse::default();
+        for (params, response) in responses {
+            match response {
+                Ok(dicoms) => {
+                    multi_response.results.push((params, dicoms));
+                }
+                Err(err) => {
+                    warn!(
+                        "GET request for study {} with inference command {} failed: {}",
+                        study_instance_uid, params.inference_command, err
+                    );
+                    multi_response.errors.push((params, err));
+                }
+            }
         }
+
+        Ok(multi_response)
     }
 
-    let content_type = match response.headers().get(reqwest::header::CONTENT_TYPE) {
-        Some(content_type) => content_type.to_str()?,
-        None => return Err(MilvueError::NoContentType),
-    };
-    debug!("Content-Type: {}", content_type);
-
-    let boundary_parts = content_type.split("boundary=").collect::<Vec<_>>();
-    let boundary = match boundary_parts.len() {
-        2 => boundary_parts[1].to_string(),
-        _ => {
-            warn!("No boundary found in Content-Type header, it is likely that the study has no output for the given configuration (inference command, output_selection, etc.)");
-            return Ok(None);
+    /// Fetches the status of a study.
+    ///
+    /// # Arguments
+    ///
+    /// * `study_instance_uid` - A string slice that holds the ID of the study
+    ///
+    /// # Returns
+    ///
+    /// * A Result containing the response from the server, whose body is a [StatusResponse], or an error
+    #[instrument(name = "status", skip_all, fields(study_instance_uid = %study_instance_uid))]
+    pub async fn get_study_status(
+        &self,
+        study_instance_uid: &str,
+    ) -> Result<reqwest::Response, MilvueError> {
+        let milvue_api_url = format!("{}/v3/studies/{}", self.url(), study_instance_uid);
+
+        debug!(
+            "Fetching status of study {} from {}",
+            study_instance_uid,
+            self.url()
+        );
+        let response = self
+            .send(
+                "status",
+                self.http()
+                    .get(format!("{}/status", milvue_api_url))
+                    .header(header::ACCEPT, "application/json"),
+            )
+            .await?;
+
+        match response.status() {
+            reqwest::StatusCode::OK => debug!("GET request successfully sent."),
+            status => {
+                error!("GET request failed with status code {}", status);
+                return Err(MilvueError::StatusResponseError(response));
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Waits for a study to be done, reporting a [Progress::Status] event to `progress` after each poll.
+    ///
+    /// # Arguments
+    ///
+    /// * `study_instance_uid` - A string slice that holds the ID of the study
+    /// * `progress` - The [ProgressReporter] receiving the progress events, `&Default::default()` to ignore them
+    ///
+    /// # Returns
+    ///
+    /// * A Result indicating success (empty Ok value), [MilvueError::ProcessingTimeout] if the processing deadline
+    ///   of the client is exceeded, or another error
+    #[instrument(name = "wait_for_done", skip_all, fields(study_instance_uid = %study_instance_uid))]
+    pub async fn wait_for_done(
+        &self,
+        study_instance_uid: &str,
+        progress: &ProgressReporter,
+    ) -> Result<(), MilvueError> {
+        with_deadline(
+            self.timeouts().processing,
+            MilvueError::ProcessingTimeout,
+            self.poll_until_done(study_instance_uid, progress),
+        )
+        .await
+    }
+
+    async fn poll_until_done(
+        &self,
+        study_instance_uid: &str,
+        progress: &ProgressReporter,
+    ) -> Result<(), MilvueError> {
+        info!("Waiting for study {} to be done", study_instance_uid);
+
+        let start = Instant::now();
+        let mut polls = 0;
+        loop {
+            let status_response = self.get_study_status(study_instance_uid).await?;
+            let status_body: StatusResponse = status_response.json().await?;
+
+            polls += 1;
+            #[cfg(feature = "metrics")]
+            if let Some(metrics) = self.metrics() {
+                metrics.poll();
+                if status_body.status == "done" {
+                    metrics.processed(start.elapsed());
+                }
+            }
+            progress.report(Progress::Status(StatusProgress {
+                status: status_body.status.clone(),
+                polls,
+                elapsed: start.elapsed(),
+            }));
+
+            if status_body.status == "done" {
+                break;
+            }
+
+            // tokio sleep for 3 sec
+            tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+        }
+
+        Ok(())
+    }
+
+    /// Fetches and parses the DICOM files of a study for one configuration.
+    #[instrument(name = "download", skip_all, fields(study_instance_uid = %study_instance_uid, inference_command = %milvue_params.inference_command))]
+    async fn fetch_results(
+        &self,
+        study_instance_uid: &str,
+        milvue_params: &MilvueParams,
+        transfer: &Arc<Transfer>,
+    ) -> Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError> {
+        #[cfg(feature = "audit")]
+        let audit = self.audit(
+            AuditOperation::Download,
+            Some(study_instance_uid.to_string()),
+            Some(milvue_params.inference_command.clone()),
+            0,
+        );
+
+        let mut received = 0;
+        let result = self
+            .receive_results(study_instance_uid, milvue_params, transfer, &mut received)
+            .await;
+
+        #[cfg(feature = "audit")]
+        if let Some(mut audit) = audit {
+            audit.set_bytes(received);
+            if let Ok(Some(dicoms)) = &result {
+                audit.set_instances(dicoms.len());
+            }
+            audit.finish(&result);
+        }
+        result
+    }
+
+    /// Sends the request of the results and parses them, counting the bytes received in `received`.
+    async fn receive_results(
+        &self,
+        study_instance_uid: &str,
+        milvue_params: &MilvueParams,
+        transfer: &Arc<Transfer>,
+        received: &mut u64,
+    ) -> Result<Option<Vec<FileDicomObject<InMemDicomObject>>>, MilvueError> {
+        let milvue_api_url = format!("{}/v3/studies/{}", self.url(), study_instance_uid);
+
+        info!("Sending GET request to {}", milvue_api_url);
+        let start = Instant::now();
+        let response = self
+            .send(
+                "results",
+                self.http()
+                    .get(milvue_api_url)
+                    .header(header::ACCEPT, "application/json")
+                    .query(milvue_params.to_query_param().as_slice()),
+            )
+            .await?;
+
+        match response.status() {
+            reqwest::StatusCode::OK => info!("GET request successfully sent."),
+            status => {
+                error!("GET request failed with status code {}", status);
+                return Err(MilvueError::StatusResponseError(response));
+            }
+        }
+
+        let content_type = match response.headers().get(reqwest::header::CONTENT_TYPE) {
+            Some(content_type) => content_type.to_str()?,
+            None => return Err(MilvueError::NoContentType),
+        };
+        debug!("Content-Type: {}", content_type);
+
+        let boundary_parts = content_type.split("boundary=").collect::<Vec<_>>();
+        let boundary = match boundary_parts.len() {
+            2 => boundary_parts[1].to_string(),
+            _ => {
+                warn!("No boundary found in Content-Type header, it is likely that the study has no output for the given configuration (inference command, output_selection, etc.)");
+                return Ok(None);
+            }
+        };
+
+        transfer.add_total_bytes(response.content_length());
+        let mut response = response;
+        let mut body = BytesMut::new();
+        while let Some(chunk) = response.chunk().await? {
+            transfer.add_bytes(chunk.len() as u64);
+            body.extend_from_slice(&chunk);
+            *received += chunk.len() as u64;
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = self.metrics() {
+            metrics.downloaded(
+                &milvue_params.inference_command,
+                body.len() as u64,
+                start.elapsed(),
+            );
         }
-    };
+        let dicoms = parse_results(body.freeze(), boundary, transfer).await?;
+        info!(
+            "{} DICOM files successfully downloaded and parsed in {:?}",
+            dicoms.len(),
+            start.elapsed()
+        );
+        Ok(Some(dicoms))
+    }
+}
 
-    let body = response.bytes().await?;
-    let cursor = Cursor::new(body);
+/// Parses the DICOM files of a multipart response.
+#[instrument(name = "parse", skip_all, fields(bytes = body.len()))]
+async fn parse_results(
+    body: Bytes,
+    boundary: String,
+    transfer: &Transfer,
+) -> Result<Vec<FileDicomObject<InMemDicomObject>>, MilvueError> {
     info!("Parsing multipart response");
-    let mut multipart = Multipart::with_reader(cursor, boundary);
+    let mut multipart = Multipart::with_reader(Cursor::new(body), boundary);
 
     let mut dicoms = Vec::new();
     let mut dicom_count = 1;
@@ -115,6 +518,7 @@ pub async fn get_with_url(
         {
             Ok(dicom_file) => {
                 info!("DICOM file {} successfully parsed", dicom_count);
+                transfer.complete_file();
                 debug!(
                     "SOPInstanceUID: {}",
                     dicom_file.element_by_name("SOPInstanceUID")?.to_str()?
@@ -128,8 +532,7 @@ pub async fn get_with_url(
         }
         dicom_count += 1;
     }
-    info!("{} DICOM files successfully parsed", dicom_count - 1);
-    Ok(Some(dicoms))
+    Ok(dicoms)
 }
 
 /// Fetches the status of a study in the default environment.
@@ -170,36 +573,9 @@ pub async fn get_study_status_with_url(
     key: &str,
     study_instance_uid: &str,
 ) -> Result<reqwest::Response, MilvueError> {
-    let milvue_api_url = format!("{}/v3/studies/{}", url, study_instance_uid);
-
-    let mut headers = header::HeaderMap::new();
-
-    let mut api_header = header::HeaderValue::from_str(key)?;
-    api_header.set_sensitive(true);
-
-    headers.insert("x-goog-meta-owner", api_header);
-    headers.insert("Accept", header::HeaderValue::from_str("application/json")?);
-
-    let client = Client::builder().default_headers(headers).build()?;
-
-    debug!(
-        "Fetching status of study {} from {}",
-        study_instance_uid, url
-    );
-    let response = client
-        .get(format!("{}/status", milvue_api_url))
-        .send()
-        .await?;
-
-    match response.status() {
-        reqwest::StatusCode::OK => debug!("GET request successfully sent."),
-        status => {
-            error!("GET request failed with status code {}", status);
-            return Err(MilvueError::StatusResponseError(response));
-        }
-    }
-
-    Ok(response)
+    MilvueClient::with_api_key(url, key)?
+        .get_study_status(study_instance_uid)
+        .await
 }
 
 /// Waits for a study to be done in the default environment.
@@ -237,19 +613,30 @@ pub async fn wait_for_done_with_url(
     key: &str,
     study_instance_uid: &str,
 ) -> Result<(), MilvueError> {
-    info!("Waiting for study {} to be done", study_instance_uid);
-
-    loop {
-        let status_response = get_study_status_with_url(url, key, study_instance_uid).await?;
-        let status_body: StatusResponse = status_response.json().await?;
-
-        if status_body.status == "done" {
-            break;
-        }
-
-        // tokio sleep for 3 sec
-        tokio::time::sleep(std::time::Duration::from_secs(3)).await;
-    }
+    wait_for_done_with_progress(url, key, study_instance_uid, &ProgressReporter::default()).await
+}
 
-    Ok(())
+/// Waits for a study to be done in the specified environment, reporting every status received.
+///
+/// Behaves like [wait_for_done_with_url()], and reports a [Progress::Status] event to `progress` after each poll.
+///
+/// # Arguments
+///
+/// * `url` - A reference to MilvueUrl that specifies the environment
+/// * `key` - A string slice that holds the API key
+/// * `study_instance_uid` - A string slice that holds the ID of the study
+/// * `progress` - The [ProgressReporter] receiving the progress events
+///
+/// # Returns
+///
+/// * A Result indicating success (empty Ok value) or an error
+pub async fn wait_for_done_with_progress(
+    url: &str,
+    key: &str,
+    study_instance_uid: &str,
+    progress: &ProgressReporter,
+) -> Result<(), MilvueError> {
+    MilvueClient::with_api_key(url, key)?
+        .wait_for_done(study_instance_uid, progress)
+        .await
 }
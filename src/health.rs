@@ -0,0 +1,514 @@
+use reqwest::{StatusCode, Url};
+use serde::{Serialize, Serializer};
+use std::{
+    error::Error,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use tokio::net::{lookup_host, TcpStream};
+use tracing::{debug, info, instrument};
+
+use crate::{
+    client::{DEFAULT_CONNECT_TIMEOUT, PROBE_STUDY_INSTANCE_UID},
+    MilvueClient, MilvueError, StatusResponse,
+};
+
+/// Outcome of a step of a [HealthReport].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    /// The environment is usable, but something deserves attention.
+    Warning,
+    Failed,
+    /// Not run because a previous step failed.
+    Skipped,
+}
+
+/// Represents a step of a health check, with what was observed and what to do about it.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthCheck {
+    /// Name of the step: `url`, `dns`, `tcp`, `tls`, `authentication` or `api`.
+    pub name: &'static str,
+    pub status: CheckStatus,
+    /// What was observed.
+    pub message: String,
+    /// What to do about a warning or a failure.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    /// Time the step took.
+    #[serde(
+        rename = "elapsed_ms",
+        serialize_with = "serialize_millis",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub elapsed: Option<Duration>,
+}
+
+/// Represents the result of [MilvueClient::health_check()], one [HealthCheck] per step.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    /// URL of the environment.
+    pub url: String,
+    /// Version of the API, as reported in its status responses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<String>,
+    /// Round trip of an authenticated request.
+    #[serde(
+        rename = "latency_ms",
+        serialize_with = "serialize_millis",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub latency: Option<Duration>,
+    pub checks: Vec<HealthCheck>,
+}
+
+impl HealthReport {
+    /// Reports an environment whose client can't be built, e.g. without a URL or with a missing certificate file: the
+    /// `url` step fails with the error and the next steps are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `environment` - What identifies the environment in the report, e.g. its profile.
+    /// * `e` - The error of the client.
+    pub fn from_error(environment: &str, e: &MilvueError) -> HealthReport {
+        let mut report = HealthReport {
+            url: environment.to_string(),
+            api_version: None,
+            latency: None,
+            checks: Vec::new(),
+        };
+        report.push(
+            "url",
+            CheckStatus::Failed,
+            format!("the client can't be built: {}", e),
+            Some(configuration_hint(e)),
+            None,
+        );
+        report.skip(&["dns", "tcp", "tls", "authentication", "api"]);
+        report
+    }
+
+    /// Returns true if no step failed.
+    pub fn is_healthy(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.status != CheckStatus::Failed)
+    }
+
+    fn push(
+        &mut self,
+        name: &'static str,
+        status: CheckStatus,
+        message: String,
+        hint: Option<&str>,
+        elapsed: Option<Duration>,
+    ) {
+        self.checks.push(HealthCheck {
+            name,
+            status,
+            message,
+            hint: hint.map(str::to_string),
+            elapsed,
+        });
+    }
+
+    fn skip(&mut self, names: &[&'static str]) {
+        for name in names {
+            self.push(
+                name,
+                CheckStatus::Skipped,
+                "not run, a previous step failed".to_string(),
+                None,
+                None,
+            );
+        }
+    }
+
+    fn check_mut(&mut self, name: &str) -> Option<&mut HealthCheck> {
+        self.checks.iter_mut().find(|check| check.name == name)
+    }
+}
+
+fn serialize_millis<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => serializer.serialize_u64(duration.as_millis() as u64),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl MilvueClient {
+    /// Diagnoses the connection to the environment step by step: parses its URL, resolves its host name, connects
+    /// to it, validates its TLS certificate, checks the credentials with the status request of an unknown study, and
+    /// reads the version of the API. The request bypasses the rate limiter and the circuit breaker of the client.
+    ///
+    /// The host is resolved and connected to directly: behind a proxy, these steps may fail while the environment is
+    /// reachable, they are then reported as warnings.
+    ///
+    /// # Returns
+    ///
+    /// * The [HealthReport] of the environment, never an error: failures are reported as failed steps, with a hint.
+    #[instrument(name = "health_check", skip_all, fields(url = %self.url()))]
+    pub async fn health_check(&self) -> HealthReport {
+        let mut report = HealthReport {
+            url: self.url().to_string(),
+            api_version: None,
+            latency: None,
+            checks: Vec::new(),
+        };
+        let connect_timeout = self.timeouts().connect.unwrap_or(DEFAULT_CONNECT_TIMEOUT);
+
+        let url = match Url::parse(self.url()) {
+            Ok(url) if url.host_str().is_some() && matches!(url.scheme(), "http" | "https") => url,
+            Ok(_) | Err(_) => {
+                report.push(
+                    "url",
+                    CheckStatus::Failed,
+                    format!("{} is not an HTTP(S) URL", self.url()),
+                    Some("check the url of the profile, or the MILVUE_API_URL* environment variable of the environment"),
+                    None,
+                );
+                report.skip(&["dns", "tcp", "tls", "authentication", "api"]);
+                return report;
+            }
+        };
+        let host = url.host_str().unwrap_or_default().to_string();
+        let port = url.port_or_known_default().unwrap_or(443);
+        report.push(
+            "url",
+            CheckStatus::Ok,
+            format!("{}://{}:{}", url.scheme(), host, port),
+            None,
+            None,
+        );
+
+        // DNS and TCP, directly
+        let start = Instant::now();
+        let addresses =
+            match tokio::time::timeout(connect_timeout, lookup_host((host.as_str(), port))).await {
+                Ok(Ok(addresses)) => addresses.collect::<Vec<SocketAddr>>(),
+                Ok(Err(e)) => {
+                    debug!("Error while resolving {}: {}", host, e);
+                    Vec::new()
+                }
+                Err(_) => Vec::new(),
+            };
+        if addresses.is_empty() {
+            report.push(
+                "dns",
+                CheckStatus::Failed,
+                format!("{} could not be resolved", host),
+                Some("check the host name and the DNS servers of the machine"),
+                Some(start.elapsed()),
+            );
+            report.skip(&["tcp"]);
+        } else {
+            report.push(
+                "dns",
+                CheckStatus::Ok,
+                format!(
+                    "{} resolved to {}",
+                    host,
+                    addresses
+                        .iter()
+                        .map(|address| address.ip().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                None,
+                Some(start.elapsed()),
+            );
+            let start = Instant::now();
+            match tokio::time::timeout(connect_timeout, TcpStream::connect(&addresses[..])).await {
+                Ok(Ok(stream)) => report.push(
+                    "tcp",
+                    CheckStatus::Ok,
+                    format!(
+                        "connected to {}",
+                        stream
+                            .peer_addr()
+                            .map_or_else(|_| host.clone(), |address| address.to_string())
+                    ),
+                    None,
+                    Some(start.elapsed()),
+                ),
+                Ok(Err(e)) => report.push(
+                    "tcp",
+                    CheckStatus::Failed,
+                    format!("connection to port {} failed: {}", port, e),
+                    Some("check that a firewall allows the connection, or set the proxy of the network with --proxy"),
+                    Some(start.elapsed()),
+                ),
+                Err(_) => report.push(
+                    "tcp",
+                    CheckStatus::Failed,
+                    format!("connection to port {} timed out after {:?}", port, connect_timeout),
+                    Some("check that a firewall allows the connection, or set the proxy of the network with --proxy"),
+                    Some(start.elapsed()),
+                ),
+            }
+        }
+
+        // TLS, authentication and API, with the HTTP client of the client, through its proxy if any
+        let headers = match self.credentials().await {
+            Ok(headers) => headers,
+            Err(e) => {
+                report.skip(&["tls"]);
+                report.push(
+                    "authentication",
+                    CheckStatus::Failed,
+                    format!("credentials unavailable: {}", e),
+                    Some(credentials_hint(&e)),
+                    None,
+                );
+                report.skip(&["api"]);
+                return report;
+            }
+        };
+        let start = Instant::now();
+        let response = self
+            .http()
+            .get(format!(
+                "{}/v3/studies/{}/status",
+                self.url(),
+                PROBE_STUDY_INSTANCE_UID
+            ))
+            .headers(headers)
+            .timeout(self.timeouts().request.unwrap_or(connect_timeout * 2))
+            .send()
+            .await;
+        let elapsed = start.elapsed();
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                let chain = error_chain(&e);
+                let tls = ["certificate", "tls", "ssl", "handshake"]
+                    .iter()
+                    .any(|word| chain.to_lowercase().contains(word));
+                if tls {
+                    report.push(
+                        "tls",
+                        CheckStatus::Failed,
+                        format!("TLS handshake failed: {}", chain),
+                        Some("if a proxy inspects TLS, trust its CA with --ca-cert; otherwise check the clock of the machine"),
+                        Some(elapsed),
+                    );
+                } else {
+                    report.push(
+                        "tls",
+                        CheckStatus::Skipped,
+                        "not run, no connection".to_string(),
+                        None,
+                        None,
+                    );
+                }
+                report.push(
+                    "authentication",
+                    CheckStatus::Failed,
+                    format!("request failed: {}", chain),
+                    Some(if e.is_timeout() {
+                        "the environment doesn't answer in time, check the proxy and the firewall"
+                    } else {
+                        "check the URL, the proxy and the firewall"
+                    }),
+                    Some(elapsed),
+                );
+                report.skip(&["api"]);
+                return report;
+            }
+        };
+        report.latency = Some(elapsed);
+        info!(
+            "Environment answered {} in {:?}",
+            response.status(),
+            elapsed
+        );
+
+        // the environment answered, the direct steps only failed because of a proxy
+        for name in ["dns", "tcp"] {
+            if let Some(check) = report.check_mut(name) {
+                if check.status != CheckStatus::Ok {
+                    check.status = CheckStatus::Warning;
+                    check.message = format!(
+                        "{}, the environment is reached through a proxy",
+                        check.message
+                    );
+                    check.hint = None;
+                }
+            }
+        }
+        if url.scheme() == "https" {
+            report.push(
+                "tls",
+                CheckStatus::Ok,
+                format!("certificate of {} verified", host),
+                None,
+                None,
+            );
+        } else {
+            report.push(
+                "tls",
+                CheckStatus::Warning,
+                "plain HTTP, the credentials are sent unencrypted".to_string(),
+                Some("use the https URL of the environment"),
+                None,
+            );
+        }
+
+        // a 404 only proves the credentials if it is the status of the unknown study, not a page of another server
+        let status = response.status();
+        let body = response.bytes().await.unwrap_or_default();
+        let status_response = serde_json::from_slice::<StatusResponse>(&body).ok();
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => report.push(
+                "authentication",
+                CheckStatus::Failed,
+                format!("credentials rejected ({})", status),
+                Some("check the API key, and that it belongs to this environment"),
+                Some(elapsed),
+            ),
+            StatusCode::PROXY_AUTHENTICATION_REQUIRED => report.push(
+                "authentication",
+                CheckStatus::Failed,
+                format!("the proxy requires authentication ({})", status),
+                Some("set the username and password of the proxy in the profile"),
+                Some(elapsed),
+            ),
+            status if status.is_server_error() => report.push(
+                "authentication",
+                CheckStatus::Failed,
+                format!("the environment is failing ({})", status),
+                Some("retry later, or contact support@milvue.com if it persists"),
+                Some(elapsed),
+            ),
+            StatusCode::NOT_FOUND if status_response.is_none() => report.push(
+                "authentication",
+                CheckStatus::Warning,
+                format!(
+                    "credentials not verified, the answer ({}) doesn't come from the Milvue API",
+                    status
+                ),
+                None,
+                Some(elapsed),
+            ),
+            status if status.is_success() || status == StatusCode::NOT_FOUND => report.push(
+                "authentication",
+                CheckStatus::Ok,
+                format!("credentials accepted ({})", status),
+                None,
+                Some(elapsed),
+            ),
+            status => report.push(
+                "authentication",
+                CheckStatus::Warning,
+                format!("unexpected answer ({})", status),
+                Some("check that the URL is the one of the Milvue API"),
+                Some(elapsed),
+            ),
+        }
+
+        if report
+            .check_mut("authentication")
+            .is_some_and(|check| check.status == CheckStatus::Failed)
+        {
+            report.skip(&["api"]);
+            return report;
+        }
+        match status_response {
+            None if status == StatusCode::NOT_FOUND => report.push(
+                "api",
+                CheckStatus::Failed,
+                format!("{} answered {} without a Milvue study status", self.url(), status),
+                Some("check that the URL is the one of the Milvue API, e.g. https://api.milvue.com without any path"),
+                None,
+            ),
+            Some(body) => {
+                report.push(
+                    "api",
+                    CheckStatus::Ok,
+                    format!("Milvue API version {}", body.version),
+                    None,
+                    None,
+                );
+                report.api_version = Some(body.version);
+            }
+            None => report.push(
+                "api",
+                CheckStatus::Warning,
+                "the API didn't report its version".to_string(),
+                None,
+                None,
+            ),
+        }
+        report
+    }
+}
+
+/// What to do when the credentials of a client cannot be obtained.
+fn credentials_hint(e: &MilvueError) -> &'static str {
+    match e {
+        MilvueError::EnvVarNotFound(_) => "set the environment variable holding the API key",
+        MilvueError::Io(_, _) => "check the path and the permissions of the API key file",
+        _ => "check the OAuth2 token endpoint and the client credentials",
+    }
+}
+
+/// What to do when the client of an environment cannot be built.
+fn configuration_hint(e: &MilvueError) -> &'static str {
+    match e {
+        MilvueError::NoApiUrl => {
+            "set the url or the environment of the profile, or --api-url, and its MILVUE_API_URL* environment variable"
+        }
+        MilvueError::EnvVarNotFound(_) => "set the environment variable, or the value in the profile",
+        MilvueError::NoApiKey => "set the api_key or oauth2 section of the profile, or --api-key-file",
+        MilvueError::Io(_, _) | MilvueError::ConfigReadError(_, _) => {
+            "check the path and the permissions of the file"
+        }
+        #[cfg(feature = "config")]
+        MilvueError::ConfigParseError(_) => "check the syntax of the configuration files",
+        MilvueError::ProfileNotFound(_) => "check the name of the profile in the configuration files",
+        _ => "check the settings of the profile and the command line",
+    }
+}
+
+/// Joins the messages of an error and of its sources, e.g. the TLS error behind a connection error.
+fn error_chain(e: &dyn Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    message
+}
+
+/// Diagnoses the connection to the default environment, see [MilvueClient::health_check()].
+///
+/// # Arguments
+///
+/// * `key` - A string slice that holds the API key
+///
+/// # Returns
+///
+/// * A Result wrapping the [HealthReport], or an error if the URL of the default environment isn't set
+pub async fn health_check(key: &str) -> Result<HealthReport, MilvueError> {
+    health_check_with_url(&crate::MilvueUrl::default().get_url_from_envar()?, key).await
+}
+
+/// Diagnoses the connection to the environment at `url`, see [MilvueClient::health_check()].
+///
+/// # Arguments
+///
+/// * `url` - A string slice that holds the URL of the environment
+/// * `key` - A string slice that holds the API key
+///
+/// # Returns
+///
+/// * A Result wrapping the [HealthReport], or an error if the client cannot be created
+pub async fn health_check_with_url(url: &str, key: &str) -> Result<HealthReport, MilvueError> {
+    Ok(MilvueClient::with_api_key(url, key)?.health_check().await)
+}
@@ -13,20 +13,73 @@
 //!
 //! The primary interaction with the API involves two steps:
 //!
-//! 1. Submitting DICOM files for analysis using the [post()] or [post_with_url()] functions.
+//! 1. Submitting DICOM files for analysis using the [upload()] or [upload_with_url()] functions, which accept any mix
+//!    of paths, readers and in-memory objects (see [DicomSource]), or the [post()] or [post_with_url()] functions.
 //! 2. Fetching the resulting analysis using the [get()], [get_with_url()], [get_study_status()], [get_study_status_with_url()],
-//! [wait_for_done()], or [wait_for_done_with_url()] functions.
+//!    [wait_for_done()], or [wait_for_done_with_url()] functions. [get_many()] and [get_many_with_url()] fetch several
+//!    configurations of the same study concurrently.
 //!
 //! The library provides a variety of structs and enums to support these interactions, including:
 //!
 //! * [MilvueParams] for specifying the parameters of the request.
 //! * [MilvueUrl] for specifying the URL of the Milvue environment to interact with.
 //! * [InferenceCommand], [Language], [OutputFormat], [OutputSelection], [RecapTheme], [StaticReportFormat], and [StructuredReportFormat]
-//!   for customizing various aspects of the analysis.
+//!   for customizing various aspects of the analysis. They implement [std::str::FromStr], `Serialize` and `Deserialize`
+//!   using the values of the Milvue API, so that they can be stored and reconstructed faithfully.
 //! * [StatusResponse] for representing the response from the Milvue API.
+//! * [MultiGetResponse] for representing the results of several configurations of the same study.
 //!
 //! Additionally, the [check_study_uids()] function is provided to ensure that all DICOM files submitted in a single request have
-//! the same StudyInstanceUID.
+//! the same StudyInstanceUID. [check_studies()] returns a detailed [StudyReport] (mismatched PatientID or AccessionNumber,
+//! duplicate SOPInstanceUIDs) and [split_studies()] splits a mixed list into one [StudyBatch] per study.
+//!
+//! The [upload_with_progress()], [wait_for_done_with_progress()], [get_with_progress()] and [get_many_with_progress()]
+//! functions report [Progress] events (bytes sent and received, files completed, throughput, processing status) to a
+//! [ProgressReporter], either a callback or a channel.
+//!
+//! A [MilvueClient] holds the URL of an environment and an [auth::Authenticator] (static key, key file, environment
+//! variable or OAuth2 client credentials), and reuses its connections across requests. The free functions taking a
+//! `key` build a client with a static key on every call. The [MilvueClientBuilder] also accepts [TlsSettings] (private
+//! root CAs, client certificate) and [ProxySettings]. The TLS implementation is native-tls by default, or rustls with
+//! the `rustls` feature. [Timeouts] bound the connection and every request, and set deadlines on the upload,
+//! processing and download phases of a study, reported as distinct [MilvueError] variants. [RateLimits] space the
+//! submissions, status polls and downloads with a [RateLimiter] shared by the clones of a client, which also slows
+//! down when the API answers 429 Too Many Requests. A [CircuitBreaker] stops the requests to an environment failing
+//! repeatedly, until a probe succeeds. A [FailoverClient] submits the studies to the first healthy environment of an
+//! ordered list, and sends their status polls and downloads to the environment each study was submitted to.
+//! [MilvueClient::health_check()] diagnoses the connection to an environment step by step, from the DNS resolution to
+//! the authentication, in a [HealthReport].
+//!
+//! The [MilvueApi] trait covers the submission, status, wait and fetch of a study. It is implemented by
+//! [MilvueClient], and by [InMemoryMilvue] which returns canned results without any network access, so that code
+//! depending on the trait can be tested offline.
+//!
+//! With the `blocking` feature, the [blocking] module exposes the same operations to synchronous programs, with a
+//! runtime managed internally.
+//!
+//! [transcode()] converts compressed or big endian files to a native little endian transfer syntax according to a
+//! [TranscodePolicy], before an upload with [DicomSource::transcode()] or after a download.
+//!
+//! The [cache] module records the studies already submitted, to avoid submitting the same study twice.
+//!
+//! The [config] module loads the TOML configuration files and named profiles used by the `milvue_rs` binary.
+//!
+//! ## Features
+//!
+//! * `native-tls` (default) or `rustls` - The TLS implementation of the HTTP client.
+//! * `cli` (default) - The `milvue_rs` binary, and the `clap::ValueEnum` derives of the parameter enums. Enables
+//!   `audit`, `cache`, `config`, `metrics`, `opentelemetry` and `transcode`.
+//! * `audit` - The [audit] module, a hash-chained log of the uploads and downloads of a [MilvueClient].
+//! * `cache` - The [cache] module.
+//! * `config` - The [config] module.
+//! * `metrics` - The [metrics] module, Prometheus metrics updated by the requests of a [MilvueClient].
+//! * `opentelemetry` - The requests carry the W3C trace context (`traceparent`) of the current span, taken from
+//!   `tracing-opentelemetry` and injected with the global propagator of OpenTelemetry.
+//! * `transcode` - [transcode()] and [DicomSource::transcode()], with the pixel data codecs of dicom-rs.
+//! * `blocking` - The [blocking] module.
+//!
+//! Libraries embedding milvue_rs can disable the default features to leave out the command line dependencies, e.g.
+//! `milvue_rs = { version = "0.1", default-features = false, features = ["rustls"] }`.
 //!
 //! This library aims to make it easy to integrate the Milvue medical imaging analysis service into Rust applications.
 //!
@@ -75,17 +128,64 @@
 //! }
 //! ```
 
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!(
+    "milvue_rs requires a TLS implementation, enable the `native-tls` or `rustls` feature"
+);
+
+mod api;
+#[cfg(feature = "audit")]
+pub mod audit;
+pub mod auth;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "cache")]
+pub mod cache;
+mod circuit_breaker;
+mod client;
+#[cfg(feature = "config")]
+pub mod config;
+mod failover;
 mod get;
+mod health;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 mod post;
+mod progress;
+mod rate_limit;
 mod structs;
+mod study;
+#[cfg(feature = "transcode")]
+mod transcode;
 
+pub use api::{InMemoryMilvue, MilvueApi};
+pub use circuit_breaker::{
+    CircuitBreaker, CircuitBreakerSettings, CircuitState, DEFAULT_FAILURE_THRESHOLD,
+    DEFAULT_OPEN_DURATION,
+};
+pub use client::{
+    MilvueClient, MilvueClientBuilder, ProxySettings, Timeouts, TlsSettings,
+    DEFAULT_CONNECT_TIMEOUT,
+};
+pub use failover::FailoverClient;
 pub use get::{
-    get, get_study_status, get_study_status_with_url, get_with_url, wait_for_done,
-    wait_for_done_with_url,
+    get, get_many, get_many_with_progress, get_many_with_url, get_study_status,
+    get_study_status_with_url, get_with_progress, get_with_url, wait_for_done,
+    wait_for_done_with_progress, wait_for_done_with_url,
 };
-pub use post::{post, post_stream, post_with_url};
+pub use health::{health_check, health_check_with_url, CheckStatus, HealthCheck, HealthReport};
+#[allow(deprecated)]
+pub use post::post_stream;
+pub use post::{post, post_with_url, upload, upload_with_progress, upload_with_url, DicomSource};
+pub use progress::{Progress, ProgressReporter, StatusProgress, TransferProgress};
+pub use rate_limit::{Rate, RateLimiter, RateLimits};
 pub use structs::{
     check_study_uids, InferenceCommand, Language, MilvueError, MilvueParams, MilvueUrl,
-    OutputFormat, OutputSelection, RecapTheme, StaticReportFormat, StatusResponse,
-    StructuredReportFormat,
+    MultiGetResponse, OutputFormat, OutputSelection, RecapTheme, StaticReportFormat,
+    StatusResponse, StructuredReportFormat,
+};
+pub use study::{check_studies, split_studies, StudyBatch, StudyGroup, StudyIssue, StudyReport};
+#[cfg(feature = "transcode")]
+pub use transcode::{
+    transcode, TranscodePolicy, EXPLICIT_VR_LITTLE_ENDIAN, IMPLICIT_VR_LITTLE_ENDIAN,
 };
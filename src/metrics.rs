@@ -0,0 +1,220 @@
+//! Prometheus metrics of the requests sent to the Milvue API.
+//!
+//! A [Metrics] given to [crate::MilvueClientBuilder::metrics()] is updated by the client on every request: HTTP
+//! status codes, retries, uploaded and downloaded bytes, polls and processing time at Milvue, download durations.
+//! Applications processing studies record their progress with [Metrics::study()] and [Metrics::observe_latency()],
+//! and expose everything with [Metrics::encode()], e.g. on a `/metrics` endpoint.
+//!
+//! Requires the `metrics` feature.
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry,
+    TextEncoder,
+};
+use std::{fmt, time::Duration};
+
+use crate::InferenceCommand;
+
+/// Buckets of the durations, in seconds, from a second to an hour.
+const DURATION_BUCKETS: [f64; 12] = [
+    1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0,
+];
+
+/// Represents a step in the processing of a study, counted by [Metrics::study()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StudyState {
+    /// The study was found in the input.
+    Received,
+    /// The DICOM files of the study were uploaded.
+    Uploaded,
+    /// The results of the study were saved.
+    Completed,
+    /// The processing of the study failed.
+    Failed,
+}
+
+impl StudyState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StudyState::Received => "received",
+            StudyState::Uploaded => "uploaded",
+            StudyState::Completed => "completed",
+            StudyState::Failed => "failed",
+        }
+    }
+}
+
+/// Counters and histograms of the interactions with the Milvue API, in their own Prometheus registry.
+///
+/// Share it between clients with an `Arc`, every metric is prefixed with `milvue_`.
+pub struct Metrics {
+    registry: Registry,
+    studies: IntCounterVec,
+    study_latency: HistogramVec,
+    responses: IntCounterVec,
+    retries: IntCounterVec,
+    upload_bytes: IntCounter,
+    download_bytes: IntCounter,
+    polls: IntCounter,
+    processing_time: Histogram,
+    download_time: HistogramVec,
+}
+
+impl Metrics {
+    /// Creates and registers the metrics.
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let studies = IntCounterVec::new(
+            Opts::new("milvue_studies_total", "Studies by processing state"),
+            &["inference_command", "state"],
+        )
+        .expect("valid metric");
+        let study_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "milvue_study_latency_seconds",
+                "Time from the reception of a study to the saving of its results",
+            )
+            .buckets(DURATION_BUCKETS.to_vec()),
+            &["inference_command"],
+        )
+        .expect("valid metric");
+        let responses = IntCounterVec::new(
+            Opts::new("milvue_http_responses_total", "Responses of the Milvue API"),
+            &["operation", "status"],
+        )
+        .expect("valid metric");
+        let retries = IntCounterVec::new(
+            Opts::new("milvue_retries_total", "Requests sent again"),
+            &["operation", "reason"],
+        )
+        .expect("valid metric");
+        let upload_bytes =
+            IntCounter::new("milvue_upload_bytes_total", "Bytes of DICOM files uploaded")
+                .expect("valid metric");
+        let download_bytes =
+            IntCounter::new("milvue_download_bytes_total", "Bytes of results downloaded")
+                .expect("valid metric");
+        let polls = IntCounter::new(
+            "milvue_status_polls_total",
+            "Status requests while waiting for studies",
+        )
+        .expect("valid metric");
+        let processing_time = Histogram::with_opts(
+            HistogramOpts::new(
+                "milvue_processing_seconds",
+                "Time spent by studies at Milvue, measured by polling their status",
+            )
+            .buckets(DURATION_BUCKETS.to_vec()),
+        )
+        .expect("valid metric");
+        let download_time = HistogramVec::new(
+            HistogramOpts::new(
+                "milvue_download_seconds",
+                "Time to download and parse the results of a study",
+            )
+            .buckets(DURATION_BUCKETS.to_vec()),
+            &["inference_command"],
+        )
+        .expect("valid metric");
+
+        for collector in [
+            Box::new(studies.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(study_latency.clone()),
+            Box::new(responses.clone()),
+            Box::new(retries.clone()),
+            Box::new(upload_bytes.clone()),
+            Box::new(download_bytes.clone()),
+            Box::new(polls.clone()),
+            Box::new(processing_time.clone()),
+            Box::new(download_time.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metric names are unique");
+        }
+
+        Metrics {
+            registry,
+            studies,
+            study_latency,
+            responses,
+            retries,
+            upload_bytes,
+            download_bytes,
+            polls,
+            processing_time,
+            download_time,
+        }
+    }
+
+    /// Registry holding the metrics, e.g. to gather them with the metrics of the application.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Encodes the metrics in the Prometheus text format.
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding doesn't fail");
+        String::from_utf8(buffer).expect("text encoding is UTF-8")
+    }
+
+    /// Counts a study reaching `state` for an inference command.
+    pub fn study(&self, inference_command: &InferenceCommand, state: StudyState) {
+        self.studies
+            .with_label_values(&[&inference_command.to_string(), state.as_str()])
+            .inc();
+    }
+
+    /// Records the time from the reception of a study to the saving of its results for an inference command.
+    pub fn observe_latency(&self, inference_command: &InferenceCommand, latency: Duration) {
+        self.study_latency
+            .with_label_values(&[&inference_command.to_string()])
+            .observe(latency.as_secs_f64());
+    }
+
+    pub(crate) fn response(&self, operation: &str, status: &str) {
+        self.responses.with_label_values(&[operation, status]).inc();
+    }
+
+    pub(crate) fn retry(&self, operation: &str, reason: &str) {
+        self.retries.with_label_values(&[operation, reason]).inc();
+    }
+
+    pub(crate) fn uploaded(&self, bytes: u64) {
+        self.upload_bytes.inc_by(bytes);
+    }
+
+    pub(crate) fn poll(&self) {
+        self.polls.inc();
+    }
+
+    pub(crate) fn processed(&self, elapsed: Duration) {
+        self.processing_time.observe(elapsed.as_secs_f64());
+    }
+
+    pub(crate) fn downloaded(
+        &self,
+        inference_command: &InferenceCommand,
+        bytes: u64,
+        elapsed: Duration,
+    ) {
+        self.download_bytes.inc_by(bytes);
+        self.download_time
+            .with_label_values(&[&inference_command.to_string()])
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
@@ -1,12 +1,35 @@
+use bytes::Bytes;
 use dicom::object::InMemDicomObject;
-use dicom_object::FileDicomObject;
-use reqwest::{header, multipart, Body, Client};
-use std::path::PathBuf;
-use tokio::fs::File;
+use dicom_dictionary_std::tags;
+use dicom_object::{file::ReadPreamble, FileDicomObject, OpenFileOptions};
+use futures_util::stream;
+use reqwest::{multipart, Body};
+use std::{
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt},
+};
 use tokio_util::codec::{BytesCodec, FramedRead};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, instrument, warn, Span};
 
-use crate::{structs::MilvueError, MilvueUrl};
+#[cfg(feature = "audit")]
+use crate::audit::AuditOperation;
+use crate::{
+    client::{with_deadline, MAX_RATE_LIMITED_RETRIES},
+    progress::{ProgressStream, Transfer},
+    structs::MilvueError,
+    MilvueClient, MilvueUrl, Progress, ProgressReporter,
+};
+#[cfg(feature = "transcode")]
+use crate::{transcode::transcode, TranscodePolicy};
+
+/// Size of the chunks in which in-memory DICOM objects are sent.
+const CHUNK_SIZE: usize = 64 * 1024;
 
 /// Sends a POST request to upload DICOM files in the default environment.
 ///
@@ -36,34 +59,26 @@ pub async fn post(
 /// # Returns
 ///
 /// * A Result wrapping a reqwest::Response indicating the HTTP response or an error.
+#[instrument(
+    name = "upload",
+    skip_all,
+    fields(files = dicom_list.len(), study_instance_uid = tracing::field::Empty)
+)]
 pub async fn post_with_url(
     url: &str,
     key: &str,
     dicom_list: &mut [FileDicomObject<InMemDicomObject>],
 ) -> Result<reqwest::Response, MilvueError> {
-    let study_instance_uid = dicom_list[0]
+    let study_instance_uid = dicom_list
+        .first()
+        .ok_or(MilvueError::EmptyDicomList)?
         .element_by_name("StudyInstanceUID")?
         .to_str()?;
+    Span::current().record("study_instance_uid", study_instance_uid.as_ref());
     info!("Preparing POST request for study {}", study_instance_uid);
 
-    let milvue_api_url = format!("{}/v3/studies", url);
-
-    let mut headers = header::HeaderMap::new();
-
-    let mut api_key = header::HeaderValue::from_str(key)?;
-    api_key.set_sensitive(true);
-    headers.insert("x-goog-meta-owner", api_key);
-
-    headers.insert(
-        header::CONTENT_TYPE,
-        header::HeaderValue::from_static("multipart/related"),
-    );
-
-    headers.insert(
-        "type",
-        header::HeaderValue::from_static("application/dicom"),
-    );
-    debug!("Headers: {:?}", headers);
+    let client = MilvueClient::with_api_key(url, key)?;
+    let milvue_api_url = format!("{}/v3/studies", client.url());
 
     info!(
         "Building multipart form with {} DICOM files",
@@ -71,10 +86,13 @@ pub async fn post_with_url(
     );
     let form = build_form(dicom_list);
 
-    let client = Client::builder().default_headers(headers).build()?;
-
     info!("Sending POST request to {}", milvue_api_url);
-    let response = client.post(milvue_api_url).multipart(form?).send().await?;
+    let response = client
+        .send(
+            "upload",
+            upload_request(&client, milvue_api_url).multipart(form?),
+        )
+        .await?;
 
     match response.status() {
         reqwest::StatusCode::OK => info!("POST request successfully sent."),
@@ -87,61 +105,488 @@ pub async fn post_with_url(
     Ok(response)
 }
 
+/// Sends a POST request to upload a study whose DICOM files are streamed from disk.
+///
+/// # Arguments
+///
+/// * `key` - A String that holds the API key.
+/// * `url` - A String that holds the URL of the environment.
+/// * `study` - The StudyInstanceUID and the list of (SOPInstanceUID, path) of its DICOM files.
+///
+/// # Returns
+///
+/// * A Result wrapping a reqwest::Response indicating the HTTP response or an error.
+#[deprecated(
+    note = "use upload_with_url, which accepts any mix of paths, readers and in-memory objects"
+)]
 pub async fn post_stream(
     key: String,
     url: String,
     study: (String, Vec<(String, PathBuf)>),
 ) -> Result<reqwest::Response, MilvueError> {
-    let milvue_api_url = format!("{}/v3/studies", url);
+    let sources = study
+        .1
+        .into_iter()
+        .map(|(sop_instance_uid, path)| DicomSource::File {
+            path,
+            sop_instance_uid: Some(sop_instance_uid),
+        })
+        .collect();
+    upload_with_url(&url, &key, sources).await
+}
 
-    let mut headers = header::HeaderMap::new();
+/// Represents a DICOM file to be uploaded with [upload()] or [upload_with_url()].
+pub enum DicomSource {
+    /// A DICOM file on disk, streamed during the upload.
+    ///
+    /// If the SOPInstanceUID is not provided, it is read from the header of the file.
+    File {
+        path: PathBuf,
+        sop_instance_uid: Option<String>,
+    },
+    /// A reader over the content of a DICOM file, including its preamble and meta group, streamed during the upload.
+    Reader {
+        sop_instance_uid: String,
+        reader: Box<dyn AsyncRead + Send + Sync + Unpin>,
+    },
+    /// A DICOM object held in memory, serialized right before the upload.
+    Object(Box<FileDicomObject<InMemDicomObject>>),
+}
 
-    let mut api_key = header::HeaderValue::from_str(&key)?;
+impl From<PathBuf> for DicomSource {
+    fn from(path: PathBuf) -> Self {
+        DicomSource::File {
+            path,
+            sop_instance_uid: None,
+        }
+    }
+}
 
-    api_key.set_sensitive(true);
+impl From<&Path> for DicomSource {
+    fn from(path: &Path) -> Self {
+        DicomSource::from(path.to_path_buf())
+    }
+}
 
-    headers.insert("x-goog-meta-owner", api_key);
+impl From<FileDicomObject<InMemDicomObject>> for DicomSource {
+    fn from(object: FileDicomObject<InMemDicomObject>) -> Self {
+        DicomSource::Object(Box::new(object))
+    }
+}
 
-    headers.insert(
-        header::CONTENT_TYPE,
-        header::HeaderValue::from_static("multipart/related"),
-    );
+impl DicomSource {
+    /// Converts the source to the transfer syntax required by `policy`, see [crate::transcode()].
+    ///
+    /// Requires the `transcode` feature.
+    ///
+    /// Files in an accepted transfer syntax are still streamed from disk, other files are loaded and converted in
+    /// memory. Readers are read entirely to find their transfer syntax.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The accepted and target transfer syntaxes.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the source to upload, or an error if the file cannot be read or converted.
+    #[cfg(feature = "transcode")]
+    pub async fn transcode(self, policy: &TranscodePolicy) -> Result<DicomSource, MilvueError> {
+        match self {
+            DicomSource::File {
+                path,
+                sop_instance_uid,
+            } => {
+                let header = OpenFileOptions::new()
+                    .read_until(tags::PIXEL_DATA)
+                    .open_file(&path)?;
+                if policy.accepts(header.meta().transfer_syntax()) {
+                    return Ok(DicomSource::File {
+                        path,
+                        sop_instance_uid,
+                    });
+                }
+                let object = OpenFileOptions::new().open_file(&path)?;
+                Ok(DicomSource::Object(Box::new(transcode(object, policy)?)))
+            }
+            DicomSource::Reader {
+                sop_instance_uid,
+                mut reader,
+            } => {
+                let mut buffer = Vec::new();
+                reader
+                    .read_to_end(&mut buffer)
+                    .await
+                    .map_err(|e| MilvueError::Io(PathBuf::from(&sop_instance_uid), e))?;
+                let object = OpenFileOptions::new()
+                    .read_preamble(ReadPreamble::Auto)
+                    .from_reader(Cursor::new(&buffer))?;
+                if policy.accepts(object.meta().transfer_syntax()) {
+                    return Ok(DicomSource::Reader {
+                        sop_instance_uid,
+                        reader: Box::new(Cursor::new(buffer)),
+                    });
+                }
+                Ok(DicomSource::Object(Box::new(transcode(object, policy)?)))
+            }
+            DicomSource::Object(object) => {
+                Ok(DicomSource::Object(Box::new(transcode(*object, policy)?)))
+            }
+        }
+    }
 
-    headers.insert(
-        "type",
-        header::HeaderValue::from_static("application/dicom"),
-    );
+    /// Loads the whole DICOM object in memory.
+    pub(crate) async fn into_object(
+        self,
+    ) -> Result<FileDicomObject<InMemDicomObject>, MilvueError> {
+        match self {
+            DicomSource::File { path, .. } => Ok(OpenFileOptions::new().open_file(&path)?),
+            DicomSource::Reader {
+                sop_instance_uid,
+                mut reader,
+            } => {
+                let mut buffer = Vec::new();
+                reader
+                    .read_to_end(&mut buffer)
+                    .await
+                    .map_err(|e| MilvueError::Io(PathBuf::from(&sop_instance_uid), e))?;
+                Ok(OpenFileOptions::new()
+                    .read_preamble(ReadPreamble::Auto)
+                    .from_reader(Cursor::new(buffer))?)
+            }
+            DicomSource::Object(object) => Ok(*object),
+        }
+    }
+
+    /// Turns the source into a multipart part reporting its progress to `transfer`.
+    ///
+    /// Returns the part with the SOPInstanceUID and the size of the file, if known.
+    async fn into_part(
+        self,
+        transfer: &Arc<Transfer>,
+    ) -> Result<(String, multipart::Part, Option<u64>), MilvueError> {
+        match self {
+            DicomSource::File {
+                path,
+                sop_instance_uid,
+            } => {
+                let sop_instance_uid = match sop_instance_uid {
+                    Some(sop_instance_uid) => sop_instance_uid,
+                    None => read_sop_instance_uid(&path)?,
+                };
+                let file = File::open(&path)
+                    .await
+                    .map_err(|e| MilvueError::Io(path.clone(), e))?;
+                let length = file
+                    .metadata()
+                    .await
+                    .map_err(|e| MilvueError::Io(path.clone(), e))?
+                    .len();
+                debug!("Streaming {} ({} bytes)", path.display(), length);
+                let stream = FramedRead::new(file, BytesCodec::new());
+                let body = Body::wrap_stream(ProgressStream::new(stream, transfer.clone()));
+                let part = multipart::Part::stream_with_length(body, length)
+                    .mime_str("application/dicom")?;
+                Ok((sop_instance_uid, part, Some(length)))
+            }
+            DicomSource::Reader {
+                sop_instance_uid,
+                reader,
+            } => {
+                let stream = FramedRead::new(reader, BytesCodec::new());
+                let body = Body::wrap_stream(ProgressStream::new(stream, transfer.clone()));
+                let part = multipart::Part::stream(body).mime_str("application/dicom")?;
+                Ok((sop_instance_uid, part, None))
+            }
+            DicomSource::Object(object) => {
+                let sop_instance_uid = object
+                    .element_by_name("SOPInstanceUID")?
+                    .to_str()?
+                    .to_string();
+                let mut buffer = Vec::new();
+                object.write_all(&mut buffer)?;
+                let length = buffer.len() as u64;
+                let buffer = Bytes::from(buffer);
+                let chunks = (0..buffer.len())
+                    .step_by(CHUNK_SIZE)
+                    .map(|start| {
+                        Ok::<_, io::Error>(
+                            buffer.slice(start..buffer.len().min(start + CHUNK_SIZE)),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let body =
+                    Body::wrap_stream(ProgressStream::new(stream::iter(chunks), transfer.clone()));
+                let part = multipart::Part::stream_with_length(body, length)
+                    .mime_str("application/dicom")?;
+                Ok((sop_instance_uid, part, Some(length)))
+            }
+        }
+    }
+}
 
-    let client = Client::builder().default_headers(headers).build()?;
+impl DicomSource {
+    /// Copies the source to upload it again, e.g. to another environment, impossible for readers which can only be
+    /// read once.
+    pub(crate) fn try_clone(&self) -> Option<DicomSource> {
+        match self {
+            DicomSource::File {
+                path,
+                sop_instance_uid,
+            } => Some(DicomSource::File {
+                path: path.clone(),
+                sop_instance_uid: sop_instance_uid.clone(),
+            }),
+            DicomSource::Reader { .. } => None,
+            DicomSource::Object(object) => Some(DicomSource::Object(object.clone())),
+        }
+    }
 
-    let mut form = multipart::Form::new();
+    /// Reads the StudyInstanceUID of the source, unknown for readers which can only be read once.
+    pub(crate) fn study_instance_uid(&self) -> Option<String> {
+        let uid = match self {
+            DicomSource::File { path, .. } => OpenFileOptions::new()
+                .read_until(tags::PIXEL_DATA)
+                .open_file(path)
+                .ok()?
+                .element_by_name("StudyInstanceUID")
+                .ok()?
+                .to_str()
+                .ok()?
+                .to_string(),
+            DicomSource::Reader { .. } => return None,
+            DicomSource::Object(object) => object
+                .element_by_name("StudyInstanceUID")
+                .ok()?
+                .to_str()
+                .ok()?
+                .to_string(),
+        };
+        Some(uid.trim_end_matches(['\0', ' ']).to_string())
+    }
+}
+
+/// Reads the SOPInstanceUID of a DICOM file without loading its pixel data.
+fn read_sop_instance_uid(path: &Path) -> Result<String, MilvueError> {
+    let object = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(path)?;
+    Ok(object
+        .element_by_name("SOPInstanceUID")?
+        .to_str()?
+        .to_string())
+}
+
+/// Sends a POST request to upload DICOM files from any mix of sources in the default environment.
+///
+/// # Arguments
+///
+/// * `key` - A string slice that holds the API key.
+/// * `sources` - The DICOM files to be uploaded, see [DicomSource].
+///
+/// # Returns
+///
+/// * A Result wrapping a reqwest::Response indicating the HTTP response or an error.
+pub async fn upload(
+    key: &str,
+    sources: Vec<DicomSource>,
+) -> Result<reqwest::Response, MilvueError> {
+    upload_with_url(&MilvueUrl::default().get_url_from_envar()?, key, sources).await
+}
 
-    for (sop, path) in study.1 {
-        let file = File::open(path).await.unwrap(); // TODO: Create a MilvueError
-        let stream = FramedRead::new(file, BytesCodec::new());
-        let body = Body::wrap_stream(stream);
-        let part = multipart::Part::stream(body)
-            .mime_str("application/dicom")
-            .unwrap();
-        form = form.part(sop, part);
+/// Sends a POST request to upload DICOM files from any mix of sources to a specific URL.
+///
+/// Files on disk and readers are streamed, they are never entirely loaded in memory. In-memory objects are consumed
+/// as they are serialized.
+///
+/// # Arguments
+///
+/// * `url` - A string slice that holds the URL of the environment.
+/// * `key` - A string slice that holds the API key.
+/// * `sources` - The DICOM files to be uploaded, see [DicomSource].
+///
+/// # Returns
+///
+/// * A Result wrapping a reqwest::Response indicating the HTTP response or an error.
+pub async fn upload_with_url(
+    url: &str,
+    key: &str,
+    sources: Vec<DicomSource>,
+) -> Result<reqwest::Response, MilvueError> {
+    upload_with_progress(url, key, sources, &ProgressReporter::default()).await
+}
+
+/// Sends a POST request to upload DICOM files to a specific URL, reporting the progress of the upload.
+///
+/// Behaves like [upload_with_url()], and reports [Progress::Upload] events to `progress` as the bytes are sent.
+///
+/// # Arguments
+///
+/// * `url` - A string slice that holds the URL of the environment.
+/// * `key` - A string slice that holds the API key.
+/// * `sources` - The DICOM files to be uploaded, see [DicomSource].
+/// * `progress` - The [ProgressReporter] receiving the progress events.
+///
+/// # Returns
+///
+/// * A Result wrapping a reqwest::Response indicating the HTTP response or an error.
+pub async fn upload_with_progress(
+    url: &str,
+    key: &str,
+    sources: Vec<DicomSource>,
+    progress: &ProgressReporter,
+) -> Result<reqwest::Response, MilvueError> {
+    MilvueClient::with_api_key(url, key)?
+        .upload(sources, progress)
+        .await
+}
+
+impl MilvueClient {
+    /// Uploads DICOM files from any mix of sources, reporting the progress of the upload.
+    ///
+    /// Files on disk and readers are streamed, see [upload_with_url()]. If the API answers 429 Too Many Requests, the
+    /// files are uploaded again once the rate limit allows it, up to 3 times, unless one of them is a reader which
+    /// can only be read once.
+    ///
+    /// # Arguments
+    ///
+    /// * `sources` - The DICOM files to be uploaded, see [DicomSource].
+    /// * `progress` - The [ProgressReporter] receiving the progress events, `&Default::default()` to ignore them.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping a reqwest::Response indicating the HTTP response, [MilvueError::EmptyDicomList] if
+    ///   `sources` is empty, [MilvueError::UploadTimeout] if the upload deadline of the client is exceeded, or another
+    ///   error.
+    #[instrument(
+        name = "upload",
+        skip_all,
+        fields(files = sources.len(), study_instance_uid = tracing::field::Empty)
+    )]
+    pub async fn upload(
+        &self,
+        sources: Vec<DicomSource>,
+        progress: &ProgressReporter,
+    ) -> Result<reqwest::Response, MilvueError> {
+        if sources.is_empty() {
+            return Err(MilvueError::EmptyDicomList);
+        }
+        let study_instance_uid = sources.first().and_then(DicomSource::study_instance_uid);
+        if let Some(uid) = &study_instance_uid {
+            Span::current().record("study_instance_uid", uid.as_str());
+        }
+        #[cfg(feature = "audit")]
+        let audit = match self.audit_log() {
+            Some(_) => self.audit(
+                AuditOperation::Upload,
+                study_instance_uid,
+                None,
+                sources.len(),
+            ),
+            None => None,
+        };
+
+        let mut sources = sources;
+        let mut rate_limited = 0;
+        #[cfg(feature = "audit")]
+        let mut bytes;
+        let result = loop {
+            let transfer = Transfer::new(progress.clone(), Progress::Upload, Some(sources.len()));
+            let replay = match rate_limited < MAX_RATE_LIMITED_RETRIES {
+                true => sources.iter().map(DicomSource::try_clone).collect(),
+                false => None,
+            };
+            // the deadline doesn't include the wait for the rate limit
+            self.rate_limiter().acquire("upload").await;
+            let result = with_deadline(
+                self.timeouts().upload,
+                MilvueError::UploadTimeout,
+                self.send_upload(sources, &transfer),
+            )
+            .await;
+            #[cfg(feature = "audit")]
+            {
+                bytes = transfer.bytes();
+            }
+            match (result, replay) {
+                (Err(MilvueError::StatusResponseError(response)), Some(replay))
+                    if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    warn!("Rate limited by the API, uploading the study again");
+                    #[cfg(feature = "metrics")]
+                    if let Some(metrics) = self.metrics() {
+                        metrics.retry("upload", "rate_limited");
+                    }
+                    rate_limited += 1;
+                    sources = replay;
+                }
+                (result, _) => break result,
+            }
+        };
+
+        #[cfg(feature = "audit")]
+        if let Some(mut audit) = audit {
+            audit.set_bytes(bytes);
+            audit.finish(&result);
+        }
+        result
     }
 
-    println!("Posting study {} with post stream", study.0);
-    let start = std::time::Instant::now();
+    async fn send_upload(
+        &self,
+        sources: Vec<DicomSource>,
+        transfer: &Arc<Transfer>,
+    ) -> Result<reqwest::Response, MilvueError> {
+        let milvue_api_url = format!("{}/v3/studies", self.url());
 
-    let response = client.post(milvue_api_url).multipart(form).send().await?;
+        let number_of_files = sources.len();
+        info!(
+            "Building multipart form with {} DICOM files",
+            number_of_files
+        );
+        let mut form = multipart::Form::new();
+        for (i, source) in sources.into_iter().enumerate() {
+            let (sop_instance_uid, part, length) = source.into_part(transfer).await?;
+            info!(
+                "Adding DICOM file {}/{} with SOPInstanceUID {}",
+                i + 1,
+                number_of_files,
+                sop_instance_uid
+            );
+            transfer.add_total_bytes(length);
+            form = form.part(format!("{}.dcm", sop_instance_uid), part);
+        }
 
-    println!("Time to post study: {:?}", start.elapsed());
+        info!("Sending POST request to {}", milvue_api_url);
+        let start = Instant::now();
+        let response = self
+            .send_acquired(
+                "upload",
+                upload_request(self, milvue_api_url).multipart(form),
+            )
+            .await?;
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = self.metrics() {
+            metrics.uploaded(transfer.bytes());
+        }
 
-    match response.status() {
-        reqwest::StatusCode::OK => info!("POST request successfully sent."),
-        status => {
-            error!("POST request failed with status code {}", status);
-            return Err(MilvueError::StatusResponseError(response));
+        match response.status() {
+            reqwest::StatusCode::OK => {
+                info!("POST request successfully sent in {:?}.", start.elapsed())
+            }
+            status => {
+                error!("POST request failed with status code {}", status);
+                return Err(MilvueError::StatusResponseError(response));
+            }
         }
+
+        Ok(response)
     }
+}
 
-    Ok(response)
+/// Builds an upload request, without its body.
+fn upload_request(client: &MilvueClient, url: String) -> reqwest::RequestBuilder {
+    debug!("Building upload request to {}", url);
+    client.http().post(url).header("type", "application/dicom")
 }
 
 /// Builds a multipart form with the provided list of DICOM files.
@@ -0,0 +1,208 @@
+use bytes::Bytes;
+use futures_util::Stream;
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, watch};
+
+/// Represents a progress event reported by the `*_with_progress` functions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    /// Bytes of DICOM files sent by [crate::upload_with_progress()].
+    Upload(TransferProgress),
+    /// Status received while polling with [crate::wait_for_done_with_progress()].
+    Status(StatusProgress),
+    /// Bytes of results received by [crate::get_with_progress()] or [crate::get_many_with_progress()].
+    Download(TransferProgress),
+}
+
+/// Represents the progress of an upload or a download.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransferProgress {
+    /// Bytes transferred so far.
+    pub bytes: u64,
+    /// Total number of bytes to transfer, if known.
+    pub total_bytes: Option<u64>,
+    /// Number of DICOM files completely transferred.
+    pub files_completed: usize,
+    /// Total number of DICOM files to transfer, if known.
+    pub total_files: Option<usize>,
+    /// Time elapsed since the beginning of the transfer.
+    pub elapsed: Duration,
+}
+
+impl TransferProgress {
+    /// Average throughput since the beginning of the transfer, in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => self.bytes as f64 / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Represents the progress of the processing of a study by Milvue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusProgress {
+    /// Status returned by the last poll, e.g. "running" or "done".
+    pub status: String,
+    /// Number of polls so far.
+    pub polls: u32,
+    /// Time elapsed since the first poll.
+    pub elapsed: Duration,
+}
+
+/// Receives the [Progress] events of an operation.
+///
+/// A reporter is either a callback, or the sending half of a channel created by [ProgressReporter::watch()] or
+/// [ProgressReporter::channel()]. The default reporter discards every event.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    callback: Option<Arc<dyn Fn(Progress) + Send + Sync>>,
+}
+
+impl ProgressReporter {
+    /// Creates a reporter calling `callback` on every event.
+    ///
+    /// The callback is called from the task performing the request, it should return quickly.
+    pub fn new(callback: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        ProgressReporter {
+            callback: Some(Arc::new(callback)),
+        }
+    }
+
+    /// Creates a reporter publishing the latest event on a [watch] channel, convenient for progress displays.
+    pub fn watch() -> (Self, watch::Receiver<Option<Progress>>) {
+        let (tx, rx) = watch::channel(None);
+        (
+            ProgressReporter::new(move |progress| {
+                tx.send_replace(Some(progress));
+            }),
+            rx,
+        )
+    }
+
+    /// Creates a reporter sending every event on an unbounded channel, which can be consumed as a stream.
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<Progress>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (
+            ProgressReporter::new(move |progress| {
+                // The receiver may have been dropped, events are then discarded
+                let _ = tx.send(progress);
+            }),
+            rx,
+        )
+    }
+
+    pub(crate) fn report(&self, progress: Progress) {
+        if let Some(callback) = &self.callback {
+            callback(progress);
+        }
+    }
+}
+
+/// Shared state of a transfer made of several files.
+pub(crate) struct Transfer {
+    reporter: ProgressReporter,
+    kind: fn(TransferProgress) -> Progress,
+    start: Instant,
+    progress: Mutex<TransferProgress>,
+}
+
+impl Transfer {
+    /// Creates a transfer whose total size is cumulated by [Transfer::add_total_bytes()].
+    pub(crate) fn new(
+        reporter: ProgressReporter,
+        kind: fn(TransferProgress) -> Progress,
+        total_files: Option<usize>,
+    ) -> Arc<Self> {
+        Arc::new(Transfer {
+            reporter,
+            kind,
+            start: Instant::now(),
+            progress: Mutex::new(TransferProgress {
+                total_bytes: Some(0),
+                total_files,
+                ..Default::default()
+            }),
+        })
+    }
+
+    fn update(&self, update: impl FnOnce(&mut TransferProgress)) {
+        let progress = {
+            let mut progress = self.progress.lock().expect("progress lock poisoned");
+            update(&mut progress);
+            progress.elapsed = self.start.elapsed();
+            progress.clone()
+        };
+        self.reporter.report((self.kind)(progress));
+    }
+
+    pub(crate) fn add_bytes(&self, bytes: u64) {
+        self.update(|progress| progress.bytes += bytes);
+    }
+
+    /// Adds the size of a file or response to the total, which becomes unknown if `bytes` is None.
+    pub(crate) fn add_total_bytes(&self, bytes: Option<u64>) {
+        self.update(|progress| {
+            progress.total_bytes = progress.total_bytes.zip(bytes).map(|(a, b)| a + b)
+        });
+    }
+
+    /// Number of bytes transferred so far.
+    #[cfg_attr(not(any(feature = "metrics", feature = "audit")), allow(dead_code))]
+    pub(crate) fn bytes(&self) -> u64 {
+        self.progress.lock().expect("progress lock poisoned").bytes
+    }
+
+    pub(crate) fn complete_file(&self) {
+        self.update(|progress| progress.files_completed += 1);
+    }
+}
+
+/// Wraps the byte stream of one file and reports its progress to a [Transfer].
+pub(crate) struct ProgressStream<S> {
+    inner: S,
+    transfer: Arc<Transfer>,
+    done: bool,
+}
+
+impl<S> ProgressStream<S> {
+    pub(crate) fn new(inner: S, transfer: Arc<Transfer>) -> Self {
+        ProgressStream {
+            inner,
+            transfer,
+            done: false,
+        }
+    }
+}
+
+impl<S, B> Stream for ProgressStream<S>
+where
+    S: Stream<Item = Result<B, io::Error>> + Unpin,
+    B: Into<Bytes>,
+{
+    type Item = Result<Bytes, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match Pin::new(&mut self.inner).poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                let chunk: Bytes = chunk.into();
+                self.transfer.add_bytes(chunk.len() as u64);
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(None) => {
+                if !self.done {
+                    self.done = true;
+                    self.transfer.complete_file();
+                }
+                Poll::Ready(None)
+            }
+            other => other.map(|item| item.map(|result| result.map(Into::into))),
+        }
+    }
+}
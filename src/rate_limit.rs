@@ -0,0 +1,306 @@
+use reqwest::{header::RETRY_AFTER, Response};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::structs::MilvueError;
+
+/// Operations of the API limited separately, as named in the metrics.
+const OPERATIONS: [&str; 3] = ["upload", "status", "results"];
+
+/// Lowest fraction of the configured rate a 429 Too Many Requests can bring an operation down to.
+const MIN_FACTOR: f64 = 0.1;
+
+/// Fraction of the configured rate recovered by every request accepted by the API.
+const RECOVERY_STEP: f64 = 0.05;
+
+/// Pause of an operation without a configured rate after a 429 Too Many Requests without `Retry-After`.
+const DEFAULT_PAUSE: Duration = Duration::from_secs(1);
+
+/// Longest pause of an operation after a 429 Too Many Requests, whatever its `Retry-After`.
+const MAX_PAUSE: Duration = Duration::from_secs(3600);
+
+/// Represents the rate of the requests of an operation, a token bucket holding up to `burst` requests and refilled
+/// at `per_minute` requests per minute.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rate {
+    /// Requests allowed per minute.
+    pub per_minute: f64,
+    /// Requests allowed at once after an idle period.
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+}
+
+fn default_burst() -> u32 {
+    1
+}
+
+impl Rate {
+    /// Creates a rate of `per_minute` requests per minute, without burst.
+    pub fn per_minute(per_minute: f64) -> Rate {
+        Rate {
+            per_minute,
+            burst: default_burst(),
+        }
+    }
+}
+
+/// Represents the rate limits of the requests of a [crate::MilvueClient], per operation, unlimited if unset.
+///
+/// The operations are the submission of the studies (`upload`), the status polls (`status`) and the downloads of
+/// the results (`results`). In a configuration profile:
+///
+/// ```toml
+/// [profiles.prod.rate_limits]
+/// upload = { per_minute = 30, burst = 5 }
+/// status = { per_minute = 600 }
+/// results = { per_minute = 120, burst = 10 }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimits {
+    /// Rate of the submissions of studies.
+    pub upload: Option<Rate>,
+    /// Rate of the status polls.
+    pub status: Option<Rate>,
+    /// Rate of the downloads of results.
+    pub results: Option<Rate>,
+}
+
+impl RateLimits {
+    /// Merges other rate limits on top of these, values set in `other` win.
+    pub fn merge(&mut self, other: RateLimits) {
+        if other.upload.is_some() {
+            self.upload = other.upload;
+        }
+        if other.status.is_some() {
+            self.status = other.status;
+        }
+        if other.results.is_some() {
+            self.results = other.results;
+        }
+    }
+
+    /// Rate of an operation, `upload`, `status` or `results`.
+    pub fn get(&self, operation: &str) -> Option<&Rate> {
+        match operation {
+            "upload" => self.upload.as_ref(),
+            "status" => self.status.as_ref(),
+            "results" => self.results.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Sets the rate of an operation, `upload`, `status` or `results`.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping nothing, or [MilvueError::InvalidValue] if the operation is unknown.
+    pub fn set(&mut self, operation: &str, rate: Rate) -> Result<(), MilvueError> {
+        match operation {
+            "upload" => self.upload = Some(rate),
+            "status" => self.status = Some(rate),
+            "results" => self.results = Some(rate),
+            _ => {
+                return Err(MilvueError::InvalidValue(
+                    "rate limited operation, expected upload, status or results",
+                    operation.to_string(),
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Token buckets limiting the requests of the clients sharing it, one per operation.
+///
+/// The tasks waiting for a request of the same operation are served in turn. A 429 Too Many Requests pauses the
+/// operation until the time given by its `Retry-After` header and halves its rate, down to a tenth of the configured
+/// rate; every accepted request then recovers a twentieth of it. Operations without a configured rate are only
+/// paused.
+#[derive(Debug)]
+pub struct RateLimiter {
+    buckets: HashMap<&'static str, Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: Option<Rate>,
+    /// Held by the task waiting for the next token, so that the waiting tasks are served in order.
+    turn: tokio::sync::Mutex<()>,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    refilled: Instant,
+    paused_until: Option<Instant>,
+    /// Fraction of the configured rate currently allowed.
+    factor: f64,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(&RateLimits::default()).expect("no rate limit to validate")
+    }
+}
+
+impl RateLimiter {
+    /// Creates the buckets of the rate limits, full.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - The rates of the operations.
+    ///
+    /// # Returns
+    ///
+    /// * A Result wrapping the limiter, or [MilvueError::InvalidValue] if a rate is not positive or a burst is 0.
+    pub fn new(limits: &RateLimits) -> Result<RateLimiter, MilvueError> {
+        let mut buckets = HashMap::new();
+        for operation in OPERATIONS {
+            let rate = limits.get(operation).cloned();
+            if let Some(rate) = &rate {
+                if !(rate.per_minute.is_finite() && rate.per_minute > 0.0) || rate.burst == 0 {
+                    return Err(MilvueError::InvalidValue(
+                        "rate limit, expected a positive rate and burst",
+                        format!(
+                            "{} = {} per minute, burst {}",
+                            operation, rate.per_minute, rate.burst
+                        ),
+                    ));
+                }
+            }
+            let tokens = rate.as_ref().map_or(0.0, |rate| f64::from(rate.burst));
+            buckets.insert(
+                operation,
+                Bucket {
+                    rate,
+                    turn: tokio::sync::Mutex::new(()),
+                    state: Mutex::new(BucketState {
+                        tokens,
+                        refilled: Instant::now(),
+                        paused_until: None,
+                        factor: 1.0,
+                    }),
+                },
+            );
+        }
+        Ok(RateLimiter { buckets })
+    }
+
+    /// Waits until a request of `operation` is allowed, and takes its token.
+    pub async fn acquire(&self, operation: &str) {
+        let Some(bucket) = self.buckets.get(operation) else {
+            return;
+        };
+        let _turn = bucket.turn.lock().await;
+        loop {
+            let wait = {
+                let mut state = bucket.state.lock().unwrap();
+                let now = Instant::now();
+                match state.paused_until {
+                    Some(until) if until > now => Some(until - now),
+                    _ => {
+                        state.paused_until = None;
+                        match &bucket.rate {
+                            Some(rate) => state.take(rate, now),
+                            None => None,
+                        }
+                    }
+                }
+            };
+            match wait {
+                Some(wait) => {
+                    debug!("Waiting {:?} for the rate limit of {}", wait, operation);
+                    tokio::time::sleep(wait).await;
+                }
+                None => return,
+            }
+        }
+    }
+
+    /// Slows down an operation rejected with a 429 Too Many Requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The rejected operation.
+    /// * `retry_after` - The delay asked by the API, if any, otherwise the time of a token at the lowered rate.
+    ///
+    /// # Returns
+    ///
+    /// * The time the operation is paused for, at most an hour.
+    pub fn throttle(&self, operation: &str, retry_after: Option<Duration>) -> Duration {
+        let retry_after = retry_after.map(|retry_after| retry_after.min(MAX_PAUSE));
+        let Some(bucket) = self.buckets.get(operation) else {
+            return retry_after.unwrap_or(DEFAULT_PAUSE);
+        };
+        let mut state = bucket.state.lock().unwrap();
+        let now = Instant::now();
+        state.factor = (state.factor / 2.0).max(MIN_FACTOR);
+        let pause = match (retry_after, &bucket.rate) {
+            (Some(retry_after), _) => retry_after,
+            (None, Some(rate)) => Duration::from_secs_f64(1.0 / state.per_second(rate)),
+            (None, None) => DEFAULT_PAUSE,
+        }
+        .min(MAX_PAUSE);
+        let until = now.checked_add(pause).unwrap_or(now);
+        state.paused_until = Some(state.paused_until.map_or(until, |paused| paused.max(until)));
+        state.tokens = 0.0;
+        state.refilled = state.paused_until.unwrap_or(now);
+        pause
+    }
+
+    /// Recovers part of the rate of an operation after a request accepted by the API.
+    pub fn recover(&self, operation: &str) {
+        if let Some(bucket) = self.buckets.get(operation) {
+            let mut state = bucket.state.lock().unwrap();
+            state.factor = (state.factor + RECOVERY_STEP).min(1.0);
+        }
+    }
+
+    /// Requests per minute currently allowed for an operation, lowered by the 429 responses, or `None` if unlimited.
+    pub fn current_rate(&self, operation: &str) -> Option<f64> {
+        let bucket = self.buckets.get(operation)?;
+        let rate = bucket.rate.as_ref()?;
+        Some(rate.per_minute * bucket.state.lock().unwrap().factor)
+    }
+}
+
+impl BucketState {
+    fn per_second(&self, rate: &Rate) -> f64 {
+        rate.per_minute / 60.0 * self.factor
+    }
+
+    /// Takes a token, or returns the time until the next one.
+    fn take(&mut self, rate: &Rate, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second(rate)).min(f64::from(rate.burst));
+        self.refilled = self.refilled.max(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.per_second(rate),
+            ))
+        }
+    }
+}
+
+/// Reads the `Retry-After` header of a response, in seconds or as an HTTP date.
+pub(crate) fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()
+            .map(|date| date.duration_since(SystemTime::now()).unwrap_or_default()),
+    }
+}
@@ -1,10 +1,9 @@
-use clap::ValueEnum;
 use reqwest::{header, Response};
-use std::{env, fmt::Display};
+use std::{env, fmt::Display, path::PathBuf, str::FromStr, time::Duration};
 use thiserror::Error;
 
 use dicom_object::{FileDicomObject, InMemDicomObject};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Represents errors that can occur within the `milvue_rs` library.
 #[derive(Error, Debug)]
@@ -69,13 +68,182 @@ pub enum MilvueError {
     #[error("More than one Study Instance UID found among files to be uploaded.")]
     StudyUidMismatch,
 
+    /// No DICOM file provided.
+    ///
+    /// Typically triggered when checking or uploading an empty list of DICOM files.
+    #[error("No DICOM file provided.")]
+    EmptyDicomList,
+
     /// No inference command provided.
     #[error("No inference command provided.")]
     NoInferenceCommand,
+
+    /// Error occurred when accessing a file.
+    ///
+    /// Typically triggered when a DICOM file to be uploaded doesn't exist or isn't readable.
+    #[error("Error accessing {0}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+
+    /// Error occurred when reading a configuration or API key file.
+    ///
+    /// Typically triggered when the file doesn't exist or isn't readable.
+    #[error("Error reading {0}: {1}")]
+    ConfigReadError(PathBuf, #[source] std::io::Error),
+
+    /// Error occurred when parsing a configuration file.
+    ///
+    /// Typically triggered when the TOML syntax is invalid or a value isn't recognized.
+    #[cfg(feature = "config")]
+    #[error("Error parsing the configuration: {0}")]
+    ConfigParseError(#[from] toml::de::Error),
+
+    /// Error occurred when serializing or parsing JSON.
+    ///
+    /// Typically triggered when a local JSON file, such as the submission cache, is corrupted.
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    /// The requested profile doesn't exist in the configuration.
+    #[error("Profile {0} not found in the configuration.")]
+    ProfileNotFound(String),
+
+    /// No API key provided, neither on the command line nor in the configuration.
+    #[error("No API key provided.")]
+    NoApiKey,
+
+    /// No API URL provided, neither on the command line nor in the configuration.
+    #[error("No API URL provided.")]
+    NoApiUrl,
+
+    /// Error occurred when parsing a parameter from a string.
+    ///
+    /// Typically triggered when the string isn't one of the values accepted by the Milvue API for this parameter.
+    #[error("Invalid {0} value: {1}")]
+    InvalidValue(&'static str, String),
+
+    /// Error occurred when a transfer syntax is unknown or cannot be produced.
+    ///
+    /// Typically triggered when a file uses a transfer syntax missing from the DICOM registry, or when transcoding to
+    /// a transfer syntax other than a native little endian one.
+    #[cfg(feature = "transcode")]
+    #[error("Unsupported transfer syntax: {0}")]
+    UnsupportedTransferSyntax(String),
+
+    /// No codec is available to decode the pixel data of a file.
+    ///
+    /// Typically triggered when transcoding JPEG 2000 or JPEG-LS files, which dicom-rs cannot decode yet.
+    #[cfg(feature = "transcode")]
+    #[error("No codec available to decode {1} ({0}) pixel data.")]
+    CodecUnavailable(String, String),
+
+    /// Error occurred when decoding pixel data.
+    ///
+    /// Typically triggered when transcoding a file with corrupted or inconsistent pixel data.
+    #[cfg(feature = "transcode")]
+    #[error("Error decoding the pixel data: {0}")]
+    PixelDataError(#[from] dicom::pixeldata::Error),
+
+    /// The upload of a study exceeded its deadline.
+    ///
+    /// Typically triggered when the network is slow or a connection is stalled during the upload.
+    #[error("Upload deadline of {0:?} exceeded.")]
+    UploadTimeout(Duration),
+
+    /// Milvue didn't finish processing a study before the deadline.
+    ///
+    /// Typically triggered when the Milvue API is overloaded, or when a study stays in an error state.
+    #[error("Processing deadline of {0:?} exceeded.")]
+    ProcessingTimeout(Duration),
+
+    /// The download of the results of a study exceeded its deadline.
+    ///
+    /// Typically triggered when the network is slow or a connection is stalled during the download.
+    #[error("Download deadline of {0:?} exceeded.")]
+    DownloadTimeout(Duration),
+
+    /// Error occurred when the circuit breaker of the client rejects a request to an environment, see
+    /// [crate::CircuitBreaker].
+    ///
+    /// Typically triggered when the environment is down or failing, the request can be sent again after the given
+    /// time.
+    #[error("Circuit open for {0}, requests are rejected for {1:?}.")]
+    CircuitOpen(String, Duration),
+
+    /// Error occurred when creating the runtime of the blocking API.
+    ///
+    /// Typically triggered when the operating system cannot create the threads or the I/O driver of the runtime.
+    #[cfg(feature = "blocking")]
+    #[error("Error creating the runtime of the blocking client: {0}")]
+    RuntimeError(#[source] std::io::Error),
+
+    /// Error occurred when verifying or opening an audit log, at the given line.
+    ///
+    /// Typically triggered when a record was modified, inserted or removed, or when the last record was only partially
+    /// written.
+    #[cfg(feature = "audit")]
+    #[error("Audit log broken at line {0}: {1}")]
+    AuditChainBroken(usize, String),
+}
+
+impl MilvueError {
+    /// Returns true if the error is a phase deadline or a request timeout.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            MilvueError::UploadTimeout(_)
+            | MilvueError::ProcessingTimeout(_)
+            | MilvueError::DownloadTimeout(_) => true,
+            MilvueError::RequestError(e) => e.is_timeout(),
+            _ => false,
+        }
+    }
+
+    /// Returns a stable identifier of the kind of error, e.g. `"upload_timeout"`, for logs and machine-readable
+    /// reports.
+    pub fn code(&self) -> &'static str {
+        match self {
+            MilvueError::HeaderCreationError(_) => "header_creation",
+            MilvueError::RequestError(_) => "request",
+            MilvueError::EnvVarNotFound(_) => "env_var_not_found",
+            MilvueError::NoContentType => "no_content_type",
+            MilvueError::ToStringError(_) => "header_to_string",
+            MilvueError::MulterError(_) => "multipart",
+            MilvueError::DicomObjectError(_) => "dicom_object",
+            MilvueError::DicomCastError(_) => "dicom_cast",
+            MilvueError::StatusResponseError(_) => "status_response",
+            MilvueError::StudyUidMismatch => "study_uid_mismatch",
+            MilvueError::EmptyDicomList => "empty_dicom_list",
+            MilvueError::NoInferenceCommand => "no_inference_command",
+            MilvueError::Io(_, _) => "io",
+            MilvueError::ConfigReadError(_, _) => "config_read",
+            #[cfg(feature = "config")]
+            MilvueError::ConfigParseError(_) => "config_parse",
+            MilvueError::JsonError(_) => "json",
+            MilvueError::ProfileNotFound(_) => "profile_not_found",
+            MilvueError::NoApiKey => "no_api_key",
+            MilvueError::NoApiUrl => "no_api_url",
+            MilvueError::InvalidValue(_, _) => "invalid_value",
+            #[cfg(feature = "transcode")]
+            MilvueError::UnsupportedTransferSyntax(_) => "unsupported_transfer_syntax",
+            #[cfg(feature = "transcode")]
+            MilvueError::CodecUnavailable(_, _) => "codec_unavailable",
+            #[cfg(feature = "transcode")]
+            MilvueError::PixelDataError(_) => "pixel_data",
+            MilvueError::UploadTimeout(_) => "upload_timeout",
+            MilvueError::ProcessingTimeout(_) => "processing_timeout",
+            MilvueError::DownloadTimeout(_) => "download_timeout",
+            MilvueError::CircuitOpen(_, _) => "circuit_open",
+            #[cfg(feature = "blocking")]
+            MilvueError::RuntimeError(_) => "runtime",
+            #[cfg(feature = "audit")]
+            MilvueError::AuditChainBroken(_, _) => "audit_chain_broken",
+        }
+    }
 }
 
 /// Enum representing possible Milvue URLs.
-#[derive(Default)]
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
 pub enum MilvueUrl {
     /// Development environment. Must be set as an environment variable with the key MILVUE_API_URL_DEV.
     Dev,
@@ -85,6 +253,8 @@ pub enum MilvueUrl {
     Prod,
     /// Default environment. Must be set as an environment variable with the key MILVUE_API_URL.
     #[default]
+    #[serde(rename = "default")]
+    #[cfg_attr(feature = "cli", value(name = "default"))]
     DefaultUrl,
 }
 
@@ -119,26 +289,56 @@ pub struct StatusResponse {
     pub message: String,
 }
 
-#[derive(Debug, Clone)]
+/// Represents the results of the [crate::get::get_many()] function, paired with their request configuration.
+///
+/// Every configuration requested is either in `results` or in `errors`, in the order of the request, a failure for
+/// one configuration doesn't prevent fetching the others. A configuration requested twice appears twice.
+#[derive(Debug, Default)]
+pub struct MultiGetResponse {
+    /// DICOM files for each configuration that succeeded, None when the study has no output for the configuration.
+    pub results: Vec<(MilvueParams, Option<Vec<FileDicomObject<InMemDicomObject>>>)>,
+    /// Error for each configuration that failed.
+    pub errors: Vec<(MilvueParams, MilvueError)>,
+}
+
+impl MultiGetResponse {
+    /// Returns true if every configuration was fetched successfully.
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 /// Represents the parameters to configure the Milvue request.
+///
+/// Serialized with the names and values of the query parameters sent to the Milvue API, unset parameters are
+/// skipped. Missing parameters are deserialized as None rather than as their [Default] value.
 pub struct MilvueParams {
     /// Whether or not to return a signed URL to handle the DICOM files instead of downloading them directly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub signed_url: Option<bool>,
     /// [OutputFormat]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub output_format: Option<OutputFormat>,
     /// [Language]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub language: Option<Language>,
     /// [InferenceCommand]
     pub inference_command: InferenceCommand,
     /// The timezone delay from UTC in hours. For example, if the timezone is UTC+2, the value should be +2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub timezone: Option<String>,
     /// [OutputSelection]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub output_selection: Option<OutputSelection>,
     /// [RecapTheme]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub recap_theme: Option<RecapTheme>,
     /// [StructuredReportFormat]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub structured_report_format: Option<StructuredReportFormat>,
     /// [StaticReportFormat]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub static_report_format: Option<StaticReportFormat>,
 }
 
@@ -205,7 +405,9 @@ impl Default for MilvueParams {
     }
 }
 
-#[derive(Clone, Debug, ValueEnum)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
 /// Represents the output format expected from the Milvue API.
 pub enum OutputFormat {
     /// Overlay contains a copy of the original image with the annotations in a separate dicom tag.
@@ -229,8 +431,25 @@ impl Display for OutputFormat {
     }
 }
 
+impl FromStr for OutputFormat {
+    type Err = MilvueError;
+
+    /// Parses the value used by the Milvue API, as produced by [Display].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overlay" => Ok(OutputFormat::Overlay),
+            "highbit" => Ok(OutputFormat::Highbit),
+            "gsps" => Ok(OutputFormat::Gsps),
+            "secondary_capture" => Ok(OutputFormat::SecondaryCapture),
+            _ => Err(MilvueError::InvalidValue("output_format", s.to_string())),
+        }
+    }
+}
+
 /// Represents the language of the annotations.
-#[derive(Clone, Debug, ValueEnum)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
 pub enum Language {
     /// French
     Fr,
@@ -259,7 +478,26 @@ impl Display for Language {
     }
 }
 
-#[derive(Clone, Debug, ValueEnum)]
+impl FromStr for Language {
+    type Err = MilvueError;
+
+    /// Parses the value used by the Milvue API, as produced by [Display].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fr" => Ok(Language::Fr),
+            "en" => Ok(Language::En),
+            "es" => Ok(Language::Es),
+            "de" => Ok(Language::De),
+            "it" => Ok(Language::It),
+            "pt" => Ok(Language::Pt),
+            _ => Err(MilvueError::InvalidValue("language", s.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
 /// Represents the inference command for the Milvue request.
 pub enum InferenceCommand {
     /// SmartUrgences yields the pathology detection.
@@ -277,7 +515,25 @@ impl Display for InferenceCommand {
     }
 }
 
-#[derive(Clone, Debug, ValueEnum)]
+impl FromStr for InferenceCommand {
+    type Err = MilvueError;
+
+    /// Parses the value used by the Milvue API, as produced by [Display].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "smarturgences" => Ok(InferenceCommand::SmartUrgences),
+            "smartxpert" => Ok(InferenceCommand::SmartXpert),
+            _ => Err(MilvueError::InvalidValue(
+                "inference_command",
+                s.to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
 /// Represents the output selection for the Milvue request.
 pub enum OutputSelection {
     /// All contains all the possible outputs including negatives and out of scope results.
@@ -301,7 +557,24 @@ impl Display for OutputSelection {
     }
 }
 
-#[derive(Clone, Debug, ValueEnum)]
+impl FromStr for OutputSelection {
+    type Err = MilvueError;
+
+    /// Parses the value used by the Milvue API, as produced by [Display].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(OutputSelection::All),
+            "no_recap" => Ok(OutputSelection::NoRecap),
+            "no_negatives" => Ok(OutputSelection::NoNegatives),
+            "none" => Ok(OutputSelection::None),
+            _ => Err(MilvueError::InvalidValue("output_selection", s.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
 /// Represents the recap theme for the Milvue request.
 pub enum RecapTheme {
     Dark,
@@ -317,10 +590,25 @@ impl Display for RecapTheme {
     }
 }
 
+impl FromStr for RecapTheme {
+    type Err = MilvueError;
+
+    /// Parses the value used by the Milvue API, as produced by [Display].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dark" => Ok(RecapTheme::Dark),
+            "light" => Ok(RecapTheme::Light),
+            _ => Err(MilvueError::InvalidValue("recap_theme", s.to_string())),
+        }
+    }
+}
+
 /// Represents the structured report format for the Milvue request.
 ///
 /// If set, this parameter will return a structured report in the specified format.
-#[derive(Clone, Debug, ValueEnum)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
 pub enum StructuredReportFormat {
     Lite,
     Normal,
@@ -339,7 +627,27 @@ impl Display for StructuredReportFormat {
     }
 }
 
-#[derive(Clone, Debug, ValueEnum)]
+impl FromStr for StructuredReportFormat {
+    type Err = MilvueError;
+
+    /// Parses the value used by the Milvue API, as produced by [Display].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lite" => Ok(StructuredReportFormat::Lite),
+            "normal" => Ok(StructuredReportFormat::Normal),
+            "full" => Ok(StructuredReportFormat::Full),
+            "none" => Ok(StructuredReportFormat::None),
+            _ => Err(MilvueError::InvalidValue(
+                "structured_report_format",
+                s.to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
 /// Represents the static report format for the Milvue request.
 pub enum StaticReportFormat {
     Rgb,
@@ -357,6 +665,23 @@ impl Display for StaticReportFormat {
     }
 }
 
+impl FromStr for StaticReportFormat {
+    type Err = MilvueError;
+
+    /// Parses the value used by the Milvue API, as produced by [Display].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rgb" => Ok(StaticReportFormat::Rgb),
+            "pdf" => Ok(StaticReportFormat::Pdf),
+            "none" => Ok(StaticReportFormat::None),
+            _ => Err(MilvueError::InvalidValue(
+                "static_report_format",
+                s.to_string(),
+            )),
+        }
+    }
+}
+
 /// Checks if all DICOM files in the provided list have the same StudyInstanceUID.
 ///
 /// # Arguments
@@ -366,11 +691,15 @@ impl Display for StaticReportFormat {
 /// # Returns
 ///
 /// * A Result wrapping a String representation of the StudyInstanceUID if all DICOM files have the same StudyInstanceUID,
-/// or an error if there is a mismatch.
+///   or an error if there is a mismatch or the list is empty.
+///
+/// See [crate::check_studies()] for a detailed report on the consistency of the files.
 pub fn check_study_uids(
     dicom_list: &[FileDicomObject<InMemDicomObject>],
 ) -> Result<String, MilvueError> {
-    let study_uid = dicom_list[0]
+    let study_uid = dicom_list
+        .first()
+        .ok_or(MilvueError::EmptyDicomList)?
         .element_by_name("StudyInstanceUID")?
         .to_str()?
         .to_string();
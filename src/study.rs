@@ -0,0 +1,259 @@
+use dicom_object::{FileDicomObject, InMemDicomObject};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::Hasher,
+};
+use tracing::warn;
+
+use crate::structs::MilvueError;
+
+/// Represents the result of [check_studies()]: the DICOM files grouped by StudyInstanceUID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StudyReport {
+    /// Studies in the order of their first file in the list.
+    pub studies: Vec<StudyGroup>,
+}
+
+impl StudyReport {
+    /// Returns true if all the files belong to a single study without any issue, i.e. they can be uploaded in a
+    /// single request.
+    pub fn is_consistent(&self) -> bool {
+        self.studies.len() == 1 && self.studies[0].issues.is_empty()
+    }
+
+    /// Iterates over every issue found, with the StudyInstanceUID of the study it was found in.
+    pub fn issues(&self) -> impl Iterator<Item = (&str, &StudyIssue)> {
+        self.studies.iter().flat_map(|study| {
+            study
+                .issues
+                .iter()
+                .map(move |issue| (study.study_instance_uid.as_str(), issue))
+        })
+    }
+}
+
+/// Represents the files of one study within the list given to [check_studies()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StudyGroup {
+    pub study_instance_uid: String,
+    /// Indices of the files of the study in the list.
+    pub indices: Vec<usize>,
+    /// Inconsistencies found among the files of the study.
+    pub issues: Vec<StudyIssue>,
+}
+
+/// Represents an inconsistency among the files of a study.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StudyIssue {
+    /// The files of the study have different PatientIDs, listed here. Missing values are listed as empty strings.
+    PatientIdMismatch(Vec<String>),
+    /// The files of the study have different AccessionNumbers, listed here. Missing values are listed as empty strings.
+    AccessionNumberMismatch(Vec<String>),
+    /// Several files share a SOPInstanceUID but have different contents.
+    ConflictingSopInstanceUid {
+        sop_instance_uid: String,
+        indices: Vec<usize>,
+    },
+    /// Several files are identical copies of the same SOP instance. Only the first one is kept by [split_studies()].
+    RepeatedSopInstance {
+        sop_instance_uid: String,
+        indices: Vec<usize>,
+    },
+}
+
+/// Represents a batch of files of a single study returned by [split_studies()], ready to be uploaded.
+#[derive(Debug)]
+pub struct StudyBatch {
+    pub study_instance_uid: String,
+    pub dicoms: Vec<FileDicomObject<InMemDicomObject>>,
+    /// Issues found in the study, see [StudyGroup].
+    pub issues: Vec<StudyIssue>,
+}
+
+/// Identifying attributes of a file.
+struct InstanceInfo {
+    study_instance_uid: String,
+    sop_instance_uid: String,
+    patient_id: String,
+    accession_number: String,
+    content_hash: u64,
+}
+
+/// Checks the consistency of a list of DICOM files before an upload.
+///
+/// The files are grouped by StudyInstanceUID, and within each study the PatientID and AccessionNumber must match and
+/// a SOPInstanceUID must not be used by files with different contents.
+///
+/// # Arguments
+///
+/// * `dicom_list` - A list of DICOM files to be checked.
+///
+/// # Returns
+///
+/// * A Result wrapping a [StudyReport], or an error if the list is empty or a file lacks a StudyInstanceUID or
+///   SOPInstanceUID.
+pub fn check_studies(
+    dicom_list: &[FileDicomObject<InMemDicomObject>],
+) -> Result<StudyReport, MilvueError> {
+    if dicom_list.is_empty() {
+        return Err(MilvueError::EmptyDicomList);
+    }
+    let infos = dicom_list
+        .iter()
+        .map(instance_info)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(build_report(&infos))
+}
+
+/// Splits a mixed list of DICOM files into one batch per study.
+///
+/// Identical copies of a SOP instance are dropped, other issues are reported in [StudyBatch::issues] and left to the
+/// caller to decide whether to upload the batch.
+///
+/// # Arguments
+///
+/// * `dicom_list` - A list of DICOM files, possibly from several studies.
+///
+/// # Returns
+///
+/// * A Result wrapping the batches in the order of the first file of each study, or an error as in [check_studies()].
+pub fn split_studies(
+    dicom_list: Vec<FileDicomObject<InMemDicomObject>>,
+) -> Result<Vec<StudyBatch>, MilvueError> {
+    let report = check_studies(&dicom_list)?;
+
+    let mut dropped = vec![false; dicom_list.len()];
+    for (study_instance_uid, issue) in report.issues() {
+        if let StudyIssue::RepeatedSopInstance {
+            sop_instance_uid,
+            indices,
+        } = issue
+        {
+            warn!(
+                "Dropping {} copies of SOP instance {} in study {}",
+                indices.len() - 1,
+                sop_instance_uid,
+                study_instance_uid
+            );
+            for &i in &indices[1..] {
+                dropped[i] = true;
+            }
+        }
+    }
+
+    let mut slots = dicom_list.into_iter().map(Some).collect::<Vec<_>>();
+    Ok(report
+        .studies
+        .into_iter()
+        .map(|study| StudyBatch {
+            dicoms: study
+                .indices
+                .iter()
+                .filter(|&&i| !dropped[i])
+                .filter_map(|&i| slots[i].take())
+                .collect(),
+            study_instance_uid: study.study_instance_uid,
+            issues: study.issues,
+        })
+        .collect())
+}
+
+fn instance_info(dicom: &FileDicomObject<InMemDicomObject>) -> Result<InstanceInfo, MilvueError> {
+    // the NUL (UIDs) and space padding must not split a study, whatever the trimming of `to_str()`
+    let trim = |value: &str| value.trim_end_matches([char::from(0), ' ']).to_string();
+    let optional_string = |name: &str| {
+        dicom
+            .element_by_name(name)
+            .ok()
+            .and_then(|element| element.to_str().ok())
+            .map(|value| trim(&value))
+            .unwrap_or_default()
+    };
+
+    let mut buffer = Vec::new();
+    dicom.write_all(&mut buffer)?;
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&buffer);
+
+    Ok(InstanceInfo {
+        study_instance_uid: trim(&dicom.element_by_name("StudyInstanceUID")?.to_str()?),
+        sop_instance_uid: trim(&dicom.element_by_name("SOPInstanceUID")?.to_str()?),
+        patient_id: optional_string("PatientID"),
+        accession_number: optional_string("AccessionNumber"),
+        content_hash: hasher.finish(),
+    })
+}
+
+fn build_report(infos: &[InstanceInfo]) -> StudyReport {
+    let mut studies: Vec<StudyGroup> = Vec::new();
+    let mut study_positions: HashMap<&str, usize> = HashMap::new();
+    for (i, info) in infos.iter().enumerate() {
+        let position = *study_positions
+            .entry(info.study_instance_uid.as_str())
+            .or_insert_with(|| {
+                studies.push(StudyGroup {
+                    study_instance_uid: info.study_instance_uid.clone(),
+                    indices: Vec::new(),
+                    issues: Vec::new(),
+                });
+                studies.len() - 1
+            });
+        studies[position].indices.push(i);
+    }
+
+    for study in &mut studies {
+        let patient_ids = distinct(study.indices.iter().map(|&i| &infos[i].patient_id));
+        if patient_ids.len() > 1 {
+            study
+                .issues
+                .push(StudyIssue::PatientIdMismatch(patient_ids));
+        }
+        let accession_numbers = distinct(study.indices.iter().map(|&i| &infos[i].accession_number));
+        if accession_numbers.len() > 1 {
+            study
+                .issues
+                .push(StudyIssue::AccessionNumberMismatch(accession_numbers));
+        }
+
+        let mut instances: HashMap<&str, Vec<usize>> = HashMap::new();
+        for &i in &study.indices {
+            instances
+                .entry(infos[i].sop_instance_uid.as_str())
+                .or_default()
+                .push(i);
+        }
+        let mut duplicates = instances
+            .into_iter()
+            .filter(|(_, indices)| indices.len() > 1)
+            .collect::<Vec<_>>();
+        duplicates.sort_by_key(|(_, indices)| indices[0]);
+        for (sop_instance_uid, indices) in duplicates {
+            let first_hash = infos[indices[0]].content_hash;
+            let sop_instance_uid = sop_instance_uid.to_string();
+            if indices.iter().all(|&i| infos[i].content_hash == first_hash) {
+                study.issues.push(StudyIssue::RepeatedSopInstance {
+                    sop_instance_uid,
+                    indices,
+                });
+            } else {
+                study.issues.push(StudyIssue::ConflictingSopInstanceUid {
+                    sop_instance_uid,
+                    indices,
+                });
+            }
+        }
+    }
+
+    StudyReport { studies }
+}
+
+/// Distinct values in order of appearance.
+fn distinct<'a>(values: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut distinct: Vec<String> = Vec::new();
+    for value in values {
+        if !distinct.contains(value) {
+            distinct.push(value.clone());
+        }
+    }
+    distinct
+}
@@ -0,0 +1,169 @@
+use dicom::{
+    core::{DataElement, PrimitiveValue, VR},
+    encoding::TransferSyntaxIndex,
+    pixeldata::PixelDecoder,
+    transfer_syntax::TransferSyntaxRegistry,
+};
+use dicom_dictionary_std::tags;
+use dicom_object::{FileDicomObject, InMemDicomObject};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::structs::MilvueError;
+
+/// UID of the Implicit VR Little Endian transfer syntax.
+pub const IMPLICIT_VR_LITTLE_ENDIAN: &str = "1.2.840.10008.1.2";
+/// UID of the Explicit VR Little Endian transfer syntax.
+pub const EXPLICIT_VR_LITTLE_ENDIAN: &str = "1.2.840.10008.1.2.1";
+
+/// Transfer syntaxes [transcode()] can convert files to.
+const TARGET_TRANSFER_SYNTAXES: [&str; 2] = [EXPLICIT_VR_LITTLE_ENDIAN, IMPLICIT_VR_LITTLE_ENDIAN];
+
+/// Represents which transfer syntaxes are accepted as is, and which one the other files are converted to.
+///
+/// Used before an upload to send Milvue files it handles well, and after a download to store the results in the
+/// transfer syntax expected by the PACS. Example in a configuration profile:
+///
+/// ```toml
+/// [profiles.prod.transcode_uploads]
+/// accepted = ["1.2.840.10008.1.2.1", "1.2.840.10008.1.2"]
+/// target = "1.2.840.10008.1.2.1"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TranscodePolicy {
+    /// Transfer syntaxes left untouched, the target is always accepted.
+    #[serde(default)]
+    pub accepted: Vec<String>,
+    /// Transfer syntax the other files are converted to, Explicit or Implicit VR Little Endian.
+    pub target: String,
+}
+
+impl Default for TranscodePolicy {
+    /// Accepts the native little endian transfer syntaxes, converts everything else to Explicit VR Little Endian.
+    fn default() -> Self {
+        TranscodePolicy {
+            accepted: vec![
+                EXPLICIT_VR_LITTLE_ENDIAN.to_string(),
+                IMPLICIT_VR_LITTLE_ENDIAN.to_string(),
+            ],
+            target: EXPLICIT_VR_LITTLE_ENDIAN.to_string(),
+        }
+    }
+}
+
+impl TranscodePolicy {
+    /// Creates a policy converting every file to `target`.
+    pub fn new(target: &str) -> Self {
+        TranscodePolicy {
+            accepted: Vec::new(),
+            target: target.to_string(),
+        }
+    }
+
+    /// Returns true if files in this transfer syntax are left untouched.
+    pub fn accepts(&self, transfer_syntax: &str) -> bool {
+        let transfer_syntax = trim_uid(transfer_syntax);
+        transfer_syntax == trim_uid(&self.target)
+            || self
+                .accepted
+                .iter()
+                .any(|accepted| trim_uid(accepted) == transfer_syntax)
+    }
+}
+
+/// Converts a DICOM object to the transfer syntax required by a policy.
+///
+/// Compressed pixel data is decoded with the codecs of dicom-rs (JPEG baseline, extended and lossless, RLE lossless)
+/// and stored natively; big endian files are rewritten in little endian. Objects in an accepted transfer syntax are
+/// returned unchanged.
+///
+/// # Arguments
+///
+/// * `dicom` - The DICOM object to convert.
+/// * `policy` - The accepted and target transfer syntaxes.
+///
+/// # Returns
+///
+/// * A Result wrapping the converted object, or an error if the target is not a native little endian transfer syntax,
+///   or if no codec is available for the pixel data, e.g. JPEG 2000 or JPEG-LS.
+pub fn transcode(
+    mut dicom: FileDicomObject<InMemDicomObject>,
+    policy: &TranscodePolicy,
+) -> Result<FileDicomObject<InMemDicomObject>, MilvueError> {
+    let source_uid = trim_uid(dicom.meta().transfer_syntax()).to_string();
+    if policy.accepts(&source_uid) {
+        return Ok(dicom);
+    }
+
+    let target_uid = trim_uid(&policy.target);
+    let target = TARGET_TRANSFER_SYNTAXES
+        .contains(&target_uid)
+        .then(|| TransferSyntaxRegistry.get(target_uid))
+        .flatten()
+        .ok_or_else(|| MilvueError::UnsupportedTransferSyntax(target_uid.to_string()))?;
+    let source = TransferSyntaxRegistry
+        .get(&source_uid)
+        .ok_or_else(|| MilvueError::UnsupportedTransferSyntax(source_uid.clone()))?;
+
+    let encapsulated = dicom
+        .element_opt(tags::PIXEL_DATA)?
+        .map(|pixel_data| pixel_data.value().fragments().is_some())
+        .unwrap_or(false);
+    if encapsulated {
+        if !source.fully_supported() {
+            return Err(MilvueError::CodecUnavailable(
+                source_uid,
+                source.name().to_string(),
+            ));
+        }
+        let decoded = dicom.decode_pixel_data()?;
+        let pixel_data = if decoded.bits_allocated() <= 8 {
+            DataElement::new(
+                tags::PIXEL_DATA,
+                VR::OB,
+                PrimitiveValue::U8(decoded.data().into()),
+            )
+        } else {
+            DataElement::new(
+                tags::PIXEL_DATA,
+                VR::OW,
+                PrimitiveValue::U16(decoded.data_ow().into()),
+            )
+        };
+        // the decoders report MONOCHROME2 for every grayscale image without inverting its pixels, so the source
+        // interpretation is kept, otherwise a MONOCHROME1 image would be uploaded with inverted grayscale
+        let samples_per_pixel = decoded.samples_per_pixel();
+        let photometric_interpretation = (samples_per_pixel > 1)
+            .then(|| decoded.photometric_interpretation().as_str().to_string());
+        drop(decoded);
+
+        dicom.put(pixel_data);
+        if let Some(photometric_interpretation) = photometric_interpretation {
+            dicom.put(DataElement::new(
+                tags::PHOTOMETRIC_INTERPRETATION,
+                VR::CS,
+                PrimitiveValue::from(photometric_interpretation),
+            ));
+            dicom.put(DataElement::new(
+                tags::PLANAR_CONFIGURATION,
+                VR::US,
+                PrimitiveValue::from(0_u16),
+            ));
+        }
+    }
+
+    dicom.meta_mut().set_transfer_syntax(target);
+    info!(
+        "Transcoded {} from {} to {}",
+        dicom.meta().media_storage_sop_instance_uid(),
+        source.name(),
+        target.name()
+    );
+    Ok(dicom)
+}
+
+/// UIDs may be padded with a null character.
+fn trim_uid(uid: &str) -> &str {
+    uid.trim_end_matches([char::from(0), ' '])
+}
@@ -0,0 +1,98 @@
+use dicom::core::{DataElement, PrimitiveValue, VR};
+use dicom_dictionary_std::tags;
+use dicom_object::{FileDicomObject, FileMetaTableBuilder, InMemDicomObject};
+use milvue_rs::{
+    DicomSource, InMemoryMilvue, InferenceCommand, MilvueApi, MilvueError, MilvueParams,
+};
+
+/// SOP class of the test objects.
+const DIGITAL_XRAY_IMAGE_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.1.1";
+
+/// Transfer syntax of the test objects.
+const EXPLICIT_VR_LITTLE_ENDIAN: &str = "1.2.840.10008.1.2.1";
+
+/// Builds a minimal DICOM object of a study.
+fn dicom(study_instance_uid: &str, sop_instance_uid: &str) -> FileDicomObject<InMemDicomObject> {
+    InMemDicomObject::from_element_iter([
+        DataElement::new(
+            tags::SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from(DIGITAL_XRAY_IMAGE_STORAGE),
+        ),
+        DataElement::new(
+            tags::SOP_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from(sop_instance_uid),
+        ),
+        DataElement::new(
+            tags::STUDY_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from(study_instance_uid),
+        ),
+    ])
+    .with_meta(
+        FileMetaTableBuilder::new()
+            .transfer_syntax(EXPLICIT_VR_LITTLE_ENDIAN)
+            .media_storage_sop_class_uid(DIGITAL_XRAY_IMAGE_STORAGE),
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn in_memory_round_trip() {
+    let params = MilvueParams::default();
+    let api = InMemoryMilvue::new().with_results("1.2.3", vec![dicom("1.2.3", "1.2.3.9")]);
+    let progress = Default::default();
+
+    api.submit(
+        vec![
+            DicomSource::from(dicom("1.2.3", "1.2.3.1")),
+            DicomSource::from(dicom("1.2.3", "1.2.3.2")),
+        ],
+        &progress,
+    )
+    .await
+    .unwrap();
+    assert_eq!(api.submitted_studies(), vec!["1.2.3".to_string()]);
+    assert_eq!(api.submitted("1.2.3").unwrap().len(), 2);
+
+    assert_eq!(api.status("1.2.3").await.unwrap().status, "done");
+    api.wait("1.2.3", &progress).await.unwrap();
+    let results = api.fetch("1.2.3", &params, &progress).await.unwrap();
+    assert_eq!(results.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn in_memory_results_by_params() {
+    let xpert = MilvueParams {
+        inference_command: InferenceCommand::SmartXpert,
+        ..Default::default()
+    };
+    let api: Box<dyn MilvueApi> = Box::new(
+        InMemoryMilvue::new()
+            .with_results_for("1.2.3", &xpert, vec![dicom("1.2.3", "1.2.3.9")])
+            .with_status("1.2.3", "running"),
+    );
+    let progress = Default::default();
+
+    assert!(matches!(
+        api.wait("1.2.3", &progress).await,
+        Err(MilvueError::ProcessingTimeout(_))
+    ));
+    assert!(api
+        .fetch("1.2.3", &MilvueParams::default(), &progress)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(api
+        .fetch("1.2.3", &xpert, &progress)
+        .await
+        .unwrap()
+        .is_some());
+    match api.status("4.5.6").await {
+        Err(MilvueError::StatusResponseError(response)) => {
+            assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND)
+        }
+        other => panic!("Expected a 404, got {:?}", other),
+    }
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "audit")]
+
+use std::{fs, path::PathBuf};
+
+use milvue_rs::{
+    audit::{verify, AuditLog, AuditOperation, AuditOutcome, AuditRecord, GENESIS_HASH},
+    MilvueError,
+};
+
+/// Returns a path in a fresh temporary directory of the test.
+fn log_path(test: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("milvue_rs-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    directory.join("audit.jsonl")
+}
+
+/// Builds a chain of upload records.
+fn chain(length: u64) -> Vec<AuditRecord> {
+    let mut previous_hash = GENESIS_HASH.to_string();
+    (1..=length)
+        .map(|sequence| {
+            let mut record = AuditRecord {
+                sequence,
+                timestamp: 1_700_000_000 + sequence,
+                operator: "gateway".to_string(),
+                url: "https://api.milvue.com".to_string(),
+                operation: AuditOperation::Upload,
+                study_instance_uid: Some(format!("1.2.3.{}", sequence)),
+                study_instance_uid_hashed: false,
+                inference_command: None,
+                instances: 2,
+                bytes: 1024,
+                outcome: AuditOutcome::Success,
+                error: None,
+                previous_hash: previous_hash.clone(),
+                hash: String::new(),
+            };
+            record.hash = record.compute_hash().unwrap();
+            previous_hash = record.hash.clone();
+            record
+        })
+        .collect()
+}
+
+fn write(path: &PathBuf, records: &[AuditRecord]) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let lines: Vec<String> = records
+        .iter()
+        .map(|record| serde_json::to_string(record).unwrap() + "\n")
+        .collect();
+    fs::write(path, lines.concat()).unwrap();
+}
+
+#[test]
+fn modified_and_removed_records_break_the_chain() {
+    let path = log_path("broken");
+    let records = chain(3);
+    write(&path, &records);
+    let summary = verify(&path).unwrap();
+    assert_eq!(summary.records, 3);
+    assert_eq!(summary.last_hash, records[2].hash);
+
+    let mut modified = records.clone();
+    modified[1].bytes = 1;
+    write(&path, &modified);
+    assert!(matches!(
+        verify(&path),
+        Err(MilvueError::AuditChainBroken(2, _))
+    ));
+
+    write(&path, &[records[0].clone(), records[2].clone()]);
+    assert!(matches!(
+        verify(&path),
+        Err(MilvueError::AuditChainBroken(2, _))
+    ));
+}
+
+#[test]
+fn opening_a_log_checks_its_last_record() {
+    let path = log_path("open");
+    assert!(AuditLog::open(&path, "gateway").is_ok());
+
+    write(&path, &chain(2));
+    assert!(AuditLog::open(&path, "gateway").is_ok());
+
+    let mut content = fs::read_to_string(&path).unwrap();
+    content.truncate(content.len() - 10);
+    fs::write(&path, content).unwrap();
+    assert!(matches!(
+        AuditLog::open(&path, "gateway"),
+        Err(MilvueError::AuditChainBroken(2, _))
+    ));
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use common::Reply;
+use milvue_rs::{
+    auth::{AuthScheme, Authenticator, EnvKey, KeyFile, OAuth2ClientCredentials, StaticKey},
+    MilvueError,
+};
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers.get(name).unwrap().to_str().unwrap()
+}
+
+#[test]
+fn schemes_format_their_header() {
+    let headers = AuthScheme::MilvueKey.headers("key").unwrap();
+    assert_eq!(header(&headers, "x-goog-meta-owner"), "key");
+    assert!(headers.get("x-goog-meta-owner").unwrap().is_sensitive());
+
+    let headers = AuthScheme::Bearer.headers("token").unwrap();
+    assert_eq!(headers.get(AUTHORIZATION).unwrap(), "Bearer token");
+    assert!(headers.get(AUTHORIZATION).unwrap().is_sensitive());
+
+    let headers = AuthScheme::Header("x-api-key".to_string())
+        .headers("key")
+        .unwrap();
+    assert_eq!(headers.len(), 1);
+    assert_eq!(header(&headers, "x-api-key"), "key");
+
+    assert!(matches!(
+        AuthScheme::Header("not a header".to_string()).headers("key"),
+        Err(MilvueError::InvalidValue(_, _))
+    ));
+    assert!(AuthScheme::MilvueKey.headers("line\nbreak").is_err());
+}
+
+#[tokio::test]
+async fn static_keys_are_redacted() {
+    let key = StaticKey::new("s3cr3t").with_scheme(AuthScheme::Bearer);
+    assert!(!format!("{:?}", key).contains("s3cr3t"));
+    assert_eq!(
+        key.headers().await.unwrap().get(AUTHORIZATION).unwrap(),
+        "Bearer s3cr3t"
+    );
+}
+
+#[tokio::test]
+async fn key_files_are_read_again_before_every_request() {
+    let path = std::env::temp_dir().join(format!("milvue_rs-auth-{}", std::process::id()));
+    let key = KeyFile::new(&path);
+    assert!(matches!(key.headers().await, Err(MilvueError::Io(_, _))));
+
+    std::fs::write(&path, "first\n").unwrap();
+    assert_eq!(
+        header(&key.headers().await.unwrap(), "x-goog-meta-owner"),
+        "first"
+    );
+    std::fs::write(&path, "  second  ").unwrap();
+    assert_eq!(
+        header(&key.headers().await.unwrap(), "x-goog-meta-owner"),
+        "second"
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn environment_keys_must_be_set() {
+    let key = EnvKey::new("MILVUE_RS_TEST_AUTH_KEY").with_scheme(AuthScheme::Bearer);
+    assert!(matches!(
+        key.headers().await,
+        Err(MilvueError::EnvVarNotFound(var)) if var == "MILVUE_RS_TEST_AUTH_KEY"
+    ));
+
+    std::env::set_var("MILVUE_RS_TEST_AUTH_KEY", "rotated");
+    assert_eq!(
+        key.headers().await.unwrap().get(AUTHORIZATION).unwrap(),
+        "Bearer rotated"
+    );
+}
+
+#[tokio::test]
+async fn oauth2_tokens_are_cached_until_invalidated() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    let url = common::serve(move |method, path| {
+        assert_eq!((method, path), ("POST", "/token"));
+        let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+        Reply::json(
+            200,
+            &format!(r#"{{"access_token": "token-{}", "expires_in": 3600}}"#, n),
+        )
+    })
+    .await;
+    let credentials = OAuth2ClientCredentials::new(&format!("{}/token", url), "id", "s3cr3t");
+    assert!(!format!("{:?}", credentials).contains("s3cr3t"));
+
+    let bearer = |headers: HeaderMap| headers.get(AUTHORIZATION).unwrap().clone();
+    assert_eq!(
+        bearer(credentials.headers().await.unwrap()),
+        "Bearer token-1"
+    );
+    assert_eq!(
+        bearer(credentials.headers().await.unwrap()),
+        "Bearer token-1"
+    );
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    credentials.invalidate();
+    assert_eq!(
+        bearer(credentials.headers().await.unwrap()),
+        "Bearer token-2"
+    );
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn a_rejected_token_request_is_an_error() {
+    let url = common::serve(|_, _| Reply::json(401, r#"{"error": "invalid_client"}"#)).await;
+    let credentials = OAuth2ClientCredentials::new(&url, "id", "wrong");
+    assert!(matches!(
+        credentials.headers().await,
+        Err(MilvueError::StatusResponseError(response)) if response.status() == 401
+    ));
+}
+
+#[tokio::test]
+async fn an_endless_token_lifetime_never_expires() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    let url = common::serve(move |_, _| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Reply::json(
+            200,
+            r#"{"access_token": "token", "expires_in": 18446744073709551615}"#,
+        )
+    })
+    .await;
+    let credentials = OAuth2ClientCredentials::new(&url, "id", "secret");
+
+    for _ in 0..2 {
+        assert_eq!(
+            credentials
+                .headers()
+                .await
+                .unwrap()
+                .get(AUTHORIZATION)
+                .unwrap(),
+            "Bearer token"
+        );
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+}
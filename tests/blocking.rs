@@ -0,0 +1,33 @@
+#![cfg(feature = "blocking")]
+
+use milvue_rs::{blocking, MilvueError};
+
+// nothing listens on port 1
+const UNREACHABLE: &str = "http://127.0.0.1:1";
+
+#[test]
+fn free_functions_report_unreachable_environments() {
+    for _ in 0..3 {
+        let error = blocking::get_study_status_with_url(UNREACHABLE, "key", "1.2.3").unwrap_err();
+        assert!(matches!(error, MilvueError::RequestError(_)), "{:?}", error);
+    }
+}
+
+#[test]
+fn clients_share_the_runtime_across_threads() {
+    let client = blocking::MilvueClient::with_api_key(UNREACHABLE, "key").unwrap();
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let client = client.clone();
+            std::thread::spawn(move || {
+                let other = blocking::MilvueClient::with_api_key(UNREACHABLE, "key").unwrap();
+                assert!(client.get_study_status("1.2.3").is_err());
+                assert!(other.get_study_status("1.2.3").is_err());
+                assert!(blocking::wait_for_done_with_url(UNREACHABLE, "key", "1.2.3").is_err());
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
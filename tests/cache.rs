@@ -0,0 +1,116 @@
+#![cfg(feature = "cache")]
+
+use std::path::PathBuf;
+
+use milvue_rs::{
+    cache::{hash_file, submission_key, CacheEntry, SubmissionCache},
+    InferenceCommand, Language, MilvueParams,
+};
+
+fn params(inference_command: InferenceCommand) -> MilvueParams {
+    MilvueParams {
+        inference_command,
+        ..Default::default()
+    }
+}
+
+fn instances(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(sop_instance_uid, hash)| (sop_instance_uid.to_string(), hash.to_string()))
+        .collect()
+}
+
+#[test]
+fn the_key_ignores_the_order_of_the_instances_and_configurations() {
+    let requested = [
+        params(InferenceCommand::SmartUrgences),
+        params(InferenceCommand::SmartXpert),
+    ];
+    let reversed = [requested[1].clone(), requested[0].clone()];
+    let key = submission_key(
+        "1.2.3",
+        &instances(&[("1.2.3.1", "aa"), ("1.2.3.2", "bb")]),
+        &requested,
+    )
+    .unwrap();
+
+    assert_eq!(
+        key,
+        submission_key(
+            "1.2.3",
+            &instances(&[("1.2.3.2", "bb"), ("1.2.3.1", "aa")]),
+            &reversed,
+        )
+        .unwrap()
+    );
+    assert_eq!(key.len(), 64);
+}
+
+#[test]
+fn the_key_changes_with_the_study_the_files_and_the_parameters() {
+    let study = instances(&[("1.2.3.1", "aa")]);
+    let requested = [params(InferenceCommand::SmartUrgences)];
+    let key = submission_key("1.2.3", &study, &requested).unwrap();
+
+    let mut english = requested[0].clone();
+    english.language = Some(Language::En);
+    for other in [
+        submission_key("1.2.4", &study, &requested),
+        submission_key("1.2.3", &instances(&[("1.2.3.1", "ab")]), &requested),
+        submission_key(
+            "1.2.3",
+            &instances(&[("1.2.3.1", "aa"), ("1.2.3.2", "bb")]),
+            &requested,
+        ),
+        submission_key("1.2.3", &study, &[params(InferenceCommand::SmartXpert)]),
+        submission_key("1.2.3", &study, &[english]),
+    ] {
+        assert_ne!(key, other.unwrap());
+    }
+}
+
+#[test]
+fn the_cache_survives_a_save_and_open() {
+    let directory = std::env::temp_dir().join(format!("milvue_rs-cache-{}", std::process::id()));
+    let path = directory.join("nested").join("submissions.json");
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let mut cache = SubmissionCache::open(&path).unwrap();
+    assert!(cache.get("key").is_none());
+    let entry = CacheEntry::new(
+        "1.2.3",
+        "https://api.milvue.com",
+        vec![PathBuf::from("results/1.dcm")],
+    );
+    cache.insert("key".to_string(), entry.clone());
+    cache.insert("other".to_string(), entry.clone());
+    assert!(cache.remove("other").is_some());
+    cache.save().unwrap();
+
+    // written through a temporary file renamed over the cache
+    assert!(path.is_file());
+    assert!(!path.with_extension("json.tmp").exists());
+    let reopened = SubmissionCache::open(&path).unwrap();
+    assert_eq!(reopened.get("key"), Some(&entry));
+    assert!(reopened.get("other").is_none());
+    assert!(!entry.outputs_exist());
+
+    // a second save replaces the existing file
+    let mut cache = reopened;
+    cache.remove("key");
+    cache.save().unwrap();
+    assert!(SubmissionCache::open(&path).unwrap().get("key").is_none());
+    assert!(!path.with_extension("json.tmp").exists());
+
+    std::fs::write(&path, "not json").unwrap();
+    assert!(SubmissionCache::open(&path).is_err());
+
+    let file = directory.join("file");
+    std::fs::write(&file, "abc").unwrap();
+    assert_eq!(
+        hash_file(&file).unwrap(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use common::Reply;
+use milvue_rs::{
+    auth::KeyFile, CircuitBreaker, CircuitBreakerSettings, CircuitState, MilvueClient, MilvueError,
+};
+
+fn settings() -> CircuitBreakerSettings {
+    CircuitBreakerSettings {
+        failure_threshold: Some(1),
+        open_duration: Some(Duration::from_millis(50)),
+    }
+}
+
+#[tokio::test]
+async fn requests_are_rejected_while_the_environment_is_down() {
+    // nothing listens on port 1
+    let client = MilvueClient::builder()
+        .url("http://127.0.0.1:1")
+        .api_key("key")
+        .circuit_breaker(CircuitBreakerSettings {
+            failure_threshold: Some(2),
+            open_duration: Some(Duration::from_millis(100)),
+        })
+        .build()
+        .unwrap();
+    let circuit_breaker = client.circuit_breaker().unwrap();
+
+    for _ in 0..2 {
+        let error = client.get_study_status("1.2.3").await.unwrap_err();
+        assert!(matches!(error, MilvueError::RequestError(_)));
+    }
+    assert_eq!(circuit_breaker.state(), CircuitState::Open);
+    let error = client.get_study_status("1.2.3").await.unwrap_err();
+    assert_eq!(error.code(), "circuit_open");
+
+    // the probe fails and opens the circuit again
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    let error = client.get_study_status("1.2.3").await.unwrap_err();
+    assert!(matches!(error, MilvueError::CircuitOpen(_, _)));
+    assert_eq!(circuit_breaker.state(), CircuitState::Open);
+
+    // a response to a request sent before the circuit opened doesn't close it
+    circuit_breaker.record_success();
+    assert_eq!(circuit_breaker.state(), CircuitState::Open);
+}
+
+#[tokio::test]
+async fn a_failed_probe_opens_the_circuit_again_and_an_answer_closes_it() {
+    let status = Arc::new(AtomicU16::new(500));
+    let url = {
+        let status = status.clone();
+        common::serve(move |_, _| Reply::json(status.load(Ordering::SeqCst), "{}")).await
+    };
+    let client = MilvueClient::builder()
+        .url(&url)
+        .api_key("key")
+        .circuit_breaker(settings())
+        .build()
+        .unwrap();
+    let circuit_breaker = client.circuit_breaker().unwrap();
+
+    assert!(client.get_study_status("1.2.3").await.is_err());
+    assert_eq!(circuit_breaker.state(), CircuitState::Open);
+
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    let error = client.get_study_status("1.2.3").await.unwrap_err();
+    assert!(matches!(error, MilvueError::CircuitOpen(_, _)));
+    assert_eq!(circuit_breaker.state(), CircuitState::Open);
+
+    // the probe of the unknown study gets a 404, the API answers again
+    status.store(404, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    assert!(matches!(
+        client.get_study_status("1.2.3").await,
+        Err(MilvueError::StatusResponseError(_))
+    ));
+    assert_eq!(circuit_breaker.state(), CircuitState::Closed);
+}
+
+#[tokio::test]
+async fn a_probe_which_cannot_be_authenticated_opens_the_circuit_again() {
+    // nothing listens on port 1, the probe fails before any request
+    let client = MilvueClient::builder()
+        .url("http://127.0.0.1:1")
+        .authenticator(KeyFile::new("/nonexistent/milvue.key"))
+        .circuit_breaker(settings())
+        .build()
+        .unwrap();
+    let circuit_breaker = client.circuit_breaker().unwrap();
+    circuit_breaker.record_failure();
+    assert_eq!(circuit_breaker.state(), CircuitState::Open);
+
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    let error = client.get_study_status("1.2.3").await.unwrap_err();
+    assert!(matches!(error, MilvueError::CircuitOpen(_, _)));
+    assert_eq!(circuit_breaker.state(), CircuitState::Open);
+}
+
+#[test]
+fn successes_only_close_a_half_open_circuit() {
+    let circuit_breaker = CircuitBreaker::new(&CircuitBreakerSettings {
+        failure_threshold: Some(2),
+        open_duration: Some(Duration::ZERO),
+    });
+    circuit_breaker.record_failure();
+    circuit_breaker.record_success();
+    circuit_breaker.record_failure();
+    assert_eq!(circuit_breaker.state(), CircuitState::Closed);
+
+    circuit_breaker.record_failure();
+    assert_eq!(circuit_breaker.state(), CircuitState::Open);
+    circuit_breaker.record_success();
+    assert_eq!(circuit_breaker.state(), CircuitState::Open);
+}
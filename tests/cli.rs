@@ -0,0 +1,246 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use std::{
+    path::{Path, PathBuf},
+    process::Output,
+    sync::{Arc, Mutex},
+};
+
+use common::Reply;
+use dicom::core::{DataElement, PrimitiveValue, VR};
+use dicom_dictionary_std::tags;
+use dicom_object::{FileMetaTableBuilder, InMemDicomObject};
+use serde_json::Value;
+
+/// SOP class of the test objects.
+const DIGITAL_XRAY_IMAGE_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.1.1";
+
+/// Directory of a test, removed at the end of the test.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("milvue_rs-cli-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs the binary with `args`, without the configuration files of the user.
+async fn run(home: &Path, args: &[&str]) -> Output {
+    tokio::process::Command::new(env!("CARGO_BIN_EXE_milvue_rs"))
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home)
+        .env_remove("RUST_LOG")
+        .output()
+        .await
+        .unwrap()
+}
+
+/// Parses the JSON events printed on stdout.
+fn events(output: &Output) -> Vec<Value> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+/// Writes a minimal DICOM file of the study 1.2.3 in `directory`.
+fn write_dicom(directory: &Path) {
+    InMemDicomObject::from_element_iter([
+        DataElement::new(
+            tags::SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from(DIGITAL_XRAY_IMAGE_STORAGE),
+        ),
+        DataElement::new(
+            tags::SOP_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.3.1"),
+        ),
+        DataElement::new(
+            tags::STUDY_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.3"),
+        ),
+    ])
+    .with_meta(
+        FileMetaTableBuilder::new()
+            .transfer_syntax("1.2.840.10008.1.2.1")
+            .media_storage_sop_class_uid(DIGITAL_XRAY_IMAGE_STORAGE)
+            .media_storage_sop_instance_uid("1.2.3.1"),
+    )
+    .unwrap()
+    .write_to_file(directory.join("1.dcm"))
+    .unwrap();
+}
+
+#[tokio::test]
+async fn an_invalid_input_directory_is_a_fatal_error() {
+    let home = TempDir::new("fatal");
+    let missing = home.0.join("missing");
+
+    let output = run(
+        &home.0,
+        &[
+            missing.to_str().unwrap(),
+            "--output",
+            "json",
+            "-k",
+            "key",
+            "-a",
+            "http://127.0.0.1:1",
+            "-u",
+        ],
+    )
+    .await;
+
+    assert_eq!(output.status.code(), Some(1));
+    let events = events(&output);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["event"], "error");
+    assert_eq!(events[0]["code"], "invalid_input");
+}
+
+#[tokio::test]
+async fn a_failed_upload_fails_its_study_without_polling() {
+    let home = TempDir::new("upload");
+    let input = home.0.join("input");
+    std::fs::create_dir_all(&input).unwrap();
+    write_dicom(&input);
+    let url = common::serve(|method, _| match method {
+        "POST" => Reply::json(400, r#"{"detail": "invalid study"}"#),
+        _ => Reply::json(200, r#"{"status": "done"}"#),
+    })
+    .await;
+
+    let output = run(
+        &home.0,
+        &[
+            input.to_str().unwrap(),
+            "--output",
+            "json",
+            "-k",
+            "key",
+            "-a",
+            &url,
+            "-u",
+            "-o",
+            home.0.join("results").to_str().unwrap(),
+        ],
+    )
+    .await;
+
+    assert_eq!(output.status.code(), Some(3));
+    let events = events(&output);
+    let kinds: Vec<&str> = events
+        .iter()
+        .map(|event| event["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, ["error", "finished", "summary"]);
+    assert_eq!(events[0]["phase"], "upload");
+    assert_eq!(events[0]["http_status"], 400);
+    assert_eq!(events[1]["study_instance_uid"], "1.2.3");
+    assert_eq!(events[1]["outcome"], "failed");
+    assert_eq!(events[2]["failed"], 1);
+    assert_eq!(events[2]["exit_code"], 3);
+}
+
+#[tokio::test]
+async fn logs_are_written_as_json_to_stderr_and_the_log_file() {
+    let home = TempDir::new("logs");
+    let log_file = home.0.join("milvue.log");
+    let missing = home.0.join("missing");
+
+    let output = run(
+        &home.0,
+        &[
+            missing.to_str().unwrap(),
+            "-k",
+            "key",
+            "-a",
+            "http://127.0.0.1:1",
+            "-u",
+            "--log-format",
+            "json",
+            "--log-file",
+            log_file.to_str().unwrap(),
+            "--log-rotation",
+            "never",
+        ],
+    )
+    .await;
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let log: Value = serde_json::from_str(stderr.lines().next().unwrap()).unwrap();
+    assert_eq!(log["level"], "ERROR");
+    assert!(log["fields"]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Input directory does not exist"));
+    let file = std::fs::read_to_string(&log_file).unwrap();
+    let log: Value = serde_json::from_str(file.lines().next().unwrap()).unwrap();
+    assert_eq!(log["level"], "ERROR");
+    assert!(log["timestamp"].is_string());
+}
+
+#[tokio::test]
+async fn requests_carry_the_trace_context_of_the_study() {
+    let home = TempDir::new("traces");
+    let input = home.0.join("input");
+    std::fs::create_dir_all(&input).unwrap();
+    write_dicom(&input);
+    let traceparent = Arc::new(Mutex::new(None));
+    let url = {
+        let traceparent = traceparent.clone();
+        common::serve_with_headers(move |method, path, headers| {
+            if (method, path) == ("POST", "/v3/studies") {
+                *traceparent.lock().unwrap() = headers.get("traceparent").cloned();
+                return Reply::json(400, "{}");
+            }
+            // the spans exported to the collector
+            Reply::json(200, "{}")
+        })
+        .await
+    };
+
+    let output = run(
+        &home.0,
+        &[
+            input.to_str().unwrap(),
+            "-k",
+            "key",
+            "-a",
+            &url,
+            "-u",
+            "--otlp-endpoint",
+            &url,
+            "-o",
+            home.0.join("results").to_str().unwrap(),
+        ],
+    )
+    .await;
+
+    assert_eq!(output.status.code(), Some(3));
+    let traceparent = traceparent.lock().unwrap().clone().unwrap();
+    // version, trace id, parent span id and sampled flag
+    let parts: Vec<&str> = traceparent.split('-').collect();
+    assert_eq!(parts.len(), 4);
+    assert_eq!(parts[0], "00");
+    assert_eq!(parts[1].len(), 32);
+    assert_ne!(parts[1], "0".repeat(32));
+    assert_eq!(parts[2].len(), 16);
+    assert_eq!(parts[3], "01");
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::Reply;
+use milvue_rs::{
+    MilvueClient, MilvueClientBuilder, MilvueError, ProxySettings, Timeouts, TlsSettings,
+};
+
+fn data(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+fn builder() -> MilvueClientBuilder {
+    MilvueClient::builder()
+        .url("http://127.0.0.1:1")
+        .api_key("key")
+}
+
+#[test]
+fn root_certificates_are_read_from_pem_files() {
+    let tls = TlsSettings {
+        root_certificates: vec![data("ca.pem")],
+        ..Default::default()
+    };
+    assert!(builder().tls(tls.clone()).build().is_ok());
+    assert!(builder()
+        .tls(TlsSettings {
+            only_custom_roots: true,
+            ..tls
+        })
+        .build()
+        .is_ok());
+
+    let missing = TlsSettings {
+        root_certificates: vec![data("missing.pem")],
+        ..Default::default()
+    };
+    assert!(matches!(
+        builder().tls(missing).build(),
+        Err(MilvueError::Io(path, _)) if path == data("missing.pem")
+    ));
+
+    // a key holds no certificate
+    let not_a_certificate = TlsSettings {
+        root_certificates: vec![data("client.key")],
+        ..Default::default()
+    };
+    assert!(matches!(
+        builder().tls(not_a_certificate).build(),
+        Err(MilvueError::InvalidValue("PEM certificate file", _))
+    ));
+}
+
+#[test]
+fn client_certificates_need_their_key() {
+    let tls = TlsSettings {
+        client_certificate: Some(data("client.pem")),
+        client_key: Some(data("client.key")),
+        ..Default::default()
+    };
+    assert!(builder().tls(tls).build().is_ok());
+
+    let key_only = TlsSettings {
+        client_key: Some(data("client.key")),
+        ..Default::default()
+    };
+    assert!(matches!(
+        builder().tls(key_only).build(),
+        Err(MilvueError::InvalidValue("TLS settings", _))
+    ));
+
+    let missing_key = TlsSettings {
+        client_certificate: Some(data("client.pem")),
+        client_key: Some(data("missing.key")),
+        ..Default::default()
+    };
+    assert!(matches!(
+        builder().tls(missing_key).build(),
+        Err(MilvueError::Io(_, _))
+    ));
+}
+
+#[tokio::test]
+async fn requests_go_through_the_proxy() {
+    let requested = Arc::new(Mutex::new(Vec::new()));
+    let proxy_url = {
+        let requested = requested.clone();
+        common::serve(move |method, path| {
+            requested
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", method, path));
+            Reply::json(404, "{}")
+        })
+        .await
+    };
+
+    let client = MilvueClient::builder()
+        .url("http://milvue.invalid")
+        .api_key("key")
+        .proxy(ProxySettings {
+            url: proxy_url,
+            username: Some("milvue".to_string()),
+            password: Some("password".to_string()),
+            no_proxy: vec![],
+        })
+        .build()
+        .unwrap();
+    let _ = client.get_study_status("1.2.3").await;
+
+    let requested = requested.lock().unwrap();
+    assert_eq!(requested.len(), 1);
+    assert!(
+        requested[0].starts_with("GET http://milvue.invalid/"),
+        "{}",
+        requested[0]
+    );
+}
+
+#[test]
+fn an_invalid_proxy_url_is_rejected() {
+    let proxy = ProxySettings {
+        url: "not a url".to_string(),
+        ..Default::default()
+    };
+    assert!(matches!(
+        builder().proxy(proxy).build(),
+        Err(MilvueError::RequestError(_))
+    ));
+}
+
+#[tokio::test]
+async fn requests_time_out() {
+    // accepts the connections but never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            connections.push(stream);
+        }
+    });
+
+    let client = MilvueClient::builder()
+        .url(&url)
+        .api_key("key")
+        .timeouts(Timeouts {
+            request: Some(Duration::from_millis(200)),
+            ..Default::default()
+        })
+        .build()
+        .unwrap();
+    assert_eq!(client.timeouts().request, Some(Duration::from_millis(200)));
+
+    let started = std::time::Instant::now();
+    let error = client.get_study_status("1.2.3").await.unwrap_err();
+    assert!(
+        matches!(&error, MilvueError::RequestError(e) if e.is_timeout()),
+        "{:?}",
+        error
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
@@ -0,0 +1,132 @@
+//! Minimal HTTP server answering the requests of the integration tests.
+#![allow(dead_code)]
+
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Answers a request from its method, path and headers.
+type Handler = dyn Fn(&str, &str, &HashMap<String, String>) -> Reply + Send + Sync;
+
+/// Response of the test server.
+pub struct Reply {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+    pub headers: Vec<(&'static str, String)>,
+}
+
+impl Reply {
+    pub fn json(status: u16, body: &str) -> Reply {
+        Reply {
+            status,
+            content_type: "application/json",
+            body: body.as_bytes().to_vec(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Adds a header to the response.
+    pub fn header(mut self, name: &'static str, value: &str) -> Reply {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+}
+
+/// Serves the requests with `handler`, called with the method and the path with its query, until the test ends.
+///
+/// # Returns
+///
+/// * The base URL of the server.
+pub async fn serve<F>(handler: F) -> String
+where
+    F: Fn(&str, &str) -> Reply + Send + Sync + 'static,
+{
+    serve_with_headers(move |method, path, _| handler(method, path)).await
+}
+
+/// Serves the requests like [serve()], with their headers, whose names are lowercase.
+pub async fn serve_with_headers<F>(handler: F) -> String
+where
+    F: Fn(&str, &str, &HashMap<String, String>) -> Reply + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let _ = answer(stream, handler.as_ref()).await;
+            });
+        }
+    });
+    url
+}
+
+async fn answer(mut stream: TcpStream, handler: &Handler) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 8192];
+    let header_end = loop {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..read]);
+        if let Some(position) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+    let head = String::from_utf8_lossy(&request[..header_end]).to_string();
+    let headers: HashMap<String, String> = head
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            Some((key.trim().to_ascii_lowercase(), value.trim().to_string()))
+        })
+        .collect();
+    let header = |name: &str| headers.get(name).cloned();
+
+    // drain the body so that the client doesn't see a reset connection
+    if let Some(length) = header("content-length").and_then(|length| length.parse::<usize>().ok()) {
+        while request.len() < header_end + length {
+            let read = stream.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+    } else if header("transfer-encoding").is_some_and(|encoding| encoding.contains("chunked")) {
+        while !request.ends_with(b"0\r\n\r\n") {
+            let read = stream.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+    }
+
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default();
+    let reply = handler(method, path, &headers);
+    let headers: String = reply
+        .headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}\r\n", name, value))
+        .collect();
+    let head = format!(
+        "HTTP/1.1 {} Status\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        reply.status,
+        reply.content_type,
+        reply.body.len(),
+        headers
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&reply.body).await?;
+    stream.shutdown().await
+}
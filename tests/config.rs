@@ -0,0 +1,157 @@
+#![cfg(feature = "config")]
+
+use milvue_rs::{
+    config::{Config, Profile},
+    MilvueError,
+};
+
+#[test]
+fn later_files_win_field_by_field() {
+    let mut config = Config::from_toml(
+        r#"
+        default_profile = "prod"
+
+        [profiles.prod]
+        url = "https://eu.api.milvue.com"
+        concurrency = 4
+        sinks = ["/mnt/archive"]
+
+        [profiles.prod.params]
+        inference_commands = ["smarturgences"]
+        language = "fr"
+        "#,
+    )
+    .unwrap();
+    config.merge(
+        Config::from_toml(
+            r#"
+            [profiles.prod]
+            concurrency = 2
+
+            [profiles.prod.params]
+            language = "en"
+
+            [profiles.dev]
+            url = "https://dev.api.milvue.com"
+            "#,
+        )
+        .unwrap(),
+    );
+
+    assert_eq!(config.default_profile.as_deref(), Some("prod"));
+    let prod = config.profile(None).unwrap();
+    assert_eq!(prod.url.as_deref(), Some("https://eu.api.milvue.com"));
+    assert_eq!(prod.concurrency, Some(2));
+    assert_eq!(prod.sinks, vec!["/mnt/archive".to_string()]);
+    assert_eq!(prod.params.inference_commands.len(), 1);
+    assert_eq!(prod.params.language, Some(milvue_rs::Language::En));
+    assert_eq!(
+        config.profile(Some("dev")).unwrap().url.as_deref(),
+        Some("https://dev.api.milvue.com")
+    );
+}
+
+#[test]
+fn profiles_are_selected_by_name_then_default() {
+    let config = Config::from_toml(
+        r#"
+        [profiles.default]
+        url = "https://default.example.com"
+
+        [profiles.prod]
+        url = "https://prod.example.com"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.profile(None).unwrap().url.as_deref(),
+        Some("https://default.example.com")
+    );
+    assert_eq!(
+        config.profile(Some("prod")).unwrap().url.as_deref(),
+        Some("https://prod.example.com")
+    );
+    assert!(matches!(
+        config.profile(Some("staging")),
+        Err(MilvueError::ProfileNotFound(name)) if name == "staging"
+    ));
+    assert!(Config::default().profile(None).unwrap().url.is_none());
+
+    let config = Config::from_toml("default_profile = \"missing\"").unwrap();
+    assert!(config.profile(None).is_err());
+}
+
+#[test]
+fn api_keys_are_read_from_their_source() {
+    let path = std::env::temp_dir().join(format!("milvue_rs-key-{}", std::process::id()));
+    std::fs::write(&path, "  file-key\n").unwrap();
+    std::env::set_var("MILVUE_RS_TEST_CONFIG_KEY", "env-key");
+
+    let key = |source: &str| {
+        toml::from_str::<Profile>(&format!("api_key = {}", source))
+            .unwrap()
+            .resolve_api_key()
+    };
+    assert_eq!(key(r#"{ value = "value-key" }"#).unwrap(), "value-key");
+    assert_eq!(
+        key(&format!("{{ file = {:?} }}", path)).unwrap(),
+        "file-key"
+    );
+    assert_eq!(
+        key(r#"{ env = "MILVUE_RS_TEST_CONFIG_KEY" }"#).unwrap(),
+        "env-key"
+    );
+    assert!(matches!(
+        key(r#"{ env = "MILVUE_RS_TEST_CONFIG_UNSET" }"#),
+        Err(MilvueError::EnvVarNotFound(_))
+    ));
+    assert!(matches!(
+        key(r#"{ file = "/nonexistent/milvue_rs/key" }"#),
+        Err(MilvueError::ConfigReadError(_, _))
+    ));
+    assert!(matches!(
+        Profile::default().resolve_api_key(),
+        Err(MilvueError::NoApiKey)
+    ));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn a_concurrency_of_zero_is_rejected() {
+    assert!(Config::from_toml("[profiles.prod]\nconcurrency = 0").is_err());
+    let config = Config::from_toml("[profiles.prod]\nconcurrency = 1").unwrap();
+    assert_eq!(config.profile(Some("prod")).unwrap().concurrency, Some(1));
+}
+
+#[test]
+fn secrets_are_redacted_from_the_debug_output() {
+    let config = Config::from_toml(
+        r#"
+        [profiles.prod]
+        api_key = { value = "s3cr3t-key" }
+
+        [profiles.prod.proxy]
+        url = "http://proxy.hospital.local:3128"
+        username = "milvue"
+        password = { value = "s3cr3t-password" }
+        "#,
+    )
+    .unwrap();
+    let profile = config.profile(Some("prod")).unwrap();
+
+    let debug = format!("{:?}", profile);
+    assert!(!debug.contains("s3cr3t"), "{}", debug);
+    assert!(debug.contains("proxy.hospital.local"));
+    assert!(debug.contains("<redacted>"));
+    assert_eq!(
+        profile
+            .proxy
+            .as_ref()
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .password
+            .as_deref(),
+        Some("s3cr3t-password")
+    );
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::Reply;
+use dicom::core::{DataElement, PrimitiveValue, VR};
+use dicom_dictionary_std::tags;
+use dicom_object::{FileMetaTableBuilder, InMemDicomObject};
+use milvue_rs::{CircuitBreakerSettings, DicomSource, FailoverClient, MilvueClient, MilvueError};
+
+/// SOP class of the test objects.
+const DIGITAL_XRAY_IMAGE_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.1.1";
+
+fn endpoint(url: &str) -> MilvueClient {
+    MilvueClient::builder()
+        .url(url)
+        .api_key("key")
+        .circuit_breaker(CircuitBreakerSettings {
+            failure_threshold: Some(1),
+            open_duration: Some(Duration::from_secs(60)),
+        })
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn studies_are_submitted_to_the_first_healthy_environment_and_stay_there() {
+    // nothing listens on ports 1 and 2
+    let client = FailoverClient::new(vec![
+        endpoint("http://127.0.0.1:1"),
+        endpoint("http://127.0.0.1:2/"),
+    ])
+    .unwrap();
+    assert_eq!(client.submission_client().url(), "http://127.0.0.1:1");
+
+    // an unpinned study is polled on the first environment, whose failure opens its circuit
+    assert!(client.get_study_status("1.2.3").await.is_err());
+    assert_eq!(client.submission_client().url(), "http://127.0.0.1:2");
+
+    assert!(client.pin("1.2.3", "http://127.0.0.1:2/"));
+    assert_eq!(client.client_for("1.2.3").url(), "http://127.0.0.1:2");
+    assert_eq!(client.client_for("1.2.4").url(), "http://127.0.0.1:1");
+    assert!(!client.pin("1.2.4", "https://api.milvue.com"));
+
+    client.unpin("1.2.3");
+    assert_eq!(client.client_for("1.2.3").url(), "http://127.0.0.1:1");
+}
+
+#[test]
+fn at_least_one_environment_is_required() {
+    assert!(FailoverClient::new(Vec::new()).is_err());
+}
+
+/// Builds a minimal DICOM object of the study 1.2.3.
+fn dicom() -> DicomSource {
+    InMemDicomObject::from_element_iter([
+        DataElement::new(
+            tags::SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from(DIGITAL_XRAY_IMAGE_STORAGE),
+        ),
+        DataElement::new(
+            tags::SOP_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.3.1"),
+        ),
+        DataElement::new(
+            tags::STUDY_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.3"),
+        ),
+    ])
+    .with_meta(
+        FileMetaTableBuilder::new()
+            .transfer_syntax("1.2.840.10008.1.2.1")
+            .media_storage_sop_class_uid(DIGITAL_XRAY_IMAGE_STORAGE),
+    )
+    .unwrap()
+    .into()
+}
+
+/// Serves an environment answering the uploads with `status`, and counts them.
+async fn environment(status: u16) -> (String, Arc<Mutex<usize>>) {
+    let uploads = Arc::new(Mutex::new(0));
+    let url = {
+        let uploads = uploads.clone();
+        common::serve(move |method, path| {
+            if method == "POST" && path == "/v3/studies" {
+                *uploads.lock().unwrap() += 1;
+            }
+            Reply::json(status, "{}")
+        })
+        .await
+    };
+    (url, uploads)
+}
+
+#[tokio::test]
+async fn uploads_fail_over_to_the_backup_environment() {
+    let (backup, uploads) = environment(200).await;
+    // nothing listens on port 1
+    let client =
+        FailoverClient::new(vec![endpoint("http://127.0.0.1:1"), endpoint(&backup)]).unwrap();
+
+    client
+        .upload("1.2.3", vec![dicom()], &Default::default())
+        .await
+        .unwrap();
+
+    assert_eq!(*uploads.lock().unwrap(), 1);
+    assert_eq!(client.client_for("1.2.3").url(), backup);
+    // the circuit of the primary environment is open, the next study goes to the backup directly
+    assert_eq!(client.submission_client().url(), backup);
+}
+
+#[tokio::test]
+async fn server_errors_fail_over_but_not_client_errors() {
+    let (failing, failed_uploads) = environment(503).await;
+    let (backup, uploads) = environment(200).await;
+    let client = FailoverClient::new(vec![endpoint(&failing), endpoint(&backup)]).unwrap();
+    client
+        .upload("1.2.3", vec![dicom()], &Default::default())
+        .await
+        .unwrap();
+    assert_eq!(*failed_uploads.lock().unwrap(), 1);
+    assert_eq!(*uploads.lock().unwrap(), 1);
+    assert_eq!(client.client_for("1.2.3").url(), backup);
+
+    // a rejected study would be rejected by every environment
+    let (rejecting, _) = environment(400).await;
+    let (backup, uploads) = environment(200).await;
+    let client = FailoverClient::new(vec![endpoint(&rejecting), endpoint(&backup)]).unwrap();
+    let error = client
+        .upload("1.2.3", vec![dicom()], &Default::default())
+        .await
+        .unwrap_err();
+    assert!(matches!(error, MilvueError::StatusResponseError(_)));
+    assert_eq!(*uploads.lock().unwrap(), 0);
+}
+
+#[tokio::test]
+async fn studies_which_may_have_been_received_are_not_submitted_again() {
+    for status in [500, 504] {
+        let (failing, failed_uploads) = environment(status).await;
+        let (backup, uploads) = environment(200).await;
+        let client = FailoverClient::new(vec![endpoint(&failing), endpoint(&backup)]).unwrap();
+        let error = client
+            .upload("1.2.3", vec![dicom()], &Default::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, MilvueError::StatusResponseError(_)));
+        assert_eq!(*failed_uploads.lock().unwrap(), 1);
+        assert_eq!(*uploads.lock().unwrap(), 0);
+    }
+}
+
+#[tokio::test]
+async fn readers_are_not_uploaded_twice() {
+    let (backup, uploads) = environment(200).await;
+    let client =
+        FailoverClient::new(vec![endpoint("http://127.0.0.1:1"), endpoint(&backup)]).unwrap();
+    let sources = vec![DicomSource::Reader {
+        sop_instance_uid: "1.2.3.1".to_string(),
+        reader: Box::new(std::io::Cursor::new(vec![0u8; 16])),
+    }];
+
+    let error = client
+        .upload("1.2.3", sources, &Default::default())
+        .await
+        .unwrap_err();
+    assert!(matches!(error, MilvueError::RequestError(_)));
+    assert_eq!(*uploads.lock().unwrap(), 0);
+    assert_eq!(client.client_for("1.2.3").url(), "http://127.0.0.1:1");
+}
@@ -0,0 +1,83 @@
+use std::{fmt::Display, str::FromStr};
+
+use milvue_rs::{
+    InferenceCommand, Language, OutputFormat, OutputSelection, RecapTheme, StaticReportFormat,
+    StructuredReportFormat,
+};
+
+/// Checks that every value parses back into the variant which displayed it.
+fn assert_round_trip<T>(values: &[T])
+where
+    T: Display + FromStr + PartialEq + std::fmt::Debug,
+    T::Err: std::fmt::Debug,
+{
+    for value in values {
+        assert_eq!(&value.to_string().parse::<T>().unwrap(), value);
+    }
+}
+
+#[test]
+fn parameters_parse_without_the_cli_feature() {
+    assert_round_trip(&[
+        OutputFormat::Overlay,
+        OutputFormat::Highbit,
+        OutputFormat::Gsps,
+        OutputFormat::SecondaryCapture,
+    ]);
+    assert_round_trip(&[
+        Language::Fr,
+        Language::En,
+        Language::Es,
+        Language::De,
+        Language::It,
+        Language::Pt,
+    ]);
+    assert_round_trip(&[
+        InferenceCommand::SmartUrgences,
+        InferenceCommand::SmartXpert,
+    ]);
+    assert_round_trip(&[
+        OutputSelection::All,
+        OutputSelection::NoRecap,
+        OutputSelection::NoNegatives,
+        OutputSelection::None,
+    ]);
+    assert!("smart-urgences".parse::<InferenceCommand>().is_err());
+}
+
+#[test]
+fn report_formats_parse_without_the_cli_feature() {
+    assert_round_trip(&[RecapTheme::Dark, RecapTheme::Light]);
+    assert_round_trip(&[
+        StructuredReportFormat::Lite,
+        StructuredReportFormat::Normal,
+        StructuredReportFormat::Full,
+        StructuredReportFormat::None,
+    ]);
+    assert_round_trip(&[
+        StaticReportFormat::Rgb,
+        StaticReportFormat::Pdf,
+        StaticReportFormat::None,
+    ]);
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn parameters_are_command_line_values_with_the_cli_feature() {
+    use clap::ValueEnum;
+    use milvue_rs::MilvueUrl;
+
+    assert_eq!(
+        <InferenceCommand as ValueEnum>::from_str("smart-urgences", false).unwrap(),
+        InferenceCommand::SmartUrgences
+    );
+    assert_eq!(
+        <OutputFormat as ValueEnum>::from_str("secondary-capture", false).unwrap(),
+        OutputFormat::SecondaryCapture
+    );
+    assert_eq!(
+        <MilvueUrl as ValueEnum>::from_str("default", false).unwrap(),
+        MilvueUrl::DefaultUrl
+    );
+    assert_eq!(Language::value_variants().len(), 6);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::Reply;
+use milvue_rs::{InferenceCommand, Language, MilvueClient, MilvueError, MilvueParams};
+
+fn params(inference_command: InferenceCommand, language: Language) -> MilvueParams {
+    MilvueParams {
+        language: Some(language),
+        inference_command,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn a_failed_configuration_doesnt_prevent_the_others() {
+    // SmartXpert fails, SmartUrgences has no output
+    let url = common::serve(|_, path| match path.contains("smartxpert") {
+        true => Reply::json(500, "{}"),
+        false => Reply::json(200, "{}"),
+    })
+    .await;
+    let client = MilvueClient::with_api_key(&url, "key").unwrap();
+    let requested = [
+        params(InferenceCommand::SmartUrgences, Language::Fr),
+        params(InferenceCommand::SmartXpert, Language::Fr),
+        params(InferenceCommand::SmartUrgences, Language::En),
+        params(InferenceCommand::SmartUrgences, Language::Fr),
+    ];
+
+    let response = client
+        .get_many("1.2.3", &requested, &Default::default())
+        .await
+        .unwrap();
+
+    assert!(!response.is_complete());
+    // in the order of the request, the repeated configuration included
+    let succeeded: Vec<_> = response.results.iter().map(|(params, _)| params).collect();
+    assert_eq!(succeeded, vec![&requested[0], &requested[2], &requested[3]]);
+    assert!(response.results.iter().all(|(_, dicoms)| dicoms.is_none()));
+    assert_eq!(response.errors.len(), 1);
+    assert_eq!(response.errors[0].0, requested[1]);
+    assert!(matches!(
+        &response.errors[0].1,
+        MilvueError::StatusResponseError(response) if response.status() == 500
+    ));
+}
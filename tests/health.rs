@@ -0,0 +1,115 @@
+mod common;
+
+use common::Reply;
+use milvue_rs::{CheckStatus, HealthReport, MilvueClient, MilvueError};
+
+fn status(report: &milvue_rs::HealthReport, name: &str) -> CheckStatus {
+    report
+        .checks
+        .iter()
+        .find(|check| check.name == name)
+        .unwrap_or_else(|| panic!("no {} step", name))
+        .status
+}
+
+#[tokio::test]
+async fn an_unreachable_environment_fails_at_the_connection() {
+    // nothing listens on port 1
+    let client = MilvueClient::with_api_key("http://127.0.0.1:1", "key").unwrap();
+    let report = client.health_check().await;
+
+    assert!(!report.is_healthy());
+    assert_eq!(status(&report, "url"), CheckStatus::Ok);
+    assert_eq!(status(&report, "dns"), CheckStatus::Ok);
+    assert_eq!(status(&report, "tcp"), CheckStatus::Failed);
+    assert_eq!(status(&report, "authentication"), CheckStatus::Failed);
+    assert_eq!(status(&report, "api"), CheckStatus::Skipped);
+    assert!(report.latency.is_none());
+    assert!(report
+        .checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Failed)
+        .all(|check| check.hint.is_some()));
+}
+
+#[tokio::test]
+async fn an_invalid_url_is_reported_without_any_connection() {
+    let client = MilvueClient::with_api_key("ftp://example.com", "key").unwrap();
+    let report = client.health_check().await;
+
+    assert!(!report.is_healthy());
+    assert_eq!(status(&report, "url"), CheckStatus::Failed);
+    assert!(report.checks[1..]
+        .iter()
+        .all(|check| check.status == CheckStatus::Skipped));
+}
+
+#[tokio::test]
+async fn a_404_of_the_milvue_api_accepts_the_credentials() {
+    let url = common::serve(|_, _| {
+        Reply::json(
+            404,
+            r#"{"StudyInstanceUID": "1.2.3", "status": "not_found", "version": "3.1", "message": "unknown study"}"#,
+        )
+    })
+    .await;
+    let report = MilvueClient::with_api_key(&url, "key")
+        .unwrap()
+        .health_check()
+        .await;
+
+    assert!(report.is_healthy());
+    assert_eq!(status(&report, "authentication"), CheckStatus::Ok);
+    assert_eq!(status(&report, "api"), CheckStatus::Ok);
+    assert_eq!(report.api_version.as_deref(), Some("3.1"));
+    // plain HTTP
+    assert_eq!(status(&report, "tls"), CheckStatus::Warning);
+}
+
+#[tokio::test]
+async fn a_404_of_another_server_fails_with_a_url_hint() {
+    let url = common::serve(|_, _| Reply {
+        status: 404,
+        content_type: "text/html",
+        body: b"<html>Not Found</html>".to_vec(),
+        headers: Vec::new(),
+    })
+    .await;
+    let report = MilvueClient::with_api_key(&format!("{}/wrong/path", url), "key")
+        .unwrap()
+        .health_check()
+        .await;
+
+    assert!(!report.is_healthy());
+    assert_eq!(status(&report, "authentication"), CheckStatus::Warning);
+    assert_eq!(status(&report, "api"), CheckStatus::Failed);
+    let api = report
+        .checks
+        .iter()
+        .find(|check| check.name == "api")
+        .unwrap();
+    assert!(api.hint.as_deref().unwrap().contains("URL"));
+    assert!(report.api_version.is_none());
+
+    // a JSON 404 without the fields of a study status isn't from Milvue either
+    let url = common::serve(|_, _| Reply::json(404, r#"{"detail": "Not Found"}"#)).await;
+    let report = MilvueClient::with_api_key(&url, "key")
+        .unwrap()
+        .health_check()
+        .await;
+    assert_eq!(status(&report, "api"), CheckStatus::Failed);
+}
+
+#[test]
+fn a_client_that_cannot_be_built_fails_at_the_url() {
+    let report = HealthReport::from_error("profile prod", &MilvueError::NoApiUrl);
+
+    assert!(!report.is_healthy());
+    assert_eq!(report.url, "profile prod");
+    assert_eq!(status(&report, "url"), CheckStatus::Failed);
+    assert!(report.checks[0].hint.is_some());
+    assert!(report.checks[0].message.contains("No API URL"));
+    assert!(report.checks[1..]
+        .iter()
+        .all(|check| check.status == CheckStatus::Skipped));
+}
@@ -0,0 +1,28 @@
+#![cfg(feature = "metrics")]
+
+use std::time::Duration;
+
+use milvue_rs::{
+    metrics::{Metrics, StudyState},
+    InferenceCommand,
+};
+
+#[test]
+fn studies_are_counted_per_inference_command_and_state() {
+    let metrics = Metrics::new();
+    metrics.study(&InferenceCommand::SmartUrgences, StudyState::Received);
+    metrics.study(&InferenceCommand::SmartUrgences, StudyState::Received);
+    metrics.study(&InferenceCommand::SmartXpert, StudyState::Failed);
+    metrics.observe_latency(&InferenceCommand::SmartUrgences, Duration::from_secs(42));
+
+    let text = metrics.encode();
+    assert!(text
+        .contains(r#"milvue_studies_total{inference_command="smarturgences",state="received"} 2"#));
+    assert!(
+        text.contains(r#"milvue_studies_total{inference_command="smartxpert",state="failed"} 1"#)
+    );
+    assert!(text.contains(
+        r#"milvue_study_latency_seconds_bucket{inference_command="smarturgences",le="60"} 1"#
+    ));
+    assert!(text.contains("milvue_upload_bytes_total 0"));
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+
+use dicom::{
+    core::{DataElement, PrimitiveValue, VR},
+    dictionary_std::tags,
+    object::InMemDicomObject,
+};
+use milvue_rs::config::{Config, PriorityRule};
+
+fn object(priority: &str) -> InMemDicomObject {
+    InMemDicomObject::from_element_iter([DataElement::new(
+        tags::REQUESTED_PROCEDURE_PRIORITY,
+        VR::CS,
+        PrimitiveValue::from(priority),
+    )])
+}
+
+#[test]
+fn the_highest_matching_priority_is_used() {
+    let config: Config = toml::from_str(
+        r#"
+        [[profiles.prod.priorities]]
+        attribute = "RequestedProcedurePriority"
+        values = ["stat", "HIGH"]
+        priority = 10
+
+        [[profiles.prod.priorities]]
+        path = "/srv/exports/followups"
+        priority = -5
+
+        [[profiles.prod.priorities]]
+        path = "/srv/exports"
+        attribute = "RequestedProcedurePriority"
+        priority = 1
+        "#,
+    )
+    .unwrap();
+    let rules = config.profile(Some("prod")).unwrap().priorities;
+
+    let highest = |path: &str, object: &InMemDicomObject| {
+        PriorityRule::highest(&rules, Path::new(path), object)
+    };
+    assert_eq!(highest("/tmp/a.dcm", &object("STAT")), 10);
+    assert_eq!(highest("/srv/exports/followups/a.dcm", &object("STAT")), 10);
+    assert_eq!(
+        highest("/srv/exports/followups/a.dcm", &object("ROUTINE")),
+        1
+    );
+    assert_eq!(
+        highest(
+            "/srv/exports/followups/a.dcm",
+            &InMemDicomObject::new_empty()
+        ),
+        -5
+    );
+    assert_eq!(highest("/tmp/a.dcm", &object("ROUTINE")), 0);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::Reply;
+use milvue_rs::{DicomSource, MilvueClient, Progress, ProgressReporter};
+
+async fn client() -> MilvueClient {
+    let url = common::serve(|_, _| Reply::json(200, "{}")).await;
+    MilvueClient::with_api_key(&url, "key").unwrap()
+}
+
+#[tokio::test]
+async fn the_bytes_and_files_of_an_upload_are_counted() {
+    let directory = std::env::temp_dir().join(format!("milvue_rs-progress-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let sizes = [200_000, 10];
+    let sources = sizes
+        .iter()
+        .enumerate()
+        .map(|(i, size)| {
+            let path = directory.join(format!("{}.dcm", i));
+            std::fs::write(&path, vec![0u8; *size]).unwrap();
+            DicomSource::File {
+                path,
+                sop_instance_uid: Some(format!("1.2.3.{}", i)),
+            }
+        })
+        .collect();
+
+    let (reporter, receiver) = ProgressReporter::watch();
+    client().await.upload(sources, &reporter).await.unwrap();
+
+    let Some(Progress::Upload(progress)) = receiver.borrow().clone() else {
+        panic!("no upload progress reported");
+    };
+    assert_eq!(progress.bytes, 200_010);
+    assert_eq!(progress.total_bytes, Some(200_010));
+    assert_eq!(progress.files_completed, 2);
+    assert_eq!(progress.total_files, Some(2));
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn every_event_of_a_streamed_upload_is_sent_on_the_channel() {
+    let body = vec![1u8; 100_000];
+    let sources = vec![DicomSource::Reader {
+        sop_instance_uid: "1.2.3.4".to_string(),
+        reader: Box::new(std::io::Cursor::new(body)),
+    }];
+
+    let (reporter, mut receiver) = ProgressReporter::channel();
+    client().await.upload(sources, &reporter).await.unwrap();
+    drop(reporter);
+
+    let mut events = Vec::new();
+    while let Some(event) = receiver.recv().await {
+        match event {
+            Progress::Upload(progress) => events.push(progress),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+    assert!(events
+        .windows(2)
+        .all(|pair| pair[0].bytes <= pair[1].bytes
+            && pair[0].files_completed <= pair[1].files_completed));
+    let last = events.last().unwrap();
+    assert_eq!(last.bytes, 100_000);
+    // the size of a reader isn't known in advance
+    assert_eq!(last.total_bytes, None);
+    assert_eq!(last.files_completed, 1);
+}
@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+
+use milvue_rs::{Rate, RateLimiter, RateLimits};
+
+#[tokio::test]
+async fn requests_wait_for_their_tokens_and_slow_down_when_rejected() {
+    let limiter = RateLimiter::new(&RateLimits {
+        status: Some(Rate {
+            per_minute: 1200.0,
+            burst: 2,
+        }),
+        ..Default::default()
+    })
+    .unwrap();
+
+    let start = Instant::now();
+    for _ in 0..4 {
+        limiter.acquire("status").await;
+        limiter.acquire("upload").await;
+    }
+    // The burst is spent at once, then a token every 50 ms.
+    assert!(start.elapsed() >= Duration::from_millis(95));
+
+    assert_eq!(
+        limiter.throttle("status", Some(Duration::from_millis(200))),
+        Duration::from_millis(200)
+    );
+    assert_eq!(limiter.current_rate("status"), Some(600.0));
+    assert_eq!(limiter.current_rate("upload"), None);
+    let start = Instant::now();
+    limiter.acquire("status").await;
+    assert!(start.elapsed() >= Duration::from_millis(195));
+
+    limiter.recover("status");
+    assert_eq!(limiter.current_rate("status"), Some(660.0));
+}
+
+#[test]
+fn invalid_rates_are_rejected() {
+    let limits = RateLimits {
+        upload: Some(Rate::per_minute(0.0)),
+        ..Default::default()
+    };
+    assert!(RateLimiter::new(&limits).is_err());
+    assert!(RateLimits::default()
+        .set("submit", Rate::per_minute(30.0))
+        .is_err());
+}
+
+#[tokio::test]
+async fn huge_retry_after_is_capped() {
+    let limiter = RateLimiter::new(&RateLimits {
+        upload: Some(Rate::per_minute(60.0)),
+        ..Default::default()
+    })
+    .unwrap();
+
+    let pause = limiter.throttle("upload", Some(Duration::from_secs(u64::MAX)));
+    assert_eq!(pause, Duration::from_secs(3600));
+    assert_eq!(
+        limiter.throttle("status", Some(Duration::MAX)),
+        Duration::from_secs(3600)
+    );
+    // the bucket is still usable
+    assert_eq!(limiter.current_rate("upload"), Some(30.0));
+}
@@ -0,0 +1,132 @@
+use std::{fmt::Display, str::FromStr};
+
+use milvue_rs::{
+    InferenceCommand, Language, MilvueParams, MilvueUrl, OutputFormat, OutputSelection, RecapTheme,
+    StaticReportFormat, StructuredReportFormat,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Checks that every value serializes to its API wire string and parses back from it.
+fn assert_round_trip<T>(values: &[T])
+where
+    T: Serialize + DeserializeOwned + Display + FromStr + PartialEq + std::fmt::Debug,
+    <T as FromStr>::Err: std::fmt::Debug,
+{
+    for value in values {
+        let wire = value.to_string();
+        let json = serde_json::to_string(value).unwrap();
+        assert_eq!(json, format!("\"{}\"", wire));
+        assert_eq!(&serde_json::from_str::<T>(&json).unwrap(), value);
+        assert_eq!(&wire.parse::<T>().unwrap(), value);
+    }
+}
+
+#[test]
+fn enums_round_trip() {
+    assert_round_trip(&[
+        OutputFormat::Overlay,
+        OutputFormat::Highbit,
+        OutputFormat::Gsps,
+        OutputFormat::SecondaryCapture,
+    ]);
+    assert_round_trip(&[
+        Language::Fr,
+        Language::En,
+        Language::Es,
+        Language::De,
+        Language::It,
+        Language::Pt,
+    ]);
+    assert_round_trip(&[
+        InferenceCommand::SmartUrgences,
+        InferenceCommand::SmartXpert,
+    ]);
+    assert_round_trip(&[
+        OutputSelection::All,
+        OutputSelection::NoRecap,
+        OutputSelection::NoNegatives,
+        OutputSelection::None,
+    ]);
+    assert_round_trip(&[RecapTheme::Dark, RecapTheme::Light]);
+    assert_round_trip(&[
+        StructuredReportFormat::Lite,
+        StructuredReportFormat::Normal,
+        StructuredReportFormat::Full,
+        StructuredReportFormat::None,
+    ]);
+    assert_round_trip(&[
+        StaticReportFormat::Rgb,
+        StaticReportFormat::Pdf,
+        StaticReportFormat::None,
+    ]);
+}
+
+#[test]
+fn unknown_values_are_rejected() {
+    assert!("SmartUrgences".parse::<InferenceCommand>().is_err());
+    assert!("secondary-capture".parse::<OutputFormat>().is_err());
+    assert!(serde_json::from_str::<Language>("\"EN\"").is_err());
+}
+
+#[test]
+fn milvue_url_deserializes_environment_names() {
+    for (name, url) in [
+        ("dev", MilvueUrl::Dev),
+        ("staging", MilvueUrl::Staging),
+        ("prod", MilvueUrl::Prod),
+        ("default", MilvueUrl::DefaultUrl),
+    ] {
+        let json = format!("\"{}\"", name);
+        assert_eq!(serde_json::from_str::<MilvueUrl>(&json).unwrap(), url);
+        assert_eq!(serde_json::to_string(&url).unwrap(), json);
+    }
+}
+
+#[test]
+fn params_round_trip() {
+    let full = MilvueParams {
+        signed_url: Some(true),
+        output_format: Some(OutputFormat::SecondaryCapture),
+        language: Some(Language::De),
+        inference_command: InferenceCommand::SmartXpert,
+        timezone: Some("+2".to_string()),
+        output_selection: Some(OutputSelection::NoNegatives),
+        recap_theme: Some(RecapTheme::Light),
+        structured_report_format: Some(StructuredReportFormat::Full),
+        static_report_format: Some(StaticReportFormat::Pdf),
+    };
+    let minimal = MilvueParams {
+        signed_url: None,
+        output_format: None,
+        language: None,
+        inference_command: InferenceCommand::SmartUrgences,
+        timezone: None,
+        output_selection: None,
+        recap_theme: None,
+        structured_report_format: None,
+        static_report_format: None,
+    };
+
+    for params in [full, minimal, MilvueParams::default()] {
+        let json = serde_json::to_string(&params).unwrap();
+        assert_eq!(serde_json::from_str::<MilvueParams>(&json).unwrap(), params);
+
+        let toml = toml::to_string(&params).unwrap();
+        assert_eq!(toml::from_str::<MilvueParams>(&toml).unwrap(), params);
+    }
+}
+
+#[test]
+fn params_use_query_parameter_names() {
+    let params = MilvueParams::default();
+    let json: serde_json::Value = serde_json::to_value(&params).unwrap();
+    for (name, value) in params.to_query_param() {
+        assert_eq!(json[&name], serde_json::Value::String(value));
+    }
+
+    let minimal: MilvueParams =
+        serde_json::from_str(r#"{"inference_command": "smartxpert"}"#).unwrap();
+    assert_eq!(minimal.inference_command, InferenceCommand::SmartXpert);
+    assert_eq!(minimal.language, None);
+    assert_eq!(minimal.to_query_param().len(), 1);
+}
@@ -0,0 +1,115 @@
+#![cfg(feature = "cli")]
+
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use tokio::process::{Child, Command};
+
+/// Directory of a test, removed at the end of the test.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("milvue_rs-serve-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Starts `milvue_rs serve` with a profile of an unreachable environment, and `args`.
+fn serve(directory: &Path, args: &[&str]) -> Child {
+    let config = directory.join("config.toml");
+    std::fs::write(
+        &config,
+        r#"
+        [profiles.test]
+        url = "http://127.0.0.1:1"
+        api_key = { value = "key" }
+
+        [profiles.test.params]
+        inference_commands = ["smarturgences"]
+        "#,
+    )
+    .unwrap();
+    Command::new(env!("CARGO_BIN_EXE_milvue_rs"))
+        .arg("serve")
+        .args(["-c", config.to_str().unwrap(), "-p", "test"])
+        .args(["--work-dir", directory.join("work").to_str().unwrap()])
+        .args(args)
+        .env("HOME", directory)
+        .env("XDG_CONFIG_HOME", directory)
+        .env_remove("RUST_LOG")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_api_is_only_served_beyond_the_loopback_with_a_token() {
+    let directory = TempDir::new("refused");
+
+    let output = serve(&directory.0, &["--listen", "0.0.0.0:0"])
+        .wait_with_output()
+        .await
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--token-file"));
+}
+
+#[tokio::test]
+async fn requests_need_the_bearer_token() {
+    let directory = TempDir::new("token");
+    let token_file = directory.0.join("token");
+    std::fs::write(&token_file, "s3cr3t\n").unwrap();
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let listen = format!("127.0.0.1:{}", port);
+    let _server = serve(
+        &directory.0,
+        &[
+            "--listen",
+            &listen,
+            "--token-file",
+            token_file.to_str().unwrap(),
+        ],
+    );
+
+    let url = format!("http://{}/jobs", listen);
+    let client = reqwest::Client::new();
+    let mut response = None;
+    for _ in 0..100 {
+        match client.get(&url).bearer_auth("wrong").send().await {
+            Ok(answer) => {
+                response = Some(answer);
+                break;
+            }
+            Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+        }
+    }
+    let response = response.expect("the API didn't start");
+    assert_eq!(response.status(), 401);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    let response = client.get(&url).bearer_auth("s3cr3t").send().await.unwrap();
+    assert_eq!(response.status(), 200);
+}
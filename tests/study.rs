@@ -0,0 +1,185 @@
+use dicom::core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom_dictionary_std::tags;
+use dicom_object::{FileDicomObject, FileMetaTableBuilder, InMemDicomObject};
+use milvue_rs::{check_studies, split_studies, MilvueError, StudyIssue};
+
+/// SOP class of the test objects.
+const DIGITAL_XRAY_IMAGE_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.1.1";
+
+/// Transfer syntax of the test objects.
+const EXPLICIT_VR_LITTLE_ENDIAN: &str = "1.2.840.10008.1.2.1";
+
+/// Builds a minimal DICOM object of a study, with additional string attributes.
+fn dicom(
+    study_instance_uid: &str,
+    sop_instance_uid: &str,
+    attributes: &[(Tag, VR, &str)],
+) -> FileDicomObject<InMemDicomObject> {
+    let mut object = InMemDicomObject::from_element_iter([
+        DataElement::new(
+            tags::SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from(DIGITAL_XRAY_IMAGE_STORAGE),
+        ),
+        DataElement::new(
+            tags::SOP_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from(sop_instance_uid),
+        ),
+        DataElement::new(
+            tags::STUDY_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from(study_instance_uid),
+        ),
+    ]);
+    for (tag, vr, value) in attributes {
+        object.put(DataElement::new(*tag, *vr, PrimitiveValue::from(*value)));
+    }
+    object
+        .with_meta(
+            FileMetaTableBuilder::new()
+                .transfer_syntax(EXPLICIT_VR_LITTLE_ENDIAN)
+                .media_storage_sop_class_uid(DIGITAL_XRAY_IMAGE_STORAGE),
+        )
+        .unwrap()
+}
+
+#[test]
+fn an_empty_list_is_an_error() {
+    assert!(matches!(
+        check_studies(&[]),
+        Err(MilvueError::EmptyDicomList)
+    ));
+    assert!(matches!(
+        split_studies(Vec::new()),
+        Err(MilvueError::EmptyDicomList)
+    ));
+}
+
+#[test]
+fn padded_uids_belong_to_the_same_study() {
+    let report = check_studies(&[
+        dicom("1.2.3\0", "1.2.3.1\0", &[]),
+        dicom("1.2.3", "1.2.3.2", &[]),
+    ])
+    .unwrap();
+    assert!(report.is_consistent());
+    assert_eq!(report.studies[0].study_instance_uid, "1.2.3");
+    assert_eq!(report.studies[0].indices, vec![0, 1]);
+}
+
+#[test]
+fn patient_ids_and_accession_numbers_must_match() {
+    let report = check_studies(&[
+        dicom(
+            "1.2.3",
+            "1.2.3.1",
+            &[
+                (tags::PATIENT_ID, VR::LO, "P1"),
+                (tags::ACCESSION_NUMBER, VR::SH, "A1"),
+            ],
+        ),
+        dicom(
+            "1.2.3",
+            "1.2.3.2",
+            &[
+                (tags::PATIENT_ID, VR::LO, "P1 "),
+                (tags::ACCESSION_NUMBER, VR::SH, "A2"),
+            ],
+        ),
+        dicom("1.2.3", "1.2.3.3", &[(tags::PATIENT_ID, VR::LO, "P2")]),
+        dicom("4.5.6", "4.5.6.1", &[(tags::PATIENT_ID, VR::LO, "P3")]),
+    ])
+    .unwrap();
+
+    assert!(!report.is_consistent());
+    assert_eq!(report.studies.len(), 2);
+    assert_eq!(
+        report.studies[0].issues,
+        vec![
+            StudyIssue::PatientIdMismatch(vec!["P1".to_string(), "P2".to_string()]),
+            StudyIssue::AccessionNumberMismatch(vec![
+                "A1".to_string(),
+                "A2".to_string(),
+                String::new()
+            ]),
+        ]
+    );
+    assert!(report.studies[1].issues.is_empty());
+}
+
+#[test]
+fn repeated_sop_instance_uids_are_copies_or_conflicts() {
+    let copy = || {
+        dicom(
+            "1.2.3",
+            "1.2.3.1",
+            &[(tags::SERIES_DESCRIPTION, VR::LO, "A")],
+        )
+    };
+    let report = check_studies(&[
+        copy(),
+        dicom(
+            "1.2.3",
+            "1.2.3.2",
+            &[(tags::SERIES_DESCRIPTION, VR::LO, "A")],
+        ),
+        copy(),
+        dicom(
+            "1.2.3",
+            "1.2.3.2",
+            &[(tags::SERIES_DESCRIPTION, VR::LO, "B")],
+        ),
+    ])
+    .unwrap();
+
+    assert_eq!(
+        report.studies[0].issues,
+        vec![
+            StudyIssue::RepeatedSopInstance {
+                sop_instance_uid: "1.2.3.1".to_string(),
+                indices: vec![0, 2],
+            },
+            StudyIssue::ConflictingSopInstanceUid {
+                sop_instance_uid: "1.2.3.2".to_string(),
+                indices: vec![1, 3],
+            },
+        ]
+    );
+}
+
+#[test]
+fn studies_are_split_without_the_copies() {
+    let copy = || dicom("1.2.3", "1.2.3.1", &[]);
+    let batches = split_studies(vec![
+        copy(),
+        dicom("4.5.6", "4.5.6.1", &[]),
+        copy(),
+        dicom("1.2.3", "1.2.3.2", &[]),
+        copy(),
+    ])
+    .unwrap();
+
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[0].study_instance_uid, "1.2.3");
+    let sop_instance_uids = batches[0]
+        .dicoms
+        .iter()
+        .map(|dicom| {
+            dicom
+                .element(tags::SOP_INSTANCE_UID)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .trim_end_matches('\0')
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(sop_instance_uids, vec!["1.2.3.1", "1.2.3.2"]);
+    assert!(matches!(
+        batches[0].issues.as_slice(),
+        [StudyIssue::RepeatedSopInstance { indices, .. }] if indices == &vec![0, 2, 4]
+    ));
+    assert_eq!(batches[1].study_instance_uid, "4.5.6");
+    assert_eq!(batches[1].dicoms.len(), 1);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use common::Reply;
+use dicom::core::{DataElement, PrimitiveValue, VR};
+use dicom_dictionary_std::tags;
+use dicom_object::{FileMetaTableBuilder, InMemDicomObject};
+use milvue_rs::{DicomSource, MilvueClient};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Subscriber,
+};
+use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+/// SOP class of the test objects.
+const DIGITAL_XRAY_IMAGE_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.1.1";
+
+/// Fields recorded on the spans, by span name.
+#[derive(Clone, Default)]
+struct SpanFields(Arc<Mutex<HashMap<String, HashMap<String, String>>>>);
+
+struct Visitor<'a>(&'a mut HashMap<String, String>);
+
+impl Visit for Visitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanFields {
+    fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+        let mut spans = self.0.lock().unwrap();
+        let fields = spans
+            .entry(attrs.metadata().name().to_string())
+            .or_default();
+        attrs.record(&mut Visitor(fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut spans = self.0.lock().unwrap();
+        let fields = spans.entry(span.name().to_string()).or_default();
+        values.record(&mut Visitor(fields));
+    }
+}
+
+/// Builds a minimal DICOM object of the study 1.2.3.
+fn dicom() -> DicomSource {
+    InMemDicomObject::from_element_iter([
+        DataElement::new(
+            tags::SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from(DIGITAL_XRAY_IMAGE_STORAGE),
+        ),
+        DataElement::new(
+            tags::SOP_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.3.1"),
+        ),
+        DataElement::new(
+            tags::STUDY_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.3"),
+        ),
+    ])
+    .with_meta(
+        FileMetaTableBuilder::new()
+            .transfer_syntax("1.2.840.10008.1.2.1")
+            .media_storage_sop_class_uid(DIGITAL_XRAY_IMAGE_STORAGE),
+    )
+    .unwrap()
+    .into()
+}
+
+#[tokio::test]
+async fn the_upload_span_carries_the_study_instance_uid() {
+    let fields = SpanFields::default();
+    let _guard = tracing_subscriber::registry()
+        .with(fields.clone())
+        .set_default();
+    let url = common::serve(|_, _| Reply::json(200, "{}")).await;
+
+    MilvueClient::with_api_key(&url, "key")
+        .unwrap()
+        .upload(vec![dicom()], &Default::default())
+        .await
+        .unwrap();
+
+    let spans = fields.0.lock().unwrap();
+    let upload = &spans["upload"];
+    assert_eq!(upload["study_instance_uid"], "1.2.3");
+    assert_eq!(upload["files"], "1");
+}
@@ -0,0 +1,212 @@
+#![cfg(feature = "transcode")]
+
+use dicom::core::{value::Value, DataElement, PrimitiveValue, VR};
+use dicom_dictionary_std::tags;
+use dicom_object::{FileDicomObject, FileMetaTableBuilder, InMemDicomObject};
+use milvue_rs::{
+    transcode, MilvueError, TranscodePolicy, EXPLICIT_VR_LITTLE_ENDIAN, IMPLICIT_VR_LITTLE_ENDIAN,
+};
+
+/// SOP class of the test objects.
+const DIGITAL_XRAY_IMAGE_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.1.1";
+const RLE_LOSSLESS: &str = "1.2.840.10008.1.2.5";
+const JPEG_2000: &str = "1.2.840.10008.1.2.4.90";
+
+/// Encodes the segments of an RLE Lossless frame, each one as a single literal run of PackBits.
+fn rle_frame(segments: &[Vec<u8>]) -> Vec<u8> {
+    let mut header = vec![0u8; 64];
+    header[..4].copy_from_slice(&(segments.len() as u32).to_le_bytes());
+    let mut body = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let offset = 64 + body.len() as u32;
+        header[4 + 4 * i..8 + 4 * i].copy_from_slice(&offset.to_le_bytes());
+        body.push((segment.len() - 1) as u8);
+        body.extend_from_slice(segment);
+    }
+    header.extend(body);
+    header
+}
+
+/// Builds a 2x2 grayscale image whose pixel data is a single encapsulated fragment.
+fn image(
+    transfer_syntax: &str,
+    photometric_interpretation: &str,
+    bits_allocated: u16,
+    fragment: Vec<u8>,
+) -> FileDicomObject<InMemDicomObject> {
+    let us = |tag, value: u16| DataElement::new(tag, VR::US, PrimitiveValue::from(value));
+    let bits_stored = if bits_allocated == 8 { 8 } else { 12 };
+    let mut object = InMemDicomObject::from_element_iter([
+        DataElement::new(
+            tags::SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from(DIGITAL_XRAY_IMAGE_STORAGE),
+        ),
+        DataElement::new(
+            tags::SOP_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.3.1"),
+        ),
+        DataElement::new(
+            tags::STUDY_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.3"),
+        ),
+        DataElement::new(
+            tags::PHOTOMETRIC_INTERPRETATION,
+            VR::CS,
+            PrimitiveValue::from(photometric_interpretation),
+        ),
+        us(tags::SAMPLES_PER_PIXEL, 1),
+        us(tags::ROWS, 2),
+        us(tags::COLUMNS, 2),
+        us(tags::BITS_ALLOCATED, bits_allocated),
+        us(tags::BITS_STORED, bits_stored),
+        us(tags::HIGH_BIT, bits_stored - 1),
+        us(tags::PIXEL_REPRESENTATION, 0),
+    ]);
+    object.put(DataElement::new(
+        tags::PIXEL_DATA,
+        VR::OB,
+        Value::PixelSequence {
+            offset_table: Default::default(),
+            fragments: vec![fragment].into(),
+        },
+    ));
+    object
+        .with_meta(
+            FileMetaTableBuilder::new()
+                .transfer_syntax(transfer_syntax)
+                .media_storage_sop_class_uid(DIGITAL_XRAY_IMAGE_STORAGE),
+        )
+        .unwrap()
+}
+
+fn photometric_interpretation(dicom: &FileDicomObject<InMemDicomObject>) -> String {
+    dicom
+        .element(tags::PHOTOMETRIC_INTERPRETATION)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .trim()
+        .to_string()
+}
+
+#[test]
+fn policies_accept_their_target_and_accepted_syntaxes() {
+    let default = TranscodePolicy::default();
+    assert!(default.accepts(EXPLICIT_VR_LITTLE_ENDIAN));
+    assert!(default.accepts(IMPLICIT_VR_LITTLE_ENDIAN));
+    assert!(default.accepts("1.2.840.10008.1.2.1\0"));
+    assert!(!default.accepts(RLE_LOSSLESS));
+
+    let implicit = TranscodePolicy::new(IMPLICIT_VR_LITTLE_ENDIAN);
+    assert!(implicit.accepts(IMPLICIT_VR_LITTLE_ENDIAN));
+    assert!(!implicit.accepts(EXPLICIT_VR_LITTLE_ENDIAN));
+
+    let policy = TranscodePolicy {
+        accepted: vec![format!("{}\0", RLE_LOSSLESS)],
+        target: EXPLICIT_VR_LITTLE_ENDIAN.to_string(),
+    };
+    assert!(policy.accepts(RLE_LOSSLESS));
+    assert!(policy.accepts(EXPLICIT_VR_LITTLE_ENDIAN));
+    assert!(!policy.accepts(IMPLICIT_VR_LITTLE_ENDIAN));
+}
+
+#[test]
+fn a_monochrome1_image_keeps_its_grayscale() {
+    let pixels = vec![0, 100, 200, 255];
+    let dicom = image(
+        RLE_LOSSLESS,
+        "MONOCHROME1",
+        8,
+        rle_frame(std::slice::from_ref(&pixels)),
+    );
+
+    let transcoded = transcode(dicom, &TranscodePolicy::default()).unwrap();
+
+    assert_eq!(
+        transcoded.meta().transfer_syntax().trim_end_matches('\0'),
+        EXPLICIT_VR_LITTLE_ENDIAN
+    );
+    assert_eq!(photometric_interpretation(&transcoded), "MONOCHROME1");
+    let pixel_data = transcoded.element(tags::PIXEL_DATA).unwrap();
+    assert_eq!(pixel_data.vr(), VR::OB);
+    assert_eq!(pixel_data.to_bytes().unwrap().to_vec(), pixels);
+
+    // the written file reads back the same
+    let mut buffer = Vec::new();
+    transcoded.write_all(&mut buffer).unwrap();
+    let read = dicom_object::from_reader(&buffer[128..]).unwrap();
+    assert_eq!(photometric_interpretation(&read), "MONOCHROME1");
+    assert_eq!(
+        read.element(tags::PIXEL_DATA)
+            .unwrap()
+            .to_bytes()
+            .unwrap()
+            .to_vec(),
+        pixels
+    );
+}
+
+#[test]
+fn a_16_bit_image_is_decoded_to_words() {
+    let pixels: [u16; 4] = [0x0102, 0x0304, 0x0fff, 0];
+    let high_bytes = pixels.iter().map(|pixel| (pixel >> 8) as u8).collect();
+    let low_bytes = pixels.iter().map(|pixel| (pixel & 0xff) as u8).collect();
+    let dicom = image(
+        RLE_LOSSLESS,
+        "MONOCHROME2",
+        16,
+        rle_frame(&[high_bytes, low_bytes]),
+    );
+
+    let transcoded = transcode(dicom, &TranscodePolicy::new(IMPLICIT_VR_LITTLE_ENDIAN)).unwrap();
+
+    assert_eq!(
+        transcoded.meta().transfer_syntax().trim_end_matches('\0'),
+        IMPLICIT_VR_LITTLE_ENDIAN
+    );
+    assert_eq!(photometric_interpretation(&transcoded), "MONOCHROME2");
+    let pixel_data = transcoded.element(tags::PIXEL_DATA).unwrap();
+    assert_eq!(pixel_data.vr(), VR::OW);
+    assert_eq!(pixel_data.uint16_slice().unwrap(), pixels);
+}
+
+#[test]
+fn accepted_objects_are_untouched_and_unsupported_ones_rejected() {
+    let policy = TranscodePolicy {
+        accepted: vec![RLE_LOSSLESS.to_string()],
+        target: EXPLICIT_VR_LITTLE_ENDIAN.to_string(),
+    };
+    let dicom = image(
+        RLE_LOSSLESS,
+        "MONOCHROME1",
+        8,
+        rle_frame(&[vec![1, 2, 3, 4]]),
+    );
+    let untouched = transcode(dicom, &policy).unwrap();
+    assert_eq!(
+        untouched.meta().transfer_syntax().trim_end_matches('\0'),
+        RLE_LOSSLESS
+    );
+    assert!(untouched
+        .element(tags::PIXEL_DATA)
+        .unwrap()
+        .value()
+        .fragments()
+        .is_some());
+
+    let dicom = image(JPEG_2000, "MONOCHROME2", 8, vec![0; 16]);
+    assert!(matches!(
+        transcode(dicom, &TranscodePolicy::default()),
+        Err(MilvueError::CodecUnavailable(uid, _)) if uid == JPEG_2000
+    ));
+
+    // only the native little endian transfer syntaxes can be targeted
+    let dicom = image(JPEG_2000, "MONOCHROME2", 8, vec![0; 16]);
+    assert!(matches!(
+        transcode(dicom, &TranscodePolicy::new(RLE_LOSSLESS)),
+        Err(MilvueError::UnsupportedTransferSyntax(_))
+    ));
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use common::Reply;
+use milvue_rs::{DicomSource, MilvueClient, MilvueError, Rate, RateLimits, Timeouts};
+
+#[tokio::test]
+async fn an_empty_upload_is_rejected_before_any_request() {
+    // nothing listens on port 1
+    let client = MilvueClient::with_api_key("http://127.0.0.1:1", "key").unwrap();
+    assert!(matches!(
+        client.upload(Vec::new(), &Default::default()).await,
+        Err(MilvueError::EmptyDicomList)
+    ));
+}
+
+/// Serves 429 Too Many Requests with `retry_after` to the first `rejected` uploads, then 200.
+async fn rate_limited_server(
+    rejected: usize,
+    retry_after: &'static str,
+) -> (String, Arc<AtomicUsize>) {
+    let uploads = Arc::new(AtomicUsize::new(0));
+    let counter = uploads.clone();
+    let url = common::serve(move |method, _| {
+        if method != "POST" {
+            return Reply::json(404, "{}");
+        }
+        match counter.fetch_add(1, Ordering::SeqCst) < rejected {
+            true => Reply::json(429, "{}").header("Retry-After", retry_after),
+            false => Reply::json(200, "{}"),
+        }
+    })
+    .await;
+    (url, uploads)
+}
+
+#[tokio::test]
+async fn rate_limited_uploads_are_sent_again() {
+    let (url, uploads) = rate_limited_server(2, "0").await;
+    let directory = std::env::temp_dir().join(format!("milvue_rs-upload-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("1.dcm");
+    std::fs::write(&path, vec![0u8; 1000]).unwrap();
+
+    let client = MilvueClient::with_api_key(&url, "key").unwrap();
+    let source = DicomSource::File {
+        path,
+        sop_instance_uid: Some("1.2.3.1".to_string()),
+    };
+    client
+        .upload(vec![source], &Default::default())
+        .await
+        .unwrap();
+    assert_eq!(uploads.load(Ordering::SeqCst), 3);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn a_huge_retry_after_fails_a_streamed_upload_without_panicking() {
+    let (url, uploads) = rate_limited_server(usize::MAX, "18446744073709551615").await;
+    let client = MilvueClient::with_api_key(&url, "key").unwrap();
+    // a reader can only be read once, so its upload is not sent again
+    let source = DicomSource::Reader {
+        sop_instance_uid: "1.2.3.1".to_string(),
+        reader: Box::new(std::io::Cursor::new(vec![0u8; 1000])),
+    };
+
+    match client.upload(vec![source], &Default::default()).await {
+        Err(MilvueError::StatusResponseError(response)) => assert_eq!(response.status(), 429),
+        other => panic!(
+            "unexpected result {:?}",
+            other.map(|response| response.status())
+        ),
+    }
+    assert_eq!(uploads.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn the_upload_deadline_starts_once_the_rate_limit_allows_the_upload() {
+    let (url, uploads) = rate_limited_server(0, "0").await;
+    // a token every 100 ms, longer than the deadline
+    let client = MilvueClient::builder()
+        .url(&url)
+        .api_key("key")
+        .rate_limits(RateLimits {
+            upload: Some(Rate::per_minute(600.0)),
+            ..Default::default()
+        })
+        .timeouts(Timeouts {
+            upload: Some(Duration::from_millis(50)),
+            ..Default::default()
+        })
+        .build()
+        .unwrap();
+    let reader = || DicomSource::Reader {
+        sop_instance_uid: "1.2.3.1".to_string(),
+        reader: Box::new(std::io::Cursor::new(vec![0u8; 1000])),
+    };
+
+    for _ in 0..2 {
+        client
+            .upload(vec![reader()], &Default::default())
+            .await
+            .unwrap();
+    }
+    assert_eq!(uploads.load(Ordering::SeqCst), 2);
+}